@@ -9,220 +9,132 @@ pub struct BinaryOp {
 #[rustfmt::skip]
 pub enum BinaryOpType {
     Add, Sub, Mul, Div, Rem, Shl, Shr, And, Or, Xor,
+    WrappingAdd, WrappingSub, WrappingMul, SaturatingAdd, SaturatingSub, SaturatingMul,
     Equal, NotEqual, Greater, GreaterOrEqual, Less, LessOrEqual,
-    Identity, LogicAnd, LogicOr
+    Identity, LogicAnd, LogicOr,
+    StructEqual, StructNotEqual, StructCmp
 }
 
 impl BinaryOp {
     pub fn compile(&self, g: &mut CodeGenerator) {
-        match self.op_type {
-            BinaryOpType::Add => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Add.into());
-            }
-            BinaryOpType::Sub => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Sub.into());
-            }
-            BinaryOpType::Mul => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Mul.into());
-            }
-            BinaryOpType::Div => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Div.into());
-            }
-            BinaryOpType::Rem => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Rem.into());
-            }
-            BinaryOpType::Shl => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Shl.into());
-            }
-            BinaryOpType::Shr => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Shr.into());
-            }
-            BinaryOpType::And => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::And.into());
-            }
-            BinaryOpType::Or => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Or.into());
-            }
-            BinaryOpType::Xor => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Xor.into());
-            }
-            BinaryOpType::Identity => {
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                g.push(ops::Cmp.into());
-            }
-            BinaryOpType::Equal => {
-                let label_true = g.create_label();
-                let label_next = g.create_label();
-                // compile lhs and rhs
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                // push -1 if a < b, 0 if a == b, 1 if a > b
-                g.push(ops::Cmp.into());
-                // if 0, jump to label_true
-                g.push_jump(label_true, ops::JumpZero::new(0).into());
-                // push false (0)
-                g.push(ops::LiteralCreate::new(0.into()).into());
-                // jump to label_next
-                g.push_jump(label_next, ops::Jump::new(0).into());
-                // push true (1)
-                g.label_here(label_true);
-                g.push(ops::LiteralCreate::new(1.into()).into());
-                g.label_here(label_next);
-            }
-            BinaryOpType::NotEqual => {
-                let label_false = g.create_label();
-                let label_next = g.create_label();
-                // compile lhs and rhs
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                // push -1 if a < b, 0 if a == b, 1 if a > b
-                g.push(ops::Cmp.into());
-                // if 0, jump to label_false
-                g.push_jump(label_false, ops::JumpZero::new(0).into());
-                // push true (1)
-                g.push(ops::LiteralCreate::new(1.into()).into());
-                // jump to label_next
-                g.push_jump(label_next, ops::Jump::new(0).into());
-                // push false (0)
-                g.label_here(label_false);
-                g.push(ops::LiteralCreate::new(0.into()).into());
-                g.label_here(label_next);
-            }
-            BinaryOpType::Greater => {
-                let label_true = g.create_label();
-                let label_next = g.create_label();
-                // compile lhs and rhs
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                // push -1 if a < b, 0 if a == b, 1 if a > b
-                g.push(ops::Cmp.into());
-                // flip sign
-                g.push(ops::Neg.into());
-                // if negative, jump to label_true
-                g.push_jump(label_true, ops::JumpNeg::new(0).into());
-                // push false (0)
-                g.push(ops::LiteralCreate::new(0.into()).into());
-                // jump to label_next
-                g.push_jump(label_next, ops::Jump::new(0).into());
-                // push true (1)
-                g.label_here(label_true);
-                g.push(ops::LiteralCreate::new(1.into()).into());
-                g.label_here(label_next);
-            }
-            BinaryOpType::GreaterOrEqual => {
+        self.lhs.compile(g);
+        self.op_type.push_with_rhs(g, &self.rhs);
+    }
+}
+
+impl BinaryOpType {
+    /// Compiles `rhs`, and replaces lhs, which is on the stack, with the
+    /// result. Unlike `push_op`, this works for LogicAnd and LogicOr, which
+    /// only evaluate rhs if they need it.
+    pub fn push_with_rhs(&self, g: &mut CodeGenerator, rhs: &Expr) {
+        match self {
+            BinaryOpType::LogicAnd => {
                 let label_false = g.create_label();
                 let label_next = g.create_label();
-                // compile lhs and rhs
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                // push -1 if a < b, 0 if a == b, 1 if a > b
-                g.push(ops::Cmp.into());
-                // if negative, jump to label_false
-                g.push_jump(label_false, ops::JumpNeg::new(0).into());
-                // push true (1)
-                g.push(ops::LiteralCreate::new(1.into()).into());
+                // if lhs is false, jump to label_false
+                g.push_cond_jump(label_false);
+                // compile rhs
+                rhs.compile(g);
                 // jump to label_next
                 g.push_jump(label_next, ops::Jump::new(0).into());
-                // push false (0)
+                // push false
                 g.label_here(label_false);
-                g.push(ops::LiteralCreate::new(0.into()).into());
-                g.label_here(label_next);
-            }
-            BinaryOpType::Less => {
-                let label_true = g.create_label();
-                let label_next = g.create_label();
-                // compile lhs and rhs
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                // push -1 if a < b, 0 if a == b, 1 if a > b
-                g.push(ops::Cmp.into());
-                // if negative, jump to label_true
-                g.push_jump(label_true, ops::JumpNeg::new(0).into());
-                // push false (0)
-                g.push(ops::LiteralCreate::new(0.into()).into());
-                // jump to label_next
-                g.push_jump(label_next, ops::Jump::new(0).into());
-                // push true (1)
-                g.label_here(label_true);
-                g.push(ops::LiteralCreate::new(1.into()).into());
+                g.push(ops::LiteralCreate::new(false.into()).into());
                 g.label_here(label_next);
             }
-            BinaryOpType::LessOrEqual => {
+            BinaryOpType::LogicOr => {
                 let label_false = g.create_label();
                 let label_next = g.create_label();
-                // compile lhs and rhs
-                self.lhs.compile(g);
-                self.rhs.compile(g);
-                // push -1 if a < b, 0 if a == b, 1 if a > b
-                g.push(ops::Cmp.into());
-                // flip sign
-                g.push(ops::Neg.into());
-                // if negative, jump to label_false
-                g.push_jump(label_false, ops::JumpNeg::new(0).into());
-                // push true (1)
-                g.push(ops::LiteralCreate::new(1.into()).into());
+                // if lhs is false, jump to label_false
+                g.push_cond_jump(label_false);
+                // push true
+                g.push(ops::LiteralCreate::new(true.into()).into());
                 // jump to label_next
                 g.push_jump(label_next, ops::Jump::new(0).into());
-                // push false (0)
-                g.label_here(label_false);
-                g.push(ops::LiteralCreate::new(0.into()).into());
-                g.label_here(label_next);
-            }
-            BinaryOpType::LogicAnd => {
-                let label_false = g.create_label();
-                let label_next = g.create_label();
-                // compile lhs
-                self.lhs.compile(g);
-                // if zero, jump to label_false
-                g.push_jump(label_false, ops::JumpZero::new(0).into());
                 // compile rhs
-                self.rhs.compile(g);
-                // jump to label_next
-                g.push_jump(label_next, ops::Jump::new(0).into());
-                // push false (0)
                 g.label_here(label_false);
-                g.push(ops::LiteralCreate::new(0.into()).into());
+                rhs.compile(g);
                 g.label_here(label_next);
             }
-            BinaryOpType::LogicOr => {
-                let label_true = g.create_label();
-                let label_next = g.create_label();
-                // compile lhs
-                self.lhs.compile(g);
-                // flip sign
-                g.push(ops::Neg.into());
-                // if negative, jump to label_true
-                g.push_jump(label_true, ops::JumpNeg::new(0).into());
-                // compile rhs
-                self.rhs.compile(g);
-                // jump to label_next
-                g.push_jump(label_next, ops::Jump::new(0).into());
-                // push true (1)
-                g.label_here(label_true);
-                g.push(ops::LiteralCreate::new(1.into()).into());
-                g.label_here(label_next);
+            _ => {
+                rhs.compile(g);
+                self.push_op(g);
             }
         }
     }
+
+    /// Pops rhs and lhs, and pushes the result. LogicAnd and LogicOr can't be
+    /// compiled this way, since they only evaluate rhs if they need it; use
+    /// `push_with_rhs` for those.
+    pub fn push_op(&self, g: &mut CodeGenerator) {
+        match self {
+            BinaryOpType::Add => g.push(ops::Add.into()),
+            BinaryOpType::Sub => g.push(ops::Sub.into()),
+            BinaryOpType::Mul => g.push(ops::Mul.into()),
+            BinaryOpType::Div => g.push(ops::Div.into()),
+            BinaryOpType::Rem => g.push(ops::Rem.into()),
+            BinaryOpType::Shl => g.push(ops::Shl.into()),
+            BinaryOpType::Shr => g.push(ops::Shr.into()),
+            BinaryOpType::And => g.push(ops::And.into()),
+            BinaryOpType::Or => g.push(ops::Or.into()),
+            BinaryOpType::Xor => g.push(ops::Xor.into()),
+            BinaryOpType::WrappingAdd => g.push(ops::WrappingAdd.into()),
+            BinaryOpType::WrappingSub => g.push(ops::WrappingSub.into()),
+            BinaryOpType::WrappingMul => g.push(ops::WrappingMul.into()),
+            BinaryOpType::SaturatingAdd => g.push(ops::SaturatingAdd.into()),
+            BinaryOpType::SaturatingSub => g.push(ops::SaturatingSub.into()),
+            BinaryOpType::SaturatingMul => g.push(ops::SaturatingMul.into()),
+            // Identity pushes what Cmp does, which is an Integer rather than
+            // the Bool of Equal
+            BinaryOpType::Identity => g.push(ops::Cmp.into()),
+            BinaryOpType::Equal => g.push(ops::CmpEq.into()),
+            BinaryOpType::NotEqual => g.push(ops::CmpNe.into()),
+            BinaryOpType::Greater => g.push(ops::CmpGt.into()),
+            BinaryOpType::GreaterOrEqual => g.push(ops::CmpGe.into()),
+            BinaryOpType::Less => g.push(ops::CmpLt.into()),
+            BinaryOpType::LessOrEqual => g.push(ops::CmpLe.into()),
+            BinaryOpType::StructEqual => g.push(ops::StructEq.into()),
+            BinaryOpType::StructNotEqual => {
+                g.push(ops::StructEq.into());
+                g.push(ops::Not.into());
+            }
+            BinaryOpType::StructCmp => g.push(ops::StructCmp.into()),
+            BinaryOpType::LogicAnd | BinaryOpType::LogicOr => {
+                panic!("logic ops need their operands to be compiled")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{run, var, Function, Statement};
+    use super::*;
+    use crate::vm::datamodel::{List, Value};
+
+    fn compare(op_type: BinaryOpType, lhs: Value, rhs: Value) -> Value {
+        let function = Function {
+            args: vec![0, 1],
+            body: vec![Statement::Return(Expr::BinaryOp(BinaryOp {
+                op_type,
+                lhs: Box::new(var(0)),
+                rhs: Box::new(var(1)),
+            }))],
+        };
+        run(function, vec![lhs, rhs]).unwrap()
+    }
+
+    #[test]
+    fn identity_pushes_an_integer() {
+        let list: Value = List::empty().into();
+        let t = compare(BinaryOpType::Identity, list.clone(), list.clone());
+        assert!(matches!(t, Value::Integer(1)));
+        let t = compare(BinaryOpType::Identity, list.clone(), List::empty().into());
+        assert!(matches!(t, Value::Integer(0)));
+        let t = compare(BinaryOpType::Identity, Value::Integer(2), Value::Integer(3));
+        assert!(matches!(t, Value::Integer(-1)));
+        // unlike Equal
+        let t = compare(BinaryOpType::Equal, list.clone(), list);
+        assert!(matches!(t, Value::Bool(true)));
+    }
 }
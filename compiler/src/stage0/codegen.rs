@@ -1,6 +1,9 @@
 use std::collections::BTreeMap;
 
-use super::{bytecode::Op, ops, Var};
+use super::{
+    bytecode::{DebugInfo, LocalInfo, Op},
+    ops, DebugNames, Var,
+};
 
 pub type Label = usize;
 
@@ -29,7 +32,23 @@ impl LabelData {
     }
 }
 
+#[derive(Clone, Copy, Default)]
+pub struct CompileOptions {
+    /// if set, conditions must evaluate to a Bool, and anything else is an
+    /// error at runtime; otherwise None, zero and false all count as false
+    pub strict_conditions: bool,
+}
+
+/// What the code generator records for DebugInfo.
+struct DebugRecord {
+    /// each binding of a variable: the var, its local, and the cursors where
+    /// it was bound and dropped
+    vars: Vec<(Var, u8, usize, Option<usize>)>,
+    lines: Vec<(u32, u32)>,
+}
+
 pub struct CodeGenerator {
+    options: CompileOptions,
     ops: Vec<Op>,
     labels: Vec<LabelData>,
     loops: BTreeMap<usize, (Label, Label)>,
@@ -37,11 +56,17 @@ pub struct CodeGenerator {
     vars: BTreeMap<Var, u8>,
     dropped: Vec<u8>,
     next_index: u8,
+    debug: Option<DebugRecord>,
 }
 
 impl CodeGenerator {
     pub fn new() -> CodeGenerator {
+        CodeGenerator::with_options(CompileOptions::default())
+    }
+
+    pub fn with_options(options: CompileOptions) -> CodeGenerator {
         CodeGenerator {
+            options,
             ops: Vec::new(),
             labels: Vec::new(),
             loops: BTreeMap::new(),
@@ -50,6 +75,7 @@ impl CodeGenerator {
             dropped: Vec::new(),
             // next_index starts at 1, because module ref is at index 0
             next_index: 1,
+            debug: None,
         }
     }
 
@@ -79,7 +105,8 @@ impl CodeGenerator {
         let i = self.ops.len();
         self.get_label_data(label).jumps.push(i);
         match jump {
-            Op::Jump(_) | Op::JumpZero(_) | Op::JumpNeg(_) => {}
+            Op::Jump(_) | Op::JumpZero(_) | Op::JumpNeg(_) | Op::JumpFalse(_) | Op::IterNext(_) => {
+            }
             _ => panic!(
                 "expected jump op, but found {} op",
                 jump.get_type().get_name()
@@ -88,6 +115,15 @@ impl CodeGenerator {
         self.ops.push(jump);
     }
 
+    /// Pops a condition, and jumps to `label` if it is false.
+    pub fn push_cond_jump(&mut self, label: Label) {
+        let jump = match self.options.strict_conditions {
+            true => ops::JumpFalse::new(0).into(),
+            false => ops::JumpZero::new(0).into(),
+        };
+        self.push_jump(label, jump);
+    }
+
     pub fn into_vec(self) -> Vec<Op> {
         let mut ops = self.ops;
         for label in self.labels {
@@ -98,6 +134,8 @@ impl CodeGenerator {
                     Op::Jump(j) => j.dest = target,
                     Op::JumpZero(j) => j.dest = target,
                     Op::JumpNeg(j) => j.dest = target,
+                    Op::JumpFalse(j) => j.dest = target,
+                    Op::IterNext(j) => j.dest = target,
                     _ => unreachable!(),
                 }
             }
@@ -105,6 +143,57 @@ impl CodeGenerator {
         ops
     }
 
+    // debug methods
+
+    /// Starts recording the live ranges of variables and the source lines
+    /// of ops, for `debug_info`.
+    pub fn record_debug(&mut self) {
+        self.debug = Some(DebugRecord {
+            vars: Vec::new(),
+            lines: Vec::new(),
+        });
+    }
+
+    /// Sets the source line of the ops pushed from here on.
+    pub fn set_line(&mut self, line: u32) {
+        let cursor = self.ops.len() as u32;
+        if let Some(debug) = &mut self.debug {
+            match debug.lines.last_mut() {
+                Some((_, last)) if *last == line => {}
+                // no ops were pushed for the previous line
+                Some((c, last)) if *c == cursor => *last = line,
+                _ => debug.lines.push((cursor, line)),
+            }
+        }
+    }
+
+    /// The DebugInfo of the ops pushed so far, if `record_debug` was called.
+    /// Variables without a name in `names` are left out.
+    pub fn debug_info(&self, names: &DebugNames) -> Option<DebugInfo> {
+        let debug = self.debug.as_ref()?;
+        let end = self.ops.len();
+        let locals = (debug.vars.iter())
+            .filter_map(|&(var, slot, start, stop)| {
+                let stop = stop.unwrap_or(end);
+                let name = names.vars.get(&var)?;
+                if start == stop {
+                    return None;
+                }
+                Some(LocalInfo {
+                    name: name.clone(),
+                    slot,
+                    start: start as u32,
+                    end: stop as u32,
+                })
+            })
+            .collect();
+        Some(DebugInfo {
+            name: names.function.clone(),
+            locals,
+            lines: debug.lines.clone(),
+        })
+    }
+
     // loop methods
 
     pub fn loop_enter(&mut self, loop_id: Option<usize>) {
@@ -173,6 +262,10 @@ impl CodeGenerator {
             Some(_) => panic!("variable with id {} has already been bound", var),
             None => {}
         }
+        let cursor = self.ops.len();
+        if let Some(debug) = &mut self.debug {
+            debug.vars.push((var, index, cursor, None));
+        }
     }
 
     pub fn drop_var(&mut self, var: Var) {
@@ -182,6 +275,24 @@ impl CodeGenerator {
             }
             None => panic!("failed to drop variable with id {}", var),
         }
+        let cursor = self.ops.len();
+        if let Some(debug) = &mut self.debug {
+            // the binding is the last one of the var that hasn't been dropped
+            let binding = (debug.vars.iter_mut().rev()).find(|(v, ..)| *v == var);
+            if let Some((.., end @ None)) = binding {
+                *end = Some(cursor);
+            }
+        }
+    }
+
+    /// Reserves a local that isn't tied to a variable, for values that the
+    /// generated code keeps around, such as the iterator of a for loop.
+    pub fn alloc_temp(&mut self) -> u8 {
+        self.get_next_var_index()
+    }
+
+    pub fn free_temp(&mut self, index: u8) {
+        self.dropped.push(index);
     }
 
     fn get_var_index(&self, var: Var) -> u8 {
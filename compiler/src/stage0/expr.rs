@@ -1,4 +1,5 @@
 use super::{ops, ops::LiteralValue, BinaryOp, CodeGenerator, UnaryOp};
+use crate::vm::datamodel::{ByteOrder, NumType};
 
 pub type Var = usize;
 
@@ -13,7 +14,15 @@ pub enum Expr {
     LiteralValue(Span<LiteralValue>),
     Var(Span<Var>),
     ModuleRef,
+    /// `_`, which can only be used as a place, to ignore a value
+    Discard,
     BinaryOp(BinaryOp),
+    /// evaluates to `then` if `condition` is true, and to `else_` otherwise
+    Cond {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        else_: Box<Expr>,
+    },
     UnaryOp(UnaryOp),
     Call {
         func: Box<Expr>,
@@ -41,21 +50,71 @@ pub enum Expr {
         b: Box<Expr>,
     },
     ListPop(Box<Expr>),
+    ListRemove {
+        list: Box<Expr>,
+        index: Box<Expr>,
+    },
+    ListSplice {
+        list: Box<Expr>,
+        a: Box<Expr>,
+        b: Box<Expr>,
+        src: Box<Expr>,
+    },
+    ListFind {
+        list: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+        step: Box<Expr>,
+        inclusive: bool,
+    },
     BufferCreate(Box<Expr>),
     BufferGetSlice {
         buffer: Box<Expr>,
         a: Box<Expr>,
         b: Box<Expr>,
     },
+    BufferView {
+        buffer: Box<Expr>,
+        a: Box<Expr>,
+        b: Box<Expr>,
+    },
+    BufferMaterialize(Box<Expr>),
+    BufferRead {
+        buffer: Box<Expr>,
+        offset: Box<Expr>,
+        ty: NumType,
+        order: ByteOrder,
+    },
 }
 
 impl Expr {
     pub fn compile(&self, g: &mut CodeGenerator) {
         match self {
-            Expr::LiteralValue(l) => g.push(ops::LiteralCreate::new(l.inner).into()),
+            Expr::LiteralValue(l) => g.push(ops::LiteralCreate::new(l.inner.clone()).into()),
             Expr::Var(var) => g.push_var_load(var.inner),
             Expr::ModuleRef => g.push(ops::StackLoad::new(0).into()),
+            Expr::Discard => panic!("cannot use `_` as a value"),
             Expr::BinaryOp(b) => b.compile(g),
+            Expr::Cond {
+                condition,
+                then,
+                else_,
+            } => {
+                let label_else = g.create_label();
+                let label_next = g.create_label();
+                condition.compile(g);
+                // if false, jump to label_else
+                g.push_cond_jump(label_else);
+                then.compile(g);
+                // jump to label_next
+                g.push_jump(label_next, ops::Jump::new(0).into());
+                g.label_here(label_else);
+                else_.compile(g);
+                g.label_here(label_next);
+            }
             Expr::UnaryOp(u) => u.compile(g),
             Expr::Call { func, args } => {
                 func.compile(g);
@@ -118,6 +177,37 @@ impl Expr {
                 e.compile(g);
                 g.push(ops::ListPop.into());
             }
+            Expr::ListRemove { list, index } => {
+                list.compile(g);
+                index.compile(g);
+                g.push(ops::ListRemove.into());
+            }
+            Expr::ListSplice { list, a, b, src } => {
+                list.compile(g);
+                a.compile(g);
+                b.compile(g);
+                src.compile(g);
+                g.push(ops::ListSplice.into());
+            }
+            Expr::ListFind { list, value } => {
+                list.compile(g);
+                value.compile(g);
+                g.push(ops::ListFind.into());
+            }
+            Expr::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                start.compile(g);
+                end.compile(g);
+                step.compile(g);
+                match inclusive {
+                    true => g.push(ops::RangeCreateInclusive.into()),
+                    false => g.push(ops::RangeCreate.into()),
+                }
+            }
             Expr::BufferCreate(e) => {
                 e.compile(g);
                 g.push(ops::BufferCreate.into());
@@ -128,6 +218,26 @@ impl Expr {
                 b.compile(g);
                 g.push(ops::BufferGetSlice.into());
             }
+            Expr::BufferView { buffer, a, b } => {
+                buffer.compile(g);
+                a.compile(g);
+                b.compile(g);
+                g.push(ops::BufferView.into());
+            }
+            Expr::BufferMaterialize(e) => {
+                e.compile(g);
+                g.push(ops::BufferMaterialize.into());
+            }
+            Expr::BufferRead {
+                buffer,
+                offset,
+                ty,
+                order,
+            } => {
+                buffer.compile(g);
+                offset.compile(g);
+                g.push(ops::BufferRead::new(*ty, *order).into());
+            }
         }
     }
 
@@ -142,10 +252,20 @@ impl Expr {
             Expr::LiteralValue(_) => {}
             Expr::Var(var) => vars.push(*var),
             Expr::ModuleRef => {}
+            Expr::Discard => {}
             Expr::BinaryOp(b) => {
                 b.lhs.acc_vars(vars);
                 b.rhs.acc_vars(vars);
             }
+            Expr::Cond {
+                condition,
+                then,
+                else_,
+            } => {
+                condition.acc_vars(vars);
+                then.acc_vars(vars);
+                else_.acc_vars(vars);
+            }
             Expr::UnaryOp(u) => u.expr.acc_vars(vars),
             Expr::Call { func, args } => {
                 func.acc_vars(vars);
@@ -179,12 +299,100 @@ impl Expr {
                 b.acc_vars(vars);
             }
             Expr::ListPop(e) => e.acc_vars(vars),
+            Expr::ListRemove { list, index } => {
+                list.acc_vars(vars);
+                index.acc_vars(vars);
+            }
+            Expr::ListSplice { list, a, b, src } => {
+                list.acc_vars(vars);
+                a.acc_vars(vars);
+                b.acc_vars(vars);
+                src.acc_vars(vars);
+            }
+            Expr::ListFind { list, value } => {
+                list.acc_vars(vars);
+                value.acc_vars(vars);
+            }
+            Expr::Range {
+                start, end, step, ..
+            } => {
+                start.acc_vars(vars);
+                end.acc_vars(vars);
+                step.acc_vars(vars);
+            }
             Expr::BufferCreate(e) => e.acc_vars(vars),
             Expr::BufferGetSlice { buffer, a, b } => {
                 buffer.acc_vars(vars);
                 a.acc_vars(vars);
                 b.acc_vars(vars);
             }
+            Expr::BufferView { buffer, a, b } => {
+                buffer.acc_vars(vars);
+                a.acc_vars(vars);
+                b.acc_vars(vars);
+            }
+            Expr::BufferMaterialize(e) => e.acc_vars(vars),
+            Expr::BufferRead { buffer, offset, .. } => {
+                buffer.acc_vars(vars);
+                offset.acc_vars(vars);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{run, span, var, CompileOptions, Function, Statement};
+    use super::*;
+    use crate::vm::datamodel::{self, Tuple, Value};
+    use crate::vm::VirtualMachine;
+
+    fn literal(t: impl Into<LiteralValue>) -> Expr {
+        Expr::LiteralValue(span(t.into()))
+    }
+
+    /// Calling None fails, so this shows whether it was evaluated.
+    fn fails() -> Expr {
+        Expr::Call {
+            func: Box::new(literal(LiteralValue::None)),
+            args: Vec::new(),
+        }
+    }
+
+    fn cond(then: Expr, else_: Expr) -> Function {
+        Function {
+            args: vec![0],
+            body: vec![Statement::Return(Expr::Cond {
+                condition: Box::new(var(0)),
+                then: Box::new(then),
+                else_: Box::new(else_),
+            })],
+        }
+    }
+
+    #[test]
+    fn cond_only_evaluates_the_selected_branch() {
+        let t = run(cond(literal(1), fails()), vec![Value::Bool(true)]);
+        assert!(matches!(t, Ok(Value::Integer(1))));
+        let t = run(cond(fails(), literal(2)), vec![Value::Bool(false)]);
+        assert!(matches!(t, Ok(Value::Integer(2))));
+        assert!(run(cond(fails(), literal(2)), vec![Value::Bool(true)]).is_err());
+        assert!(run(cond(literal(1), fails()), vec![Value::Bool(false)]).is_err());
+    }
+
+    #[test]
+    fn cond_conditions_follow_the_options() {
+        // without strict conditions, zero and None are false
+        let t = run(cond(literal(1), literal(2)), vec![Value::Integer(0)]);
+        assert!(matches!(t, Ok(Value::Integer(2))));
+        let t = run(cond(literal(1), literal(2)), vec![Value::None]);
+        assert!(matches!(t, Ok(Value::Integer(2))));
+        let options = CompileOptions {
+            strict_conditions: true,
+        };
+        let ops = cond(literal(1), literal(2)).compile_with(options).ops;
+        let function = datamodel::Function::new(Tuple::empty(0), ops);
+        let t = VirtualMachine::call(function.into(), vec![Value::Integer(0)]);
+        assert!(t.is_err());
+    }
+}
@@ -1,14 +1,45 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
-use super::{bytecode, CodeGenerator, Expr, If, Statement, Var};
+use super::{bytecode, CodeGenerator, CompileOptions, Expr, If, Statement, Var};
 
 pub struct Function {
     pub args: Vec<Var>,
     pub body: Vec<Statement>,
 }
 
+/// The names that `Function::compile_with_debug` puts in the DebugInfo.
+#[derive(Default)]
+pub struct DebugNames {
+    pub function: String,
+    /// variables without a name are left out of the DebugInfo
+    pub vars: BTreeMap<Var, String>,
+}
+
 impl Function {
-    pub fn compile(mut self) -> bytecode::Function {
+    pub fn compile(self) -> bytecode::Function {
+        self.compile_with(CompileOptions::default())
+    }
+
+    pub fn compile_with(self, options: CompileOptions) -> bytecode::Function {
+        self.compile_function(options, None)
+    }
+
+    /// Like `compile_with`, but also records DebugInfo, with the live ranges
+    /// of the variables named in `names`, and the lines from
+    /// `Statement::Line`.
+    pub fn compile_with_debug(
+        self,
+        options: CompileOptions,
+        names: &DebugNames,
+    ) -> bytecode::Function {
+        self.compile_function(options, Some(names))
+    }
+
+    fn compile_function(
+        mut self,
+        options: CompileOptions,
+        names: Option<&DebugNames>,
+    ) -> bytecode::Function {
         let mut setup = Vec::new();
         for &arg in &self.args {
             setup.push(Statement::BindVar(arg));
@@ -22,11 +53,18 @@ impl Function {
                 unknown_scope_vars.len()
             );
         }
-        let mut g = CodeGenerator::new();
+        let mut g = CodeGenerator::with_options(options);
+        if names.is_some() {
+            g.record_debug();
+        }
         for statement in &self.body {
             statement.compile(&mut g);
         }
-        bytecode::Function { ops: g.into_vec() }
+        let debug = names.and_then(|names| g.debug_info(names));
+        bytecode::Function {
+            ops: g.into_vec(),
+            debug,
+        }
     }
 
     fn block_scope_analysis(&mut self) -> Result<(), Vec<Var>> {
@@ -69,7 +107,9 @@ impl<'a> BlockScopeAnalysis<'a> {
             self.process_statement(statement);
         }
         let mut parent_scope = Vec::new();
-        for drop in self.drops.iter().rev() {
+        // drops were found from the end of the block, so inserting them in
+        // that order doesn't move the locations of the ones still to come
+        for drop in self.drops.iter() {
             if self.bindings.contains(&drop.var) {
                 block.insert(drop.loc + 1, Statement::DropVar(drop.var));
             } else {
@@ -100,6 +140,19 @@ impl<'a> BlockScopeAnalysis<'a> {
         }
     }
 
+    /// Like `process_child_block`, for statements that bind and drop `vars`
+    /// themselves.
+    fn process_scoped_block(&mut self, vars: &[Var], block: &mut Vec<Statement>) {
+        let mut b = BlockScopeAnalysis::new(self.seen);
+        let outer_scope_vars = b.process_block(block);
+        for outer in outer_scope_vars.into_iter().filter(|v| !vars.contains(v)) {
+            self.drops.push(DeferredDrop {
+                loc: self.loc,
+                var: outer,
+            });
+        }
+    }
+
     fn process_if(&mut self, if_: &mut If) {
         self.process_expr(&if_.condition);
         self.process_child_block(&mut if_.body);
@@ -120,6 +173,31 @@ impl<'a> BlockScopeAnalysis<'a> {
                 }
                 self.process_child_block(&mut l.body);
             }
+            Statement::ForIn {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                self.process_scoped_block(&[*var], body);
+                self.process_expr(iterable);
+            }
+            Statement::ForRange {
+                var,
+                start,
+                end,
+                step,
+                body,
+                ..
+            } => {
+                self.process_scoped_block(&[*var], body);
+                self.process_expr(start);
+                self.process_expr(end);
+                if let Some(step) = step {
+                    self.process_expr(step);
+                }
+            }
+            Statement::Line(_) => {}
             Statement::Break { .. } => {}
             Statement::Continue { .. } => {}
             Statement::Expr(expr) => self.process_expr(expr),
@@ -131,7 +209,25 @@ impl<'a> BlockScopeAnalysis<'a> {
                 }
                 self.process_child_block(&mut s.else_);
             }
-            Statement::Assign { place, value } => {
+            Statement::Match(m) => {
+                for arm in m.arms.iter_mut().rev() {
+                    let vars = arm.pattern.vars();
+                    self.process_scoped_block(&vars, &mut arm.body);
+                    if let Some(guard) = &arm.guard {
+                        for var in guard.find_vars() {
+                            if !vars.contains(&var.inner) {
+                                self.process_var(var.inner);
+                            }
+                        }
+                    }
+                }
+                self.process_expr(&m.value);
+            }
+            Statement::Assign { place, value, .. } => {
+                self.process_expr(place);
+                self.process_expr(value);
+            }
+            Statement::CompoundAssign { place, value, .. } => {
                 self.process_expr(place);
                 self.process_expr(value);
             }
@@ -147,6 +243,30 @@ impl<'a> BlockScopeAnalysis<'a> {
                 self.process_expr(list);
                 self.process_expr(value);
             }
+            Statement::ListSetSlice {
+                list,
+                src,
+                src_offset,
+                offset,
+                len,
+            } => {
+                self.process_expr(list);
+                self.process_expr(src);
+                self.process_expr(src_offset);
+                self.process_expr(offset);
+                self.process_expr(len);
+            }
+            Statement::ListInsert { list, index, value } => {
+                self.process_expr(list);
+                self.process_expr(index);
+                self.process_expr(value);
+            }
+            Statement::ListReverse(list) => self.process_expr(list),
+            Statement::ListSort(list) => self.process_expr(list),
+            Statement::ListSortBy { list, cmp } => {
+                self.process_expr(list);
+                self.process_expr(cmp);
+            }
             Statement::BufferSetSlice {
                 buffer,
                 src,
@@ -160,6 +280,95 @@ impl<'a> BlockScopeAnalysis<'a> {
                 self.process_expr(offset);
                 self.process_expr(len);
             }
+            Statement::BufferWrite {
+                buffer,
+                offset,
+                value,
+                ..
+            } => {
+                self.process_expr(buffer);
+                self.process_expr(offset);
+                self.process_expr(value);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{run, var};
+    use super::*;
+    use crate::vm::datamodel::Value;
+
+    #[test]
+    fn vars_are_dropped_after_their_last_use() {
+        // a is last used before b, so a is dropped first, and the drop of b
+        // has to go after the return, not before it
+        let function = Function {
+            args: vec![0, 1],
+            body: vec![Statement::Expr(var(0)), Statement::Return(var(1))],
+        };
+        let args = vec![Value::Integer(1), Value::Integer(2)];
+        let t = run(function, args).unwrap();
+        assert!(matches!(t, Value::Integer(2)));
+    }
+
+    #[test]
+    fn debug_info_follows_bindings_and_lines() {
+        // ops: 0 store a, 1 load a, 2 store b, 3 load b, 4 return
+        let function = Function {
+            args: vec![0],
+            body: vec![
+                Statement::Line(1),
+                Statement::BindVar(1),
+                Statement::BindVar(2),
+                Statement::Assign {
+                    place: Box::new(var(1)),
+                    value: Box::new(var(0)),
+                    ty: None,
+                },
+                // no ops are pushed for line 2, so line 3 replaces it
+                Statement::Line(2),
+                Statement::Line(3),
+                Statement::Line(3),
+                Statement::Return(var(1)),
+            ],
+        };
+        let mut names = DebugNames {
+            function: "f".to_owned(),
+            ..DebugNames::default()
+        };
+        names.vars.insert(0, "a".to_owned());
+        names.vars.insert(1, "b".to_owned());
+        let f = function.compile_with_debug(CompileOptions::default(), &names);
+        let debug = f.debug.unwrap();
+        assert_eq!(debug.name, "f");
+        assert_eq!(debug.lines, vec![(1, 1), (3, 3)]);
+        // the arg is stored before the first line
+        assert_eq!(debug.line_at(0), None);
+        assert_eq!(debug.line_at(2), Some(1));
+        assert_eq!(debug.line_at(4), Some(3));
+
+        // var 2 has no name, and var 1 is never dropped, so it lives to the
+        // end of the function
+        let locals: Vec<_> = (debug.locals.iter())
+            .map(|l| (l.name.as_str(), l.slot, l.start, l.end))
+            .collect();
+        assert_eq!(locals, vec![("a", 1, 0, 3), ("b", 2, 1, 5)]);
+        // each range covers the store of its var, and a is dropped after its
+        // last load
+        assert!(matches!(&f.ops[0], bytecode::Op::StackStore(s) if s.local == 1));
+        assert!(matches!(&f.ops[1], bytecode::Op::StackLoad(s) if s.local == 1));
+        assert!(matches!(&f.ops[2], bytecode::Op::StackStore(s) if s.local == 2));
+        assert_eq!(debug.local_at(1, 2), Some("a"));
+        assert_eq!(debug.local_at(1, 3), None);
+        assert_eq!(debug.local_at(2, 4), Some("b"));
+
+        // without debug names, nothing is recorded
+        let function = Function {
+            args: vec![0],
+            body: vec![Statement::Line(1), Statement::Return(var(0))],
+        };
+        assert!(function.compile().debug.is_none());
+    }
+}
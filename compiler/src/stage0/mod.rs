@@ -5,13 +5,37 @@ mod codegen;
 mod expr;
 mod function;
 mod module;
+mod pattern;
 mod statement;
 mod unaryop;
 
 pub use binaryop::{BinaryOp, BinaryOpType};
-pub use codegen::{CodeGenerator, Label};
+pub use codegen::{CodeGenerator, CompileOptions, Label};
 pub use expr::{Expr, Span, Var};
-pub use function::Function;
+pub use function::{DebugNames, Function};
 pub use module::{Module, ModuleItem, Program};
+pub use pattern::{Match, MatchArm, Pattern};
 pub use statement::{If, IfElse, Loop, Statement};
 pub use unaryop::{UnaryOp, UnaryOpType};
+
+#[cfg(test)]
+fn span<T>(inner: T) -> Span<T> {
+    Span { span: (), inner }
+}
+
+#[cfg(test)]
+fn var(var: Var) -> Expr {
+    Expr::Var(span(var))
+}
+
+/// Compiles `function` in a module without items, and calls it with `args`.
+#[cfg(test)]
+fn run(
+    function: Function,
+    args: Vec<crate::vm::datamodel::Value>,
+) -> Result<crate::vm::datamodel::Value, bytecode::OpError> {
+    use crate::vm::datamodel::{self, Tuple};
+    let ops = function.compile().ops;
+    let function = datamodel::Function::new(Tuple::empty(0), ops);
+    crate::vm::VirtualMachine::call(function.into(), args)
+}
@@ -5,10 +5,18 @@ pub enum ModuleItem {
     Buffer(Vec<u8>),
     ModuleRef(u32),
     Function(Function),
+    /// the item that `module` exports as `name`, or the module itself
+    Import {
+        module: String,
+        name: Option<String>,
+    },
 }
 
 pub struct Module {
+    pub name: Option<String>,
     pub items: Vec<ModuleItem>,
+    /// names that other modules can import items by, with the item index
+    pub exports: Vec<(String, u32)>,
 }
 
 pub struct Program {
@@ -0,0 +1,438 @@
+use super::{ops, ops::LiteralValue, CodeGenerator, Expr, Label, Statement, Var};
+use crate::stage1::Type;
+use crate::vm::datamodel::ValueType;
+
+pub enum Pattern {
+    /// matches anything
+    Wildcard,
+    /// matches anything, and stores it in a variable that is bound for the arm
+    Bind(Var),
+    /// matches values of the same type as the literal, that are equal to it
+    Literal(LiteralValue),
+    /// matches values of a type
+    Type(ValueType),
+    /// matches tuples with one item for each pattern, if every item matches
+    Tuple(Vec<Pattern>),
+}
+
+static WILDCARD: Pattern = Pattern::Wildcard;
+
+pub struct MatchArm {
+    pub pattern: Pattern,
+    /// if set, the arm only matches if this is true; the variables bound by
+    /// the pattern can be used in it
+    pub guard: Option<Expr>,
+    pub body: Vec<Statement>,
+}
+
+/// Runs the body of the first arm that matches `value`, or nothing if no arm
+/// matches. If `ty` is set, it is the stage1 type of `value`, and the arms
+/// without guards must match every value of that type.
+pub struct Match {
+    pub value: Box<Expr>,
+    pub ty: Option<Type>,
+    pub arms: Vec<MatchArm>,
+}
+
+impl Match {
+    pub fn compile(&self, g: &mut CodeGenerator) {
+        if let Some(ty) = &self.ty {
+            if !self.is_exhaustive(ty) {
+                panic!("match does not cover every value of its type");
+            }
+        }
+        let value = g.alloc_temp();
+        self.value.compile(g);
+        g.push(ops::StackStore::new(value).into());
+        let label_end = g.create_label();
+        for arm in &self.arms {
+            arm.compile(g, value, label_end);
+        }
+        g.label_here(label_end);
+        g.free_temp(value);
+    }
+
+    fn is_exhaustive(&self, ty: &Type) -> bool {
+        let rows = self
+            .arms
+            .iter()
+            .filter(|arm| arm.guard.is_none())
+            .map(|arm| vec![&arm.pattern])
+            .collect();
+        covers(std::slice::from_ref(ty), rows)
+    }
+}
+
+impl MatchArm {
+    fn compile(&self, g: &mut CodeGenerator, value: u8, label_end: Label) {
+        let label_next = g.create_label();
+        let mut path = Vec::new();
+        // if the pattern doesn't match, jump to label_next
+        self.pattern.compile_test(g, value, &mut path, label_next);
+        let vars = self.pattern.vars();
+        for &var in &vars {
+            g.bind_var(var);
+        }
+        self.pattern.compile_binds(g, value, &mut path);
+        if let Some(guard) = &self.guard {
+            guard.compile(g);
+            g.push_cond_jump(label_next);
+        }
+        for statement in &self.body {
+            statement.compile(g);
+        }
+        g.push_jump(label_end, ops::Jump::new(0).into());
+        g.label_here(label_next);
+        for var in vars {
+            g.drop_var(var);
+        }
+    }
+}
+
+impl Pattern {
+    /// The variables bound by this pattern.
+    pub fn vars(&self) -> Vec<Var> {
+        let mut vars = Vec::new();
+        self.acc_vars(&mut vars);
+        vars
+    }
+
+    fn acc_vars(&self, vars: &mut Vec<Var>) {
+        match self {
+            Pattern::Bind(var) => vars.push(*var),
+            Pattern::Tuple(items) => {
+                for item in items {
+                    item.acc_vars(vars);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn compile_test(&self, g: &mut CodeGenerator, value: u8, path: &mut Vec<i64>, label: Label) {
+        match self {
+            Pattern::Wildcard | Pattern::Bind(_) => {}
+            Pattern::Literal(lit) => {
+                let ty = lit.into_val().get_type();
+                push_type_test(g, value, path, ty, label);
+                // values of the same type can always be compared
+                if ty != ValueType::None {
+                    push_load(g, value, path);
+                    g.push(ops::LiteralCreate::new(lit.clone()).into());
+                    g.push(ops::CmpEq.into());
+                    g.push_cond_jump(label);
+                }
+            }
+            Pattern::Type(ty) => push_type_test(g, value, path, *ty, label),
+            Pattern::Tuple(items) => {
+                push_type_test(g, value, path, ValueType::Tuple, label);
+                push_load(g, value, path);
+                g.push(ops::SeqLen.into());
+                g.push(ops::LiteralCreate::new((items.len() as i64).into()).into());
+                g.push(ops::CmpEq.into());
+                g.push_cond_jump(label);
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i as i64);
+                    item.compile_test(g, value, path, label);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    fn compile_binds(&self, g: &mut CodeGenerator, value: u8, path: &mut Vec<i64>) {
+        match self {
+            Pattern::Bind(var) => {
+                push_load(g, value, path);
+                g.push_var_store(*var);
+            }
+            Pattern::Tuple(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i as i64);
+                    item.compile_binds(g, value, path);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Loads the part of the matched value at `path`, which is a list of tuple
+/// indices.
+fn push_load(g: &mut CodeGenerator, value: u8, path: &[i64]) {
+    g.push(ops::StackLoad::new(value).into());
+    for &index in path {
+        g.push(ops::LiteralCreate::new(index.into()).into());
+        g.push(ops::SeqGet.into());
+    }
+}
+
+fn push_type_test(g: &mut CodeGenerator, value: u8, path: &[i64], ty: ValueType, label: Label) {
+    push_load(g, value, path);
+    g.push(ops::GetType.into());
+    g.push(ops::LiteralCreate::new((ty as u8 as i64).into()).into());
+    g.push(ops::CmpEq.into());
+    g.push_cond_jump(label);
+}
+
+/// Whether every list of values of `types` matches at least one row of
+/// patterns. Each row has one pattern for each type.
+fn covers(types: &[Type], rows: Vec<Vec<&Pattern>>) -> bool {
+    let (ty, rest) = match types.split_first() {
+        Some(t) => t,
+        None => return !rows.is_empty(),
+    };
+    let value_type = ty.value_type();
+    let matches_any = |p: &Pattern| match p {
+        Pattern::Wildcard | Pattern::Bind(_) => true,
+        Pattern::Type(t) => Some(*t) == value_type,
+        _ => false,
+    };
+    match ty {
+        Type::Bool => [true, false].iter().all(|&b| {
+            let rows = specialize(&rows, |p| match p {
+                Pattern::Literal(LiteralValue::Bool(t)) if *t == b => Some(Vec::new()),
+                p if matches_any(p) => Some(Vec::new()),
+                _ => None,
+            });
+            covers(rest, rows)
+        }),
+        Type::Option(inner) => {
+            let is_none = |p: &Pattern| {
+                matches!(
+                    p,
+                    Pattern::Literal(LiteralValue::None) | Pattern::Type(ValueType::None)
+                )
+            };
+            let none = specialize(&rows, |p| match is_none(p) || matches_any(p) {
+                true => Some(Vec::new()),
+                false => None,
+            });
+            // the other patterns are matched against the inner type
+            let some = specialize(&rows, |p| match is_none(p) {
+                true => None,
+                false => Some(vec![p]),
+            });
+            let mut some_types = vec![(**inner).clone()];
+            some_types.extend_from_slice(rest);
+            covers(rest, none) && covers(&some_types, some)
+        }
+        Type::Tuple(items) => {
+            let rows = specialize(&rows, |p| match p {
+                Pattern::Tuple(t) if t.len() == items.len() => Some(t.iter().collect()),
+                p if matches_any(p) => Some(vec![&WILDCARD; items.len()]),
+                _ => None,
+            });
+            let mut item_types = items.to_vec();
+            item_types.extend_from_slice(rest);
+            covers(&item_types, rows)
+        }
+        // there are too many values to list, so only patterns that match
+        // everything count
+        _ => covers(rest, specialize(&rows, |p| matches_any(p).then(Vec::new))),
+    }
+}
+
+/// Keeps the rows whose first pattern is accepted by `f`, and replaces it with
+/// the patterns `f` returns.
+fn specialize<'a>(
+    rows: &[Vec<&'a Pattern>],
+    f: impl Fn(&'a Pattern) -> Option<Vec<&'a Pattern>>,
+) -> Vec<Vec<&'a Pattern>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut t = f(row[0])?;
+            t.extend_from_slice(&row[1..]);
+            Some(t)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{run, span, var};
+    use super::*;
+    use crate::stage0::{BinaryOp, BinaryOpType, Function};
+    use crate::vm::datamodel::{Buffer, Tuple, Value};
+    use std::rc::Rc;
+
+    fn literal(t: impl Into<LiteralValue>) -> Expr {
+        Expr::LiteralValue(span(t.into()))
+    }
+
+    fn arm(pattern: Pattern, guard: Option<Expr>, result: Expr) -> MatchArm {
+        MatchArm {
+            pattern,
+            guard,
+            body: vec![Statement::Return(result)],
+        }
+    }
+
+    /// Runs a match of `arms` on `value`, and returns what the arm that
+    /// matched returns, or -1 if none did.
+    fn run_match(arms: Vec<MatchArm>, value: Value) -> Value {
+        let function = Function {
+            args: vec![0],
+            body: vec![
+                Statement::Match(Match {
+                    value: Box::new(var(0)),
+                    ty: None,
+                    arms,
+                }),
+                Statement::Return(literal(-1)),
+            ],
+        };
+        run(function, vec![value]).unwrap()
+    }
+
+    fn arms() -> Vec<MatchArm> {
+        let equal = |lhs, rhs| {
+            Expr::BinaryOp(BinaryOp {
+                op_type: BinaryOpType::Equal,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            })
+        };
+        let pair = vec![Pattern::Literal(0.into()), Pattern::Bind(1)];
+        vec![
+            arm(Pattern::Literal(1.into()), None, literal(1)),
+            arm(Pattern::Literal(true.into()), None, literal(2)),
+            arm(Pattern::Literal(LiteralValue::None), None, literal(3)),
+            arm(Pattern::Tuple(pair), None, var(1)),
+            arm(Pattern::Type(ValueType::Real), None, literal(5)),
+            arm(
+                Pattern::Bind(2),
+                Some(equal(var(2), literal(42))),
+                literal(6),
+            ),
+            arm(Pattern::Wildcard, None, literal(7)),
+        ]
+    }
+
+    fn int(t: Value) -> i64 {
+        match t {
+            Value::Integer(t) => t,
+            _ => panic!("not an integer"),
+        }
+    }
+
+    #[test]
+    fn first_matching_arm_runs() {
+        let tuple = |items: Vec<Value>| Tuple::from_iter(items.into_iter()).into();
+        let cases = vec![
+            (Value::Integer(1), 1),
+            (Value::Bool(true), 2),
+            (Value::Bool(false), 7),
+            (Value::None, 3),
+            (tuple(vec![Value::Integer(0), Value::Integer(40)]), 40),
+            (tuple(vec![Value::Integer(1), Value::Integer(40)]), 7),
+            (tuple(vec![Value::Integer(0)]), 7),
+            (Value::Real(1.0), 5),
+            (Value::Integer(42), 6),
+            (Value::Integer(2), 7),
+            (Buffer::new(Vec::new()).into(), 7),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(int(run_match(arms(), value)), expected);
+        }
+    }
+
+    #[test]
+    fn no_arm_can_match() {
+        let arms = vec![arm(Pattern::Literal(1.into()), None, literal(1))];
+        assert_eq!(int(run_match(arms, Value::Integer(2))), -1);
+    }
+
+    fn exhaustive(ty: Type, arms: Vec<(Pattern, bool)>) -> bool {
+        let arms = arms
+            .into_iter()
+            .map(|(pattern, guard)| arm(pattern, guard.then(|| literal(true)), literal(0)))
+            .collect();
+        let m = Match {
+            value: Box::new(var(0)),
+            ty: Some(ty.clone()),
+            arms,
+        };
+        m.is_exhaustive(&ty)
+    }
+
+    #[test]
+    fn bools_and_options_can_be_covered_by_literals() {
+        let lit = |t: bool| (Pattern::Literal(t.into()), false);
+        assert!(exhaustive(Type::Bool, vec![lit(true), lit(false)]));
+        assert!(!exhaustive(Type::Bool, vec![lit(true)]));
+        assert!(exhaustive(
+            Type::Bool,
+            vec![lit(true), (Pattern::Bind(0), false)]
+        ));
+
+        let option = Type::Option(Box::new(Type::Bool));
+        let none = (Pattern::Literal(LiteralValue::None), false);
+        assert!(exhaustive(
+            option.clone(),
+            vec![none, lit(true), lit(false)]
+        ));
+        let none = (Pattern::Type(ValueType::None), false);
+        assert!(!exhaustive(option.clone(), vec![none, lit(true)]));
+        assert!(!exhaustive(option, vec![lit(true), lit(false)]));
+    }
+
+    #[test]
+    fn tuples_are_covered_item_by_item() {
+        let pair = Type::Tuple(Rc::from(vec![Type::Bool, Type::Integer]));
+        let row = |b: Option<bool>, i: Pattern| {
+            let b = match b {
+                Some(b) => Pattern::Literal(b.into()),
+                None => Pattern::Wildcard,
+            };
+            (Pattern::Tuple(vec![b, i]), false)
+        };
+        let int = || Pattern::Type(ValueType::Integer);
+        assert!(exhaustive(
+            pair.clone(),
+            vec![row(Some(true), int()), row(Some(false), Pattern::Bind(0))]
+        ));
+        assert!(!exhaustive(
+            pair.clone(),
+            vec![
+                row(Some(true), int()),
+                row(None, Pattern::Literal(1.into()))
+            ]
+        ));
+        assert!(exhaustive(pair.clone(), vec![(Pattern::Wildcard, false)]));
+        assert!(!exhaustive(
+            pair,
+            vec![(Pattern::Tuple(vec![Pattern::Wildcard]), false)]
+        ));
+    }
+
+    #[test]
+    fn only_arms_without_guards_count() {
+        let int = Pattern::Type(ValueType::Integer);
+        assert!(exhaustive(Type::Integer, vec![(int, false)]));
+        assert!(!exhaustive(Type::Integer, vec![(Pattern::Wildcard, true)]));
+        assert!(!exhaustive(
+            Type::Integer,
+            vec![(Pattern::Literal(1.into()), false)]
+        ));
+        assert!(!exhaustive(
+            Type::Real,
+            vec![(Pattern::Type(ValueType::Integer), false)]
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "match does not cover every value of its type")]
+    fn non_exhaustive_matches_dont_compile() {
+        let function = Function {
+            args: vec![0],
+            body: vec![Statement::Match(Match {
+                value: Box::new(var(0)),
+                ty: Some(Type::Bool),
+                arms: vec![arm(Pattern::Literal(true.into()), None, literal(1))],
+            })],
+        };
+        function.compile();
+    }
+}
@@ -1,10 +1,35 @@
-use super::{ops, CodeGenerator, Expr, Label, Var};
+use super::{ops, BinaryOpType, CodeGenerator, Expr, Label, Match, Var};
+use crate::stage1::Type;
+use crate::vm::datamodel::{ByteOrder, NumType};
 
 pub enum Statement {
     BindVar(Var),
     DropVar(Var),
     InitVar(Var),
     Loop(Loop),
+    /// Runs `body` once for each item of `iterable`, with the item in `var`.
+    /// `var` is bound by the loop, and can't be used outside of it.
+    ForIn {
+        var: Var,
+        iterable: Box<Expr>,
+        label: Option<usize>,
+        body: Vec<Statement>,
+    },
+    /// Runs `body` with `var` counting from `start` towards `end` by `step`,
+    /// which defaults to 1. The bounds are evaluated once, and unlike ForIn
+    /// over a range value, nothing is allocated. A zero step is a ZeroStep
+    /// error, and more than Integer::MAX items is an Overflow.
+    ForRange {
+        var: Var,
+        start: Box<Expr>,
+        end: Box<Expr>,
+        step: Option<Box<Expr>>,
+        inclusive: bool,
+        label: Option<usize>,
+        body: Vec<Statement>,
+    },
+    /// Sets the source line of the code that follows, for DebugInfo.
+    Line(u32),
     Break {
         label: Option<usize>,
     },
@@ -14,9 +39,23 @@ pub enum Statement {
     Expr(Expr),
     Return(Expr),
     IfElse(IfElse),
+    Match(Match),
+    /// The place can also be a TupleCreate or ListCreate of places, which
+    /// unpacks a sequence into them, and Discard ignores a value. If `ty` is
+    /// set, it is the stage1 type of `value`, and is used to check the number
+    /// of places when unpacking.
     Assign {
         place: Box<Expr>,
         value: Box<Expr>,
+        ty: Option<Type>,
+    },
+    /// `place op= value`, where the place is a Var or SeqIndex, and its seq
+    /// and index are only evaluated once. With LogicAnd or LogicOr, `value`
+    /// is only evaluated if it is needed, but the place is always set.
+    CompoundAssign {
+        place: Box<Expr>,
+        op_type: BinaryOpType,
+        value: Box<Expr>,
     },
     SeqAppend {
         seq: Box<Expr>,
@@ -30,6 +69,24 @@ pub enum Statement {
         list: Box<Expr>,
         value: Box<Expr>,
     },
+    ListSetSlice {
+        list: Box<Expr>,
+        src: Box<Expr>,
+        src_offset: Box<Expr>,
+        offset: Box<Expr>,
+        len: Box<Expr>,
+    },
+    ListInsert {
+        list: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    ListReverse(Box<Expr>),
+    ListSort(Box<Expr>),
+    ListSortBy {
+        list: Box<Expr>,
+        cmp: Box<Expr>,
+    },
     BufferSetSlice {
         buffer: Box<Expr>,
         src: Box<Expr>,
@@ -37,6 +94,13 @@ pub enum Statement {
         offset: Box<Expr>,
         len: Box<Expr>,
     },
+    BufferWrite {
+        buffer: Box<Expr>,
+        offset: Box<Expr>,
+        value: Box<Expr>,
+        ty: NumType,
+        order: ByteOrder,
+    },
 }
 
 impl Statement {
@@ -46,6 +110,97 @@ impl Statement {
             Statement::DropVar(var) => g.drop_var(*var),
             Statement::InitVar(var) => g.push_var_store(*var),
             Statement::Loop(l) => l.compile(g),
+            Statement::ForIn {
+                var,
+                iterable,
+                label,
+                body,
+            } => {
+                let iter = g.alloc_temp();
+                iterable.compile(g);
+                g.push(ops::IterNew.into());
+                g.push(ops::StackStore::new(iter).into());
+                g.bind_var(*var);
+                g.loop_enter(*label);
+                let label_continue = g.loop_get_continue(*label);
+                let label_break = g.loop_get_break(*label);
+                g.label_here(label_continue);
+                // get the next item, or jump to label_break if there is none
+                g.push(ops::StackLoad::new(iter).into());
+                g.push_jump(label_break, ops::IterNext::new(0).into());
+                g.push_var_store(*var);
+                for statement in body {
+                    statement.compile(g);
+                }
+                g.push_jump(label_continue, ops::Jump::new(0).into());
+                g.label_here(label_break);
+                g.loop_exit(*label);
+                g.drop_var(*var);
+                g.free_temp(iter);
+            }
+            Statement::ForRange {
+                var,
+                start,
+                end,
+                step,
+                inclusive,
+                label,
+                body,
+            } => {
+                let (i, n, s) = (g.alloc_temp(), g.alloc_temp(), g.alloc_temp());
+                start.compile(g);
+                g.push(ops::StackStore::new(i).into());
+                end.compile(g);
+                g.push(ops::StackStore::new(n).into());
+                match step {
+                    Some(step) => step.compile(g),
+                    None => g.push(ops::LiteralCreate::new(1.into()).into()),
+                }
+                g.push(ops::StackStore::new(s).into());
+                // the range checks the step and counts the items, so that
+                // the loop only has to count down
+                g.push(ops::StackLoad::new(i).into());
+                g.push(ops::StackLoad::new(n).into());
+                g.push(ops::StackLoad::new(s).into());
+                match inclusive {
+                    true => g.push(ops::RangeCreateInclusive.into()),
+                    false => g.push(ops::RangeCreate.into()),
+                }
+                g.push(ops::SeqLen.into());
+                g.push(ops::StackStore::new(n).into());
+                g.bind_var(*var);
+                g.loop_enter(*label);
+                let label_continue = g.loop_get_continue(*label);
+                let label_break = g.loop_get_break(*label);
+                let label_top = g.create_label();
+                g.label_here(label_top);
+                g.push(ops::StackLoad::new(n).into());
+                g.push_jump(label_break, ops::JumpZero::new(0).into());
+                g.push(ops::StackLoad::new(i).into());
+                g.push_var_store(*var);
+                for statement in body {
+                    statement.compile(g);
+                }
+                g.label_here(label_continue);
+                g.push(ops::StackLoad::new(n).into());
+                g.push(ops::LiteralCreate::new(1.into()).into());
+                g.push(ops::Sub.into());
+                g.push(ops::StackStore::new(n).into());
+                // after the last item this can go past the end of Integer,
+                // but then the count is zero, and the value is never used
+                g.push(ops::StackLoad::new(i).into());
+                g.push(ops::StackLoad::new(s).into());
+                g.push(ops::WrappingAdd.into());
+                g.push(ops::StackStore::new(i).into());
+                g.push_jump(label_top, ops::Jump::new(0).into());
+                g.label_here(label_break);
+                g.loop_exit(*label);
+                g.drop_var(*var);
+                g.free_temp(s);
+                g.free_temp(n);
+                g.free_temp(i);
+            }
+            Statement::Line(line) => g.set_line(*line),
             Statement::Break { label } => {
                 let label = g.loop_get_break(*label);
                 g.push_jump(label, ops::Jump::new(0).into());
@@ -63,7 +218,8 @@ impl Statement {
                 g.push(ops::Return.into());
             }
             Statement::IfElse(s) => s.compile(g),
-            Statement::Assign { place, value } => match &**place {
+            Statement::Match(m) => m.compile(g),
+            Statement::Assign { place, value, ty } => match &**place {
                 Expr::Var(var) => {
                     value.compile(g);
                     g.push_var_store(var.inner);
@@ -74,6 +230,42 @@ impl Statement {
                     value.compile(g);
                     g.push(ops::SeqSet.into());
                 }
+                _ => {
+                    if let Some(ty) = ty {
+                        check_unpack(place, ty);
+                    }
+                    value.compile(g);
+                    push_store(g, place);
+                }
+            },
+            Statement::CompoundAssign {
+                place,
+                op_type,
+                value,
+            } => match &**place {
+                Expr::Var(var) => {
+                    g.push_var_load(var.inner);
+                    op_type.push_with_rhs(g, value);
+                    g.push_var_store(var.inner);
+                }
+                Expr::SeqIndex { seq, index } => {
+                    let (s, i) = (g.alloc_temp(), g.alloc_temp());
+                    seq.compile(g);
+                    g.push(ops::StackStore::new(s).into());
+                    index.compile(g);
+                    g.push(ops::StackStore::new(i).into());
+                    // seq and index for SeqSet
+                    g.push(ops::StackLoad::new(s).into());
+                    g.push(ops::StackLoad::new(i).into());
+                    // seq[index] op value
+                    g.push(ops::StackLoad::new(s).into());
+                    g.push(ops::StackLoad::new(i).into());
+                    g.push(ops::SeqGet.into());
+                    op_type.push_with_rhs(g, value);
+                    g.push(ops::SeqSet.into());
+                    g.free_temp(i);
+                    g.free_temp(s);
+                }
                 _ => panic!("invalid place expression"),
             },
             Statement::SeqAppend { seq, src } => {
@@ -91,6 +283,39 @@ impl Statement {
                 value.compile(g);
                 g.push(ops::ListPush.into());
             }
+            Statement::ListSetSlice {
+                list,
+                src,
+                src_offset,
+                offset,
+                len,
+            } => {
+                list.compile(g);
+                src.compile(g);
+                src_offset.compile(g);
+                offset.compile(g);
+                len.compile(g);
+                g.push(ops::ListSetSlice.into());
+            }
+            Statement::ListInsert { list, index, value } => {
+                list.compile(g);
+                index.compile(g);
+                value.compile(g);
+                g.push(ops::ListInsert.into());
+            }
+            Statement::ListReverse(list) => {
+                list.compile(g);
+                g.push(ops::ListReverse.into());
+            }
+            Statement::ListSort(list) => {
+                list.compile(g);
+                g.push(ops::ListSort.into());
+            }
+            Statement::ListSortBy { list, cmp } => {
+                list.compile(g);
+                cmp.compile(g);
+                g.push(ops::ListSortBy.into());
+            }
             Statement::BufferSetSlice {
                 buffer,
                 src,
@@ -105,6 +330,72 @@ impl Statement {
                 len.compile(g);
                 g.push(ops::BufferSetSlice.into());
             }
+            Statement::BufferWrite {
+                buffer,
+                offset,
+                value,
+                ty,
+                order,
+            } => {
+                buffer.compile(g);
+                offset.compile(g);
+                value.compile(g);
+                g.push(ops::BufferWrite::new(*ty, *order).into());
+            }
+        }
+    }
+}
+
+/// Pops a value, and stores it in `place`.
+fn push_store(g: &mut CodeGenerator, place: &Expr) {
+    match place {
+        Expr::Var(var) => g.push_var_store(var.inner),
+        Expr::Discard => g.push(ops::StackPop.into()),
+        Expr::SeqIndex { seq, index } => {
+            let value = g.alloc_temp();
+            g.push(ops::StackStore::new(value).into());
+            seq.compile(g);
+            index.compile(g);
+            g.push(ops::StackLoad::new(value).into());
+            g.push(ops::SeqSet.into());
+            g.free_temp(value);
+        }
+        Expr::TupleCreate(places) | Expr::ListCreate(places) => {
+            // the first item ends up on top, so places are stored in order
+            g.push(ops::SeqUnpack::new(places.len() as u8).into());
+            for place in places {
+                push_store(g, place);
+            }
+        }
+        _ => panic!("invalid place expression"),
+    }
+}
+
+/// Checks that values of type `ty` can be unpacked into `place`, as far as
+/// the type is known.
+fn check_unpack(place: &Expr, ty: &Type) {
+    let places = match place {
+        Expr::TupleCreate(places) | Expr::ListCreate(places) => places,
+        _ => return,
+    };
+    match ty {
+        Type::Tuple(items) => {
+            if items.len() != places.len() {
+                panic!(
+                    "cannot unpack {} items into {} places",
+                    items.len(),
+                    places.len()
+                );
+            }
+            for (place, item) in places.iter().zip(items.iter()) {
+                check_unpack(place, item);
+            }
+        }
+        Type::List(_) | Type::Buffer | Type::Table => {}
+        _ => {
+            if ty.value_type().is_some() {
+                panic!("cannot unpack a value that isn't a sequence");
+            }
         }
     }
 }
@@ -124,8 +415,8 @@ impl Loop {
         if let Some(condition) = &self.condition {
             // compile condition
             condition.compile(g);
-            // if zero, jump to label_break
-            g.push_jump(label_break, ops::JumpZero::new(0).into());
+            // if false, jump to label_break
+            g.push_cond_jump(label_break);
         }
         for statement in &self.body {
             statement.compile(g);
@@ -170,8 +461,8 @@ impl If {
         let label_next = g.create_label();
         // compile condition
         self.condition.compile(g);
-        // if zero, jump to label_next
-        g.push_jump(label_next, ops::JumpZero::new(0).into());
+        // if false, jump to label_next
+        g.push_cond_jump(label_next);
         // compile body statements
         for statement in &self.body {
             statement.compile(g);
@@ -181,3 +472,192 @@ impl If {
         g.label_here(label_next);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{run, span, var};
+    use super::*;
+    use crate::stage0::Function;
+    use crate::vm::bytecode::OpError;
+    use crate::vm::datamodel::{self, Tuple, Value};
+    use std::convert::TryInto;
+
+    fn literal(val: bool) -> Expr {
+        Expr::LiteralValue(span(val.into()))
+    }
+
+    /// Calling None fails, so this shows whether it was evaluated.
+    fn fails() -> Expr {
+        Expr::Call {
+            func: Box::new(Expr::LiteralValue(span(ops::LiteralValue::None))),
+            args: Vec::new(),
+        }
+    }
+
+    /// Runs `a op= value; return a` with `a = lhs`, where `a` is a Var or
+    /// the item of a list.
+    fn compound(in_list: bool, lhs: bool, op_type: BinaryOpType, value: Expr) -> Value {
+        let place = || match in_list {
+            true => Box::new(Expr::SeqIndex {
+                seq: Box::new(var(0)),
+                index: Box::new(Expr::LiteralValue(span(0.into()))),
+            }),
+            false => Box::new(var(0)),
+        };
+        let init = match in_list {
+            true => Expr::ListCreate(vec![literal(lhs)]),
+            false => literal(lhs),
+        };
+        let function = Function {
+            args: Vec::new(),
+            body: vec![
+                Statement::BindVar(0),
+                Statement::Assign {
+                    place: Box::new(var(0)),
+                    value: Box::new(init),
+                    ty: None,
+                },
+                Statement::CompoundAssign {
+                    place: place(),
+                    op_type,
+                    value: Box::new(value),
+                },
+                Statement::Return(*place()),
+            ],
+        };
+        run(function, Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn compound_logic_ops_short_circuit() {
+        for &in_list in &[false, true] {
+            let t = compound(in_list, false, BinaryOpType::LogicAnd, fails());
+            assert!(matches!(t, Value::Bool(false)));
+            let t = compound(in_list, true, BinaryOpType::LogicAnd, literal(false));
+            assert!(matches!(t, Value::Bool(false)));
+            let t = compound(in_list, true, BinaryOpType::LogicOr, fails());
+            assert!(matches!(t, Value::Bool(true)));
+            let t = compound(in_list, false, BinaryOpType::LogicOr, literal(true));
+            assert!(matches!(t, Value::Bool(true)));
+        }
+    }
+
+    fn ints(val: Value) -> Vec<i64> {
+        let list: datamodel::List = val.try_into().unwrap();
+        let int = |t: &Value| t.clone().try_into().unwrap();
+        let ints = list.as_slice().iter().map(int).collect();
+        ints
+    }
+
+    /// A function that pushes each item of its arg to a list, and returns
+    /// the list, with `body` after the push.
+    fn for_in(mut body: Vec<Statement>) -> Function {
+        let mut push = vec![Statement::ListPush {
+            list: Box::new(var(1)),
+            value: Box::new(var(2)),
+        }];
+        push.append(&mut body);
+        Function {
+            args: vec![0],
+            body: vec![
+                Statement::BindVar(1),
+                Statement::Assign {
+                    place: Box::new(var(1)),
+                    value: Box::new(Expr::ListCreate(Vec::new())),
+                    ty: None,
+                },
+                Statement::ForIn {
+                    var: 2,
+                    iterable: Box::new(var(0)),
+                    label: None,
+                    body: push,
+                },
+                Statement::Return(var(1)),
+            ],
+        }
+    }
+
+    #[test]
+    fn for_in_visits_each_item() {
+        let seqs: Vec<Value> = vec![
+            datamodel::List::new(vec![Value::Integer(1), Value::Integer(2)]).into(),
+            Tuple::from_iter(vec![Value::Integer(1), Value::Integer(2)].into_iter()).into(),
+            datamodel::Buffer::new(vec![1, 2]).into(),
+            datamodel::Range::new(1, 2, 1, true).unwrap().into(),
+        ];
+        for seq in seqs {
+            let t = run(for_in(Vec::new()), vec![seq]).unwrap();
+            assert_eq!(ints(t), vec![1, 2]);
+        }
+        let t = run(for_in(Vec::new()), vec![datamodel::List::empty().into()]).unwrap();
+        assert_eq!(ints(t), Vec::<i64>::new());
+        assert!(run(for_in(Vec::new()), vec![Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn for_in_can_break() {
+        let list = datamodel::List::new(vec![Value::Integer(1), Value::Integer(2)]);
+        let function = for_in(vec![Statement::Break { label: None }]);
+        let t = run(function, vec![list.into()]).unwrap();
+        assert_eq!(ints(t), vec![1]);
+    }
+
+    /// Runs a ForRange over the args, and returns a list of the items.
+    fn for_range(inclusive: bool, args: Vec<i64>) -> Result<Vec<i64>, OpError> {
+        let function = Function {
+            args: vec![0, 1, 2],
+            body: vec![
+                Statement::BindVar(3),
+                Statement::Assign {
+                    place: Box::new(var(3)),
+                    value: Box::new(Expr::ListCreate(Vec::new())),
+                    ty: None,
+                },
+                Statement::ForRange {
+                    var: 4,
+                    start: Box::new(var(0)),
+                    end: Box::new(var(1)),
+                    step: Some(Box::new(var(2))),
+                    inclusive,
+                    label: None,
+                    body: vec![Statement::ListPush {
+                        list: Box::new(var(3)),
+                        value: Box::new(var(4)),
+                    }],
+                },
+                Statement::Return(var(3)),
+            ],
+        };
+        let args = args.into_iter().map(Value::Integer).collect();
+        run(function, args).map(ints)
+    }
+
+    #[test]
+    fn for_range_counts_by_step() {
+        assert_eq!(for_range(false, vec![0, 4, 1]).unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(for_range(true, vec![0, 4, 2]).unwrap(), vec![0, 2, 4]);
+        assert_eq!(for_range(false, vec![5, 0, -2]).unwrap(), vec![5, 3, 1]);
+        assert_eq!(for_range(true, vec![1, 1, -1]).unwrap(), vec![1]);
+        assert!(for_range(false, vec![1, 1, 1]).unwrap().is_empty());
+        assert!(for_range(true, vec![0, 5, -1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn for_range_reaches_the_ends_of_integers() {
+        let (min, max) = (i64::MIN, i64::MAX);
+        let t = for_range(true, vec![min + 2, min, -1]).unwrap();
+        assert_eq!(t, vec![min + 2, min + 1, min]);
+        let t = for_range(true, vec![max - 1, max, 1]).unwrap();
+        assert_eq!(t, vec![max - 1, max]);
+        let t = for_range(true, vec![max, min, min]).unwrap();
+        assert_eq!(t, vec![max, -1]);
+    }
+
+    #[test]
+    fn for_range_checks_its_bounds() {
+        let t = for_range(false, vec![0, 1, 0]);
+        assert!(matches!(t, Err(OpError::ZeroStep)));
+        let t = for_range(true, vec![i64::MIN, i64::MAX, 1]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+    }
+}
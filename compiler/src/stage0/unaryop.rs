@@ -7,7 +7,8 @@ pub struct UnaryOp {
 
 #[rustfmt::skip]
 pub enum UnaryOpType {
-    Neg, Not, LogicNot, IntToReal, Floor, Ceil, Trunc, Round
+    Neg, Not, LogicNot, IntToReal, RealToInt(ops::Rounding), Floor, Ceil, Trunc, Round,
+    StructHash
 }
 
 impl UnaryOp {
@@ -23,20 +24,23 @@ impl UnaryOp {
             UnaryOpType::LogicNot => {
                 let label_true = g.create_label();
                 let label_next = g.create_label();
-                // if 0, jump to label_true
-                g.push_jump(label_true, ops::JumpZero::new(0).into());
-                // push false (0)
-                g.push(ops::LiteralCreate::new(0.into()).into());
+                // if false, jump to label_true
+                g.push_cond_jump(label_true);
+                // push false
+                g.push(ops::LiteralCreate::new(false.into()).into());
                 // jump to label_next
                 g.push_jump(label_next, ops::Jump::new(0).into());
-                // push true (1)
+                // push true
                 g.label_here(label_true);
-                g.push(ops::LiteralCreate::new(1.into()).into());
+                g.push(ops::LiteralCreate::new(true.into()).into());
                 g.label_here(label_next);
             }
             UnaryOpType::IntToReal => {
                 g.push(ops::IntToReal.into());
             }
+            UnaryOpType::RealToInt(rounding) => {
+                g.push(ops::RealToInt::new(rounding).into());
+            }
             UnaryOpType::Floor => {
                 g.push(ops::Floor.into());
             }
@@ -49,6 +53,9 @@ impl UnaryOp {
             UnaryOpType::Round => {
                 g.push(ops::Round.into());
             }
+            UnaryOpType::StructHash => {
+                g.push(ops::StructHash.into());
+            }
         }
     }
 }
@@ -1,5 +1,7 @@
 use std::rc::Rc;
 
+use crate::vm::datamodel::ValueType;
+
 #[derive(Clone, PartialEq)]
 pub enum Type {
     Parameter(usize),
@@ -48,6 +50,24 @@ impl Type {
         }
     }
 
+    /// The type of the runtime values of this type, if it is always the same.
+    pub fn value_type(&self) -> Option<ValueType> {
+        match self {
+            Type::Bool => Some(ValueType::Bool),
+            Type::Integer => Some(ValueType::Integer),
+            Type::Real => Some(ValueType::Real),
+            Type::Weak(_) => Some(ValueType::TupleWeak),
+            Type::Tuple(_) => Some(ValueType::Tuple),
+            Type::Table => Some(ValueType::Table),
+            Type::List(_) => Some(ValueType::List),
+            Type::Buffer => Some(ValueType::Buffer),
+            Type::Function(_) => Some(ValueType::Function),
+            Type::NativeFn(_) => Some(ValueType::NativeFn),
+            Type::Unknown => Some(ValueType::Unknown),
+            _ => None,
+        }
+    }
+
     pub fn resolve_params(&self, params: &[Type]) -> Option<Type> {
         match self {
             Type::Parameter(i) => params.get(*i).cloned(),
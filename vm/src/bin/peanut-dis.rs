@@ -0,0 +1,31 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use peanut_script_vm::bytecode::{disasm, Program};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: peanut-dis <file.pnb>");
+            process::exit(2);
+        }
+    };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    // invalid bytecode is listed too, since that's often why it is being
+    // disassembled
+    match Program::from_bytes_unverified(&bytes) {
+        Ok(program) => print!("{}", disasm::disassemble(&program)),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
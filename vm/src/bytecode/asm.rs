@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{DebugInfo, Function, LocalInfo, Module, ModuleItem, Op, OpType, Program};
+
+/*
+The assembler reads the listings that `disasm` writes. Each line is a module,
+an item, an export, an op, a label or `end`, and `;` starts a comment:
+
+    module math
+        literal 1.5
+        buffer 00ff
+        moduleref 0
+        import io print
+        function
+            StackLoad 1
+            JumpZero done
+            StackLoad 2
+        done:
+            Return
+        end
+        export sqrt 4
+
+Items and exports belong to the module before them, and a function's ops are
+the lines up to its `end`. Ops are written as their OpType name, then their operands,
+and the index that the disassembler writes before each op is skipped. Jump
+ops take the name of a label, or a signed dest such as `-3` or `+2` for a
+jump that has no label. A label is defined by
+`name:` before the op it refers to, or before `end` to jump past the last op,
+and can only be used in its function.
+
+A function's DebugInfo is written with `debug name`, `local name slot start
+end` for each of its locals, and `line N` before the op where source line N
+starts, as the disassembler writes them. `local` and `line` give the function
+DebugInfo without a name if there is no `debug` line.
+
+The program isn't verified, so that invalid bytecode can be written too.
+*/
+
+#[derive(Debug)]
+pub enum AsmErrorKind {
+    /// an item or export before the first module
+    NoModule,
+    UnknownItem(String),
+    UnknownOp(String),
+    /// the operands aren't valid for the op or item
+    BadOperands,
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    /// a function has no `end`
+    MissingEnd,
+}
+
+/// An AsmErrorKind, with the line it was found on, counting from 1.
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::NoModule => write!(f, "item is not in a module"),
+            AsmErrorKind::UnknownItem(t) => write!(f, "unknown item `{}`", t),
+            AsmErrorKind::UnknownOp(t) => write!(f, "unknown op `{}`", t),
+            AsmErrorKind::BadOperands => write!(f, "invalid operands"),
+            AsmErrorKind::UnknownLabel(t) => write!(f, "label `{}` is not defined", t),
+            AsmErrorKind::DuplicateLabel(t) => write!(f, "label `{}` is already defined", t),
+            AsmErrorKind::MissingEnd => write!(f, "function has no `end`"),
+        }
+    }
+}
+
+/// the line number and tokens of each line that isn't empty
+type Lines<'a> = dyn Iterator<Item = (usize, Vec<&'a str>)> + 'a;
+
+pub fn assemble(src: &str) -> Result<Program, AsmError> {
+    let mut lines = src
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let code = line.split(';').next().unwrap_or("");
+            (i + 1, code.split_whitespace().collect::<Vec<_>>())
+        })
+        .filter(|(_, tokens)| !tokens.is_empty());
+    let mut modules: Vec<Module> = Vec::new();
+    while let Some((line, tokens)) = lines.next() {
+        let err = |kind| AsmError { line, kind };
+        if let ["module", name @ ..] = tokens.as_slice() {
+            let name = match name {
+                [] => None,
+                [name] => Some(name.to_string()),
+                _ => return Err(err(AsmErrorKind::BadOperands)),
+            };
+            modules.push(Module {
+                name,
+                items: Vec::new(),
+                exports: Vec::new(),
+            });
+            continue;
+        }
+        let module = modules
+            .last_mut()
+            .ok_or_else(|| err(AsmErrorKind::NoModule))?;
+        if let ["export", name, item] = tokens.as_slice() {
+            let item = item.parse().map_err(|_| err(AsmErrorKind::BadOperands))?;
+            module.exports.push((name.to_string(), item));
+            continue;
+        }
+        let item = match (tokens[0], &tokens[1..]) {
+            ("literal", [t]) => {
+                ModuleItem::LiteralValue(t.parse().map_err(|_| err(AsmErrorKind::BadOperands))?)
+            }
+            ("buffer", []) => ModuleItem::Buffer(Vec::new()),
+            ("buffer", [t]) => {
+                ModuleItem::Buffer(parse_hex(t).ok_or_else(|| err(AsmErrorKind::BadOperands))?)
+            }
+            ("moduleref", [t]) => {
+                ModuleItem::ModuleRef(t.parse().map_err(|_| err(AsmErrorKind::BadOperands))?)
+            }
+            ("import", [module, name @ ..]) if name.len() <= 1 => ModuleItem::Import {
+                module: module.to_string(),
+                name: name.first().map(|t| t.to_string()),
+            },
+            ("function", []) => ModuleItem::Function(assemble_function(&mut lines, line)?),
+            ("literal" | "buffer" | "moduleref" | "import" | "function" | "export", _) => {
+                return Err(err(AsmErrorKind::BadOperands));
+            }
+            (t, _) => return Err(err(AsmErrorKind::UnknownItem(t.to_string()))),
+        };
+        module.items.push(item);
+    }
+    Ok(Program { modules })
+}
+
+/// Reads the ops of a function up to its `end`. `start` is the line of the
+/// function item.
+fn assemble_function<'a>(lines: &mut Lines<'a>, start: usize) -> Result<Function, AsmError> {
+    // the ops are only read at the end, when every label is defined
+    let mut op_lines = Vec::new();
+    let mut labels = HashMap::new();
+    let mut debug: Option<DebugInfo> = None;
+    let new_debug = || DebugInfo {
+        name: String::new(),
+        locals: Vec::new(),
+        lines: Vec::new(),
+    };
+    loop {
+        let (line, tokens) = lines.next().ok_or(AsmError {
+            line: start,
+            kind: AsmErrorKind::MissingEnd,
+        })?;
+        let bad_operands = || AsmError {
+            line,
+            kind: AsmErrorKind::BadOperands,
+        };
+        match tokens.as_slice() {
+            ["end"] => break,
+            ["debug", name @ ..] if name.len() <= 1 => {
+                debug.get_or_insert_with(new_debug).name = name.concat();
+            }
+            ["local", name, slot, start, end] => {
+                let local = LocalInfo {
+                    name: name.to_string(),
+                    slot: slot.parse().map_err(|_| bad_operands())?,
+                    start: start.parse().map_err(|_| bad_operands())?,
+                    end: end.parse().map_err(|_| bad_operands())?,
+                };
+                debug.get_or_insert_with(new_debug).locals.push(local);
+            }
+            ["line", n] => {
+                let n = n.parse().map_err(|_| bad_operands())?;
+                let cursor = op_lines.len() as u32;
+                debug.get_or_insert_with(new_debug).lines.push((cursor, n));
+            }
+            ["debug" | "local" | "line", ..] => return Err(bad_operands()),
+            [label] if label.ends_with(':') => {
+                let label = &label[..label.len() - 1];
+                if labels.insert(label, op_lines.len()).is_some() {
+                    let kind = AsmErrorKind::DuplicateLabel(label.to_string());
+                    return Err(AsmError { line, kind });
+                }
+            }
+            // the index that the disassembler writes
+            [index, rest @ ..] if !rest.is_empty() && index.parse::<usize>().is_ok() => {
+                op_lines.push((line, rest.to_vec()));
+            }
+            _ => op_lines.push((line, tokens)),
+        }
+    }
+    let mut ops = Vec::with_capacity(op_lines.len());
+    for (i, (line, tokens)) in op_lines.into_iter().enumerate() {
+        let err = |kind| AsmError { line, kind };
+        let name = tokens[0];
+        let ty = OpType::from_name(name)
+            .ok_or_else(|| err(AsmErrorKind::UnknownOp(name.to_string())))?;
+        let op = match (is_jump(&ty), &tokens[1..]) {
+            (true, [label]) => match labels.get(label) {
+                Some(target) => {
+                    // jumps are relative to the jump op
+                    let dest = (*target as i64 - i as i64).to_string();
+                    Op::parse(ty, &[&dest])
+                }
+                None if label.starts_with(['-', '+']) => Op::parse(ty, &[label]),
+                None => return Err(err(AsmErrorKind::UnknownLabel(label.to_string()))),
+            },
+            (true, _) => None,
+            (false, operands) => Op::parse(ty, operands),
+        };
+        ops.push(op.ok_or_else(|| err(AsmErrorKind::BadOperands))?);
+    }
+    Ok(Function { ops, debug })
+}
+
+fn is_jump(ty: &OpType) -> bool {
+    matches!(
+        ty,
+        OpType::Jump | OpType::JumpZero | OpType::JumpNeg | OpType::JumpFalse | OpType::IterNext
+    )
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    (s.as_bytes().chunks(2))
+        .map(|pair| match pair {
+            [high, low] => Some(digit(*high)? << 4 | digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::disasm::disassemble;
+
+    const LISTING: &str = "\
+module math ; module 0
+    literal 1.5 ; item 0
+    buffer 00ff ; item 1
+    moduleref 0 ; item 2
+    import io print ; item 3
+    import io ; item 4
+    function ; item 5
+       0  StackLoad 1
+       1  JumpZero L3
+       2  StackLoad 2
+    L3:
+       3  Return
+    end
+    export sqrt 5
+module ; module 1
+    literal none ; item 0
+    literal true ; item 1
+    literal -7 ; item 2
+    function ; item 3
+    L0:
+       0  Jump L0
+    end
+    function ; item 4
+       0  JumpNeg L2
+       1  Return
+    L2:
+    end
+";
+
+    fn error(src: &str) -> AsmError {
+        assemble(src).err().expect("assembled")
+    }
+
+    #[test]
+    fn listings_are_disassembled_the_same() {
+        let program = assemble(LISTING).unwrap();
+        assert_eq!(disassemble(&program), LISTING);
+    }
+
+    #[test]
+    fn disassembly_round_trips() {
+        let program = assemble(LISTING).unwrap();
+        let again = assemble(&disassemble(&program)).unwrap();
+        assert_eq!(again.to_bytes(), program.to_bytes());
+    }
+
+    #[test]
+    fn debug_info_round_trips() {
+        let src = "\
+module m ; module 0
+    function ; item 0
+    debug f
+    local x 1 0 4
+    local y 2 1 3
+    line 1
+       0  StackLoad 1 ; x
+       1  StackStore 2 ; y
+    line 3
+       2  StackLoad 2 ; y
+       3  Return
+    line 4
+    end
+";
+        let program = assemble(src).unwrap();
+        let debug = match &program.modules[0].items[0] {
+            ModuleItem::Function(f) => f.debug.as_ref().unwrap(),
+            _ => panic!("not a function"),
+        };
+        assert_eq!(debug.name, "f");
+        assert_eq!(debug.lines, vec![(0, 1), (2, 3), (4, 4)]);
+        let locals: Vec<_> = (debug.locals.iter())
+            .map(|l| (l.name.as_str(), l.slot, l.start, l.end))
+            .collect();
+        assert_eq!(locals, vec![("x", 1, 0, 4), ("y", 2, 1, 3)]);
+        assert_eq!(disassemble(&program), src);
+        let again = assemble(&disassemble(&program)).unwrap();
+        assert_eq!(again.to_bytes(), program.to_bytes());
+
+        // a function without a name, or with a line table but no `debug`
+        let src = "module m\n  function\n  debug\n  end\n  function\n  line 2\n  end\n";
+        let program = assemble(src).unwrap();
+        for item in &program.modules[0].items {
+            match item {
+                ModuleItem::Function(f) => assert_eq!(f.debug.as_ref().unwrap().name, ""),
+                _ => panic!("not a function"),
+            }
+        }
+        assert!(matches!(
+            error("module m\n  function\n  local x 1 0\n  end\n").kind,
+            AsmErrorKind::BadOperands
+        ));
+        assert!(matches!(
+            error("module m\n  function\n  line x\n  end\n").kind,
+            AsmErrorKind::BadOperands
+        ));
+    }
+
+    #[test]
+    fn jumps_out_of_the_function_keep_their_dest() {
+        let src = "\
+module ; module 0
+    function ; item 0
+       0  Jump +2
+       1  JumpZero -3
+    L2:
+       2  Jump L2
+    end
+";
+        let program = assemble(src).unwrap();
+        match &program.modules[0].items[0] {
+            ModuleItem::Function(f) => {
+                let dests: Vec<_> = f.ops.iter().map(|op| op.jump_dest()).collect();
+                assert_eq!(dests, vec![Some(2), Some(-3), Some(0)]);
+            }
+            _ => panic!("not a function"),
+        }
+        // the jump to 2 has a label, but the jump to -2 doesn't
+        let listing = "\
+module ; module 0
+    function ; item 0
+       0  Jump L2
+       1  JumpZero -3
+    L2:
+       2  Jump L2
+    end
+";
+        assert_eq!(disassemble(&program), listing);
+        let again = assemble(listing).unwrap();
+        assert_eq!(again.to_bytes(), program.to_bytes());
+    }
+
+    #[test]
+    fn comments_and_indices_are_skipped() {
+        let src = "module m\n  function ; f\n  9 StackLoad 1 ; x\n  Return\n  end\n";
+        let program = assemble(src).unwrap();
+        match &program.modules[0].items[0] {
+            ModuleItem::Function(f) => assert_eq!(f.ops.len(), 2),
+            _ => panic!("not a function"),
+        }
+    }
+
+    #[test]
+    fn errors_have_the_line() {
+        let e = error("module m\n  function\n    Frobnicate\n  end\n");
+        assert_eq!(e.line, 3);
+        assert!(matches!(e.kind, AsmErrorKind::UnknownOp(ref t) if t == "Frobnicate"));
+
+        let e = error("module m\n  function\n    Jump nowhere\n  end\n");
+        assert!(matches!(e.kind, AsmErrorKind::UnknownLabel(ref t) if t == "nowhere"));
+
+        let e = error("module m\n  function\n  a:\n  a:\n    Return\n  end\n");
+        assert_eq!(e.line, 4);
+        assert!(matches!(e.kind, AsmErrorKind::DuplicateLabel(_)));
+    }
+
+    #[test]
+    fn bad_listings_are_rejected() {
+        assert!(matches!(
+            error("module m\n  function\n    Return\n").kind,
+            AsmErrorKind::MissingEnd
+        ));
+        assert!(matches!(
+            error("  literal 1\n").kind,
+            AsmErrorKind::NoModule
+        ));
+        assert!(matches!(
+            error("module m\n  thing 1\n").kind,
+            AsmErrorKind::UnknownItem(_)
+        ));
+        assert!(matches!(
+            error("module m\n  function\n    StackLoad x\n  end\n").kind,
+            AsmErrorKind::BadOperands
+        ));
+        assert!(matches!(
+            error("module m\n  buffer 0f0\n").kind,
+            AsmErrorKind::BadOperands
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+use super::{BytesIO, BytesReadError};
+
+/// Names and source lines for the ops of a function. The VM doesn't need it
+/// to run the function, and `Program::strip_debug` removes it.
+pub struct DebugInfo {
+    pub name: String,
+    pub locals: Vec<LocalInfo>,
+    /// `(cursor, line)` pairs sorted by cursor, where the ops from the cursor
+    /// up to the next pair's cursor come from the source line
+    pub lines: Vec<(u32, u32)>,
+}
+
+/// A variable that is stored in local `slot` while the cursor is in
+/// `start..end`.
+pub struct LocalInfo {
+    pub name: String,
+    pub slot: u8,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl DebugInfo {
+    /// The source line of the op at `cursor`.
+    pub fn line_at(&self, cursor: usize) -> Option<u32> {
+        let i = self.lines.partition_point(|&(c, _)| c as usize <= cursor);
+        Some(self.lines[i.checked_sub(1)?].1)
+    }
+
+    /// The name of the variable in local `slot` while the cursor is at
+    /// `cursor`.
+    pub fn local_at(&self, slot: u8, cursor: usize) -> Option<&str> {
+        let cursor = cursor as u32;
+        self.locals
+            .iter()
+            .find(|l| l.slot == slot && l.start <= cursor && cursor < l.end)
+            .map(|l| l.name.as_str())
+    }
+}
+
+impl BytesIO for DebugInfo {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b, name) = <String as BytesIO>::read(b)?;
+        let (b, locals) = <Vec<LocalInfo> as BytesIO>::read(b)?;
+        let (b, lines) = <Vec<(u32, u32)> as BytesIO>::read(b)?;
+        Ok((
+            b,
+            DebugInfo {
+                name,
+                locals,
+                lines,
+            },
+        ))
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let b = <String as BytesIO>::write(&t.name, b)?;
+        let b = <Vec<LocalInfo> as BytesIO>::write(&t.locals, b)?;
+        <Vec<(u32, u32)> as BytesIO>::write(&t.lines, b)
+    }
+    fn encoded_len(t: &Self) -> usize {
+        <String as BytesIO>::encoded_len(&t.name)
+            + <Vec<LocalInfo> as BytesIO>::encoded_len(&t.locals)
+            + <Vec<(u32, u32)> as BytesIO>::encoded_len(&t.lines)
+    }
+}
+
+impl BytesIO for LocalInfo {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b, name) = <String as BytesIO>::read(b)?;
+        let (b, (slot, start, end)) = <(u8, u32, u32) as BytesIO>::read(b)?;
+        Ok((
+            b,
+            LocalInfo {
+                name,
+                slot,
+                start,
+                end,
+            },
+        ))
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let b = <String as BytesIO>::write(&t.name, b)?;
+        <(u8, u32, u32) as BytesIO>::write(&(t.slot, t.start, t.end), b)
+    }
+    fn encoded_len(t: &Self) -> usize {
+        <String as BytesIO>::encoded_len(&t.name)
+            + <(u8, u32, u32) as BytesIO>::encoded_len(&(t.slot, t.start, t.end))
+    }
+}
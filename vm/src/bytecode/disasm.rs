@@ -0,0 +1,163 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+
+use super::{Function, ModuleItem, Op, Program};
+
+/*
+A listing has a line for each module, with its name if it has one, and a
+line for each of its items, with the index of the module or item in a comment
+after it. The module's exports are listed after its items, with the index of
+the item they export:
+
+    module math ; module 0
+        literal 1.5 ; item 0
+        buffer 00ff ; item 1
+        moduleref 0 ; item 2
+        import io print ; item 3
+        import io ; item 4
+        function ; item 5
+           0  StackLoad 1
+           1  JumpZero L3
+           2  StackLoad 2
+        L3:
+           3  Return
+        end
+        export sqrt 5
+
+Each op is after its index in the function. Jumps are shown with labels named
+after the index they jump to, and the label is listed before that op. A jump
+outside of the function, which only unverified bytecode has, is shown with
+its signed dest instead, such as `Jump -3`. `asm` reads listings back into
+the same program.
+
+If a function has DebugInfo, it starts with a `debug` line with the name of
+the function, and a `local` line for each LocalInfo, with its name, slot, and
+the cursors where it starts and ends. Each pair of the line table is written
+as `line N` before the op at its cursor, and ops on locals are followed by the
+name of the variable in the local:
+
+        function ; item 0
+        debug sqrt
+        local x 1 0 4
+        line 3
+           0  StackLoad 1 ; x
+           1  Return
+        end
+
+`asm` reads the DebugInfo back too, as long as the names don't contain
+whitespace or `;`.
+*/
+
+pub fn disassemble(program: &Program) -> String {
+    let mut out = String::new();
+    // writing to a String doesn't fail
+    write_program(&mut out, program).unwrap();
+    out
+}
+
+pub fn disassemble_function(function: &Function) -> String {
+    let mut out = String::new();
+    write_ops(&mut out, function).unwrap();
+    out
+}
+
+fn write_program(out: &mut impl Write, program: &Program) -> fmt::Result {
+    for (m, module) in program.modules.iter().enumerate() {
+        match &module.name {
+            Some(name) => writeln!(out, "module {} ; module {}", name, m)?,
+            None => writeln!(out, "module ; module {}", m)?,
+        }
+        for (i, item) in module.items.iter().enumerate() {
+            match item {
+                ModuleItem::LiteralValue(t) => write!(out, "    literal {}", t)?,
+                ModuleItem::Buffer(t) => {
+                    write!(out, "    buffer")?;
+                    if !t.is_empty() {
+                        write!(out, " ")?;
+                    }
+                    for byte in t {
+                        write!(out, "{:02x}", byte)?;
+                    }
+                }
+                ModuleItem::ModuleRef(t) => write!(out, "    moduleref {}", t)?,
+                ModuleItem::Import { module, name } => {
+                    write!(out, "    import {}", module)?;
+                    if let Some(name) = name {
+                        write!(out, " {}", name)?;
+                    }
+                }
+                ModuleItem::Function(_) => write!(out, "    function")?,
+            }
+            writeln!(out, " ; item {}", i)?;
+            if let ModuleItem::Function(f) = item {
+                write_ops(out, f)?;
+                writeln!(out, "    end")?;
+            }
+        }
+        for (name, item) in module.exports.iter() {
+            writeln!(out, "    export {} {}", name, item)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_ops(out: &mut impl Write, function: &Function) -> fmt::Result {
+    let len = function.ops.len();
+    // a jump past the last op returns, and anything further out has no label
+    let target = |i: usize, dest: i32| {
+        Some(i as isize + dest as isize).filter(|target| (0..=len as isize).contains(target))
+    };
+    let targets: BTreeSet<isize> = (function.ops.iter().enumerate())
+        .filter_map(|(i, op)| target(i, op.jump_dest()?))
+        .collect();
+    let debug = function.debug.as_ref();
+    if let Some(debug) = debug {
+        match debug.name.as_str() {
+            "" => writeln!(out, "    debug")?,
+            name => writeln!(out, "    debug {}", name)?,
+        }
+        for l in &debug.locals {
+            writeln!(out, "    local {} {} {} {}", l.name, l.slot, l.start, l.end)?;
+        }
+    }
+    let mut lines = debug.map_or(&[][..], |d| &d.lines).iter().peekable();
+    for (i, op) in function.ops.iter().enumerate() {
+        if targets.contains(&(i as isize)) {
+            writeln!(out, "    L{}:", i)?;
+        }
+        while let Some((_, line)) = lines.next_if(|&&(cursor, _)| cursor as usize <= i) {
+            writeln!(out, "    line {}", line)?;
+        }
+        write!(out, "{:>8}  ", i)?;
+        match op.jump_dest() {
+            Some(dest) => {
+                let name = op.get_type().get_name();
+                match target(i, dest) {
+                    Some(target) => write!(out, "{} L{}", name, target)?,
+                    None => write!(out, "{} {:+}", name, dest)?,
+                }
+            }
+            None => write!(out, "{}", op)?,
+        }
+        let local = match op {
+            Op::StackLoad(op) => Some(op.local),
+            Op::StackStore(op) => Some(op.local),
+            Op::StackSwap(op) => Some(op.local),
+            _ => None,
+        };
+        match (debug, local) {
+            (Some(debug), Some(local)) => match debug.local_at(local, i) {
+                Some(name) => writeln!(out, " ; {}", name)?,
+                None => writeln!(out)?,
+            },
+            _ => writeln!(out)?,
+        }
+    }
+    if targets.contains(&(len as isize)) {
+        writeln!(out, "    L{}:", len)?;
+    }
+    for (_, line) in lines {
+        writeln!(out, "    line {}", line)?;
+    }
+    Ok(())
+}
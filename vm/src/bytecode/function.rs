@@ -1,16 +1,37 @@
-use super::{BytesIO, BytesReadError, Op};
+use std::rc::Rc;
+
+use super::{BytesIO, BytesReadError, DebugInfo, Op};
+
+use crate::datamodel::{Function as FuncVal, Tuple};
 
 pub struct Function {
     pub ops: Vec<Op>,
+    /// not part of the encoding of the function; `.pnb` files keep it in a
+    /// separate section
+    pub debug: Option<DebugInfo>,
+}
+
+impl Function {
+    /// Creates the value of the function, in `module`, named after its
+    /// DebugInfo.
+    pub fn into_value(self, module: Tuple) -> FuncVal {
+        let mut f = FuncVal::new(module, self.ops);
+        let name = self.debug.map(|debug| debug.name);
+        f.name = name.filter(|name| !name.is_empty()).map(Rc::from);
+        f
+    }
 }
 
 impl BytesIO for Function {
     fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
         let (b, ops) = <Vec<Op> as BytesIO>::read(b)?;
-        let f = Function { ops };
+        let f = Function { ops, debug: None };
         Ok((b, f))
     }
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
         <Vec<Op> as BytesIO>::write(&t.ops, b)
     }
+    fn encoded_len(t: &Self) -> usize {
+        <Vec<Op> as BytesIO>::encoded_len(&t.ops)
+    }
 }
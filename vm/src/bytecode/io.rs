@@ -1,4 +1,5 @@
 use std::convert::TryInto;
+use std::io::{self, Write};
 use std::mem::size_of;
 
 pub enum BytesReadError<'a> {
@@ -9,6 +10,15 @@ pub enum BytesReadError<'a> {
 pub trait BytesIO: Sized {
     fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>>;
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]>;
+    /// The number of bytes that `write` uses for `t`.
+    fn encoded_len(t: &Self) -> usize;
+
+    fn write_to(t: &Self, w: &mut impl Write) -> io::Result<()> {
+        let mut b = vec![0; Self::encoded_len(t)];
+        let rest = Self::write(t, &mut b).map(|rest| rest.len());
+        assert_eq!(rest, Some(0), "encoded_len doesn't match what write uses");
+        w.write_all(&b)
+    }
 }
 
 pub trait DataIO: BytesIO {
@@ -73,12 +83,18 @@ impl<T: DataIO + Sized> BytesIO for T {
         let t = <T as DataIO>::into_bytes(t);
         <<T as DataIO>::Target as BytesIO>::write(&t, b)
     }
+    fn encoded_len(t: &Self) -> usize {
+        let t = <T as DataIO>::into_bytes(t);
+        <<T as DataIO>::Target as BytesIO>::encoded_len(&t)
+    }
 }
 
 impl<T: BytesIO> BytesIO for Vec<T> {
     fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
         let (mut b, len) = <u32 as BytesIO>::read(b)?;
-        let mut acc = Vec::with_capacity(len as usize);
+        // the length comes from the input, so don't allocate for more items
+        // than there are bytes left to read them from
+        let mut acc = Vec::with_capacity((len as usize).min(b.len()));
         for _ in 0..len {
             let (b2, t) = <T as BytesIO>::read(b)?;
             acc.push(t);
@@ -97,6 +113,53 @@ impl<T: BytesIO> BytesIO for Vec<T> {
         }
         Some(b)
     }
+    fn encoded_len(t: &Self) -> usize {
+        size_of::<u32>() + t.iter().map(<T as BytesIO>::encoded_len).sum::<usize>()
+    }
+}
+
+/// A u8 tag, 0 for None or 1 for Some, then the value.
+impl<T: BytesIO> BytesIO for Option<T> {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b2, tag) = <u8 as BytesIO>::read(b)?;
+        match tag {
+            0 => Ok((b2, None)),
+            1 => {
+                let (b2, t) = <T as BytesIO>::read(b2)?;
+                Ok((b2, Some(t)))
+            }
+            _ => Err(BytesReadError::InvalidValue(b)),
+        }
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        match t {
+            None => <u8 as BytesIO>::write(&0, b),
+            Some(t) => {
+                let b = <u8 as BytesIO>::write(&1, b)?;
+                <T as BytesIO>::write(t, b)
+            }
+        }
+    }
+    fn encoded_len(t: &Self) -> usize {
+        size_of::<u8>() + t.as_ref().map_or(0, <T as BytesIO>::encoded_len)
+    }
+}
+
+impl BytesIO for String {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b2, bytes) = <Vec<u8> as BytesIO>::read(b)?;
+        let t = String::from_utf8(bytes).map_err(|_| BytesReadError::InvalidValue(b))?;
+        Ok((b2, t))
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let n = t.len();
+        let b = <u32 as BytesIO>::write(&(n as u32), b)?;
+        b.get_mut(..n)?.copy_from_slice(t.as_bytes());
+        b.get_mut(n..)
+    }
+    fn encoded_len(t: &Self) -> usize {
+        size_of::<u32>() + t.len()
+    }
 }
 
 macro_rules! num_impl_bytes_io {
@@ -114,6 +177,9 @@ macro_rules! num_impl_bytes_io {
                 u.copy_from_slice(&Self::to_be_bytes(*t));
                 Some(b.get_mut(s..)?)
             }
+            fn encoded_len(_: &Self) -> usize {
+                size_of::<Self>()
+            }
         }
     };
     ($n:ty, $($nn:ty),+ $(,)?) => {
@@ -141,6 +207,10 @@ macro_rules! tuple_impl_bytes_io {
                 )+
                 Some(b)
             }
+            fn encoded_len(t: &Self) -> usize {
+                let ($($t),+ ,) = t;
+                0 $(+ $t::encoded_len($t))+
+            }
         }
     };
     ($tip:ident, $($rest:ident),+) => {
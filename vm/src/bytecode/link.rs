@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{ModuleItem, Program};
+
+use crate::datamodel::{Tuple, Value};
+
+/*
+Modules can refer to each other by position, with ModuleRef, or by name, with
+Import. An Import names a module, and optionally one of the items it exports:
+
+    math        the tuple of the module named `math`
+    math.sqrt   the item that `math` exports as `sqrt`
+
+The linker resolves every Import to the module or item it refers to. An
+export can refer to an Import or ModuleRef item, to pass on something from
+another module, and the linker follows those until it reaches the module or
+item they end up at.
+
+Imports of modules that aren't in the program are looked up in the Externs
+given to `link_with`, such as the modules that a `Loader` has already loaded.
+Those are tuples already, so an import of one of their items gets the value
+that the item has when the program is linked.
+*/
+
+#[derive(Debug)]
+pub enum LinkError {
+    /// two modules have the same name
+    DuplicateModule(String),
+    /// a module exports two items with the same name
+    DuplicateExport { module: usize, name: String },
+    /// an export refers to an item that isn't in its module
+    BadExport { module: usize, name: String },
+    /// the import at `module`, `item` leads to `import`, which refers to a
+    /// module that doesn't exist, or an item that it doesn't export
+    Unresolved {
+        module: usize,
+        item: usize,
+        import: String,
+    },
+    /// the import at `module`, `item` leads back to itself through exports
+    /// of imports
+    Cycle { module: usize, item: usize },
+    /// the ModuleRef at `module`, `item` refers to a module that isn't in the
+    /// program
+    BadModuleRef { module: usize, item: usize },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkError::DuplicateModule(name) => {
+                write!(f, "there is more than one module named `{}`", name)
+            }
+            LinkError::DuplicateExport { module, name } => {
+                write!(f, "module {} exports `{}` more than once", module, name)
+            }
+            LinkError::BadExport { module, name } => write!(
+                f,
+                "module {} exports `{}` as an item that doesn't exist",
+                module, name
+            ),
+            LinkError::Unresolved {
+                module,
+                item,
+                import,
+            } => write!(
+                f,
+                "module {}, item {}: unresolved import `{}`",
+                module, item, import
+            ),
+            LinkError::Cycle { module, item } => {
+                write!(
+                    f,
+                    "module {}, item {}: import refers to itself",
+                    module, item
+                )
+            }
+            LinkError::BadModuleRef { module, item } => write!(
+                f,
+                "module {}, item {}: module ref is out of range",
+                module, item
+            ),
+        }
+    }
+}
+
+/// What an Import resolves to.
+#[derive(Clone, Debug)]
+pub enum Target {
+    Module(usize),
+    /// an item that isn't an Import or ModuleRef
+    Item(usize, usize),
+    /// a module from the Externs, or the value of one of its items
+    Extern(Value),
+}
+
+/// Modules outside of a program, that its imports can refer to.
+pub trait Externs {
+    /// The tuple of the module named `name`, and its exports.
+    fn module(&mut self, name: &str) -> Option<(Tuple, &[(String, u32)])>;
+}
+
+/// Externs without any modules.
+pub struct NoExterns;
+
+impl Externs for NoExterns {
+    fn module(&mut self, _name: &str) -> Option<(Tuple, &[(String, u32)])> {
+        None
+    }
+}
+
+/// An Import at `module`, `item`, and what it resolves to.
+pub struct Link {
+    pub module: usize,
+    pub item: usize,
+    pub target: Target,
+}
+
+impl Program {
+    /// Resolves every Import in the program.
+    pub fn link(&self) -> Result<Vec<Link>, LinkError> {
+        self.link_with(&mut NoExterns)
+    }
+
+    /// Like `link`, but imports of modules that aren't in the program are
+    /// looked up in `externs`.
+    pub fn link_with(&self, externs: &mut dyn Externs) -> Result<Vec<Link>, LinkError> {
+        let mut names = HashMap::new();
+        for (m, module) in self.modules.iter().enumerate() {
+            if let Some(name) = &module.name {
+                if names.insert(name.as_str(), m).is_some() {
+                    return Err(LinkError::DuplicateModule(name.clone()));
+                }
+            }
+            for (i, (name, item)) in module.exports.iter().enumerate() {
+                if module.exports[..i].iter().any(|(n, _)| n == name) {
+                    let name = name.clone();
+                    return Err(LinkError::DuplicateExport { module: m, name });
+                }
+                if *item as usize >= module.items.len() {
+                    let name = name.clone();
+                    return Err(LinkError::BadExport { module: m, name });
+                }
+            }
+        }
+        let mut links = Vec::new();
+        for (m, module) in self.modules.iter().enumerate() {
+            for (i, item) in module.items.iter().enumerate() {
+                match item {
+                    ModuleItem::Import { .. } => {
+                        let target = self.resolve(&names, externs, m, i)?;
+                        links.push(Link {
+                            module: m,
+                            item: i,
+                            target,
+                        });
+                    }
+                    ModuleItem::ModuleRef(r) if *r as usize >= self.modules.len() => {
+                        return Err(LinkError::BadModuleRef { module: m, item: i });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(links)
+    }
+
+    /// Follows the item at `module`, `item` through imports, to the module or
+    /// item that it ends up at.
+    fn resolve(
+        &self,
+        names: &HashMap<&str, usize>,
+        externs: &mut dyn Externs,
+        module: usize,
+        item: usize,
+    ) -> Result<Target, LinkError> {
+        let (mut m, mut i) = (module, item);
+        // a path longer than the number of items has visited one twice
+        let mut steps = self.modules.iter().map(|m| m.items.len()).sum::<usize>();
+        loop {
+            match &self.modules[m].items[i] {
+                ModuleItem::ModuleRef(r) => return Ok(Target::Module(*r as usize)),
+                ModuleItem::Import {
+                    module: name,
+                    name: export,
+                } => {
+                    let unresolved = || LinkError::Unresolved {
+                        module,
+                        item,
+                        import: match export {
+                            Some(export) => format!("{}.{}", name, export),
+                            None => name.clone(),
+                        },
+                    };
+                    let target = match names.get(name.as_str()) {
+                        Some(&target) => target,
+                        None => {
+                            let (tuple, exports) = externs.module(name).ok_or_else(unresolved)?;
+                            let val = match export {
+                                Some(export) => {
+                                    let mut exports = exports.iter();
+                                    let found = exports.find(|(n, _)| n == export);
+                                    let (_, i) = found.ok_or_else(unresolved)?;
+                                    tuple.get(*i as usize).unwrap_or(Value::None)
+                                }
+                                None => tuple.into(),
+                            };
+                            return Ok(Target::Extern(val));
+                        }
+                    };
+                    let export = match export {
+                        Some(export) => export,
+                        None => return Ok(Target::Module(target)),
+                    };
+                    let export = self.modules[target].export(export).ok_or_else(unresolved)?;
+                    if steps == 0 {
+                        return Err(LinkError::Cycle { module, item });
+                    }
+                    steps -= 1;
+                    m = target;
+                    i = export as usize;
+                }
+                _ => return Ok(Target::Item(m, i)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::asm::assemble;
+    use crate::datamodel::Identity;
+
+    /// One extern module, `ext`, that exports its first item as `x`.
+    struct Ext(Tuple, Vec<(String, u32)>);
+
+    impl Ext {
+        fn new() -> Ext {
+            let tuple = Tuple::from_iter(vec![Value::Integer(7)].into_iter());
+            Ext(tuple, vec![("x".into(), 0)])
+        }
+    }
+
+    impl Externs for Ext {
+        fn module(&mut self, name: &str) -> Option<(Tuple, &[(String, u32)])> {
+            match name {
+                "ext" => Some((self.0.clone(), &self.1)),
+                _ => None,
+            }
+        }
+    }
+
+    fn link(src: &str) -> Result<Vec<Link>, LinkError> {
+        assemble(src).unwrap().link_with(&mut Ext::new())
+    }
+
+    fn targets(src: &str) -> Vec<(usize, usize, Target)> {
+        let links = link(src).unwrap();
+        links
+            .into_iter()
+            .map(|l| (l.module, l.item, l.target))
+            .collect()
+    }
+
+    #[test]
+    fn imports_resolve_to_modules_and_items() {
+        let src = "\
+module a
+    literal 1
+    literal 2
+    export two 1
+module b
+    import a
+    import a two
+    moduleref 0
+";
+        let targets = targets(src);
+        assert_eq!(targets.len(), 2);
+        assert!(matches!(targets[0], (1, 0, Target::Module(0))));
+        assert!(matches!(targets[1], (1, 1, Target::Item(0, 1))));
+    }
+
+    #[test]
+    fn exports_of_imports_are_followed() {
+        let src = "\
+module a
+    literal 1
+    export one 0
+module b
+    import a one
+    moduleref 0
+    export one 0
+    export a 1
+module c
+    import b one
+    import b a
+";
+        let targets = targets(src);
+        assert!(matches!(targets[1], (2, 0, Target::Item(0, 0))));
+        assert!(matches!(targets[2], (2, 1, Target::Module(0))));
+    }
+
+    #[test]
+    fn externs_are_used_for_other_modules() {
+        let src = "\
+module a
+    import ext
+    import ext x
+";
+        let mut ext = Ext::new();
+        let links = assemble(src).unwrap().link_with(&mut ext).unwrap();
+        let id = ext.0.identity();
+        assert!(matches!(&links[0].target, Target::Extern(Value::Tuple(t)) if t.identity() == id));
+        assert!(matches!(links[1].target, Target::Extern(Value::Integer(7))));
+    }
+
+    #[test]
+    fn bad_programs_are_rejected() {
+        assert!(matches!(
+            link("module a\nmodule a\n"),
+            Err(LinkError::DuplicateModule(ref name)) if name == "a"
+        ));
+        assert!(matches!(
+            link("module a\n  literal 1\n  export x 0\n  export x 0\n"),
+            Err(LinkError::DuplicateExport { module: 0, ref name }) if name == "x"
+        ));
+        assert!(matches!(
+            link("module\nmodule a\n  literal 1\n  export x 1\n"),
+            Err(LinkError::BadExport { module: 1, ref name }) if name == "x"
+        ));
+        assert!(matches!(
+            link("module a\n  literal 1\n  import a y\n"),
+            Err(LinkError::Unresolved { module: 0, item: 1, ref import }) if import == "a.y"
+        ));
+        assert!(matches!(
+            link("module a\n  import nowhere\n"),
+            Err(LinkError::Unresolved { module: 0, item: 0, ref import }) if import == "nowhere"
+        ));
+        assert!(matches!(
+            link("module a\n  import ext y\n"),
+            Err(LinkError::Unresolved { ref import, .. }) if import == "ext.y"
+        ));
+        assert!(matches!(
+            link("module a\nmodule b\n  moduleref 1\n  moduleref 2\n"),
+            Err(LinkError::BadModuleRef { module: 1, item: 1 })
+        ));
+        let program = assemble("module a\n  moduleref 1\n").unwrap();
+        assert!(matches!(
+            program.into_tuple(),
+            Err(LinkError::BadModuleRef { module: 0, item: 0 })
+        ));
+    }
+
+    #[test]
+    fn import_cycles_are_rejected() {
+        let src = "\
+module a
+    import b x
+    export x 0
+module b
+    import a x
+    export x 0
+";
+        assert!(matches!(
+            link(src),
+            Err(LinkError::Cycle { module: 0, item: 0 })
+        ));
+        assert!(matches!(
+            link("module a\n  import a x\n  export x 0\n"),
+            Err(LinkError::Cycle { module: 0, item: 0 })
+        ));
+    }
+
+    #[test]
+    fn tuples_have_the_imported_values() {
+        let src = "\
+module a
+    literal 5
+    export five 0
+module b
+    import a five
+    import a
+    import ext x
+    moduleref 0
+";
+        let tuple = assemble(src)
+            .unwrap()
+            .into_tuple_with(&mut Ext::new())
+            .unwrap();
+        let a = match tuple.get(0) {
+            Some(Value::Tuple(a)) => a,
+            _ => panic!("not a module"),
+        };
+        let b = match tuple.get(1) {
+            Some(Value::Tuple(b)) => b,
+            _ => panic!("not a module"),
+        };
+        assert!(matches!(b.get(0), Some(Value::Integer(5))));
+        assert!(matches!(b.get(1), Some(Value::Tuple(t)) if t.identity() == a.identity()));
+        assert!(matches!(b.get(2), Some(Value::Integer(7))));
+        assert!(matches!(b.get(3), Some(Value::Tuple(t)) if t.identity() == a.identity()));
+    }
+}
@@ -1,14 +1,24 @@
+pub mod asm;
+mod debug;
+pub mod disasm;
 mod function;
 mod io;
+mod link;
 mod module;
 mod op;
 pub mod ops;
+pub mod pnb;
 mod program;
+mod verify;
 
 pub use io::{BytesIO, BytesReadError, DataIO};
 
-pub use op::{Op, OpAction, OpError, OpType, Operation};
+pub use op::{Op, OpAction, OpError, OpType, Operation, ParseOperands};
 
+pub use debug::{DebugInfo, LocalInfo};
 pub use function::Function;
+pub use link::{Externs, Link, LinkError, NoExterns, Target};
 pub use module::{Module, ModuleItem};
+pub use pnb::LoadError;
 pub use program::Program;
+pub use verify::{FunctionInfo, ItemError, VerifyError};
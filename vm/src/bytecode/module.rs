@@ -1,13 +1,21 @@
 use super::ops::LiteralValue;
 use super::{BytesIO, BytesReadError, Function};
 
-use crate::datamodel::{Buffer, Function as FuncVal, Tuple, Value};
+use crate::datamodel::{Buffer, Tuple, Value};
 
 pub struct Module {
+    /// the name that imports refer to the module by
+    pub name: Option<String>,
     pub items: Vec<ModuleItem>,
+    /// the names that other modules can import items by, with the index of
+    /// each item
+    pub exports: Vec<(String, u32)>,
 }
 
 impl Module {
+    /// Creates the module's tuple. ModuleRef and Import items are left as
+    /// None, and returned with their indices, to be filled in when the rest
+    /// of the program has been created.
     pub fn into_tuple(self) -> (Tuple, Vec<(usize, usize)>) {
         let len = self.items.len();
         let tuple = Tuple::empty(len);
@@ -20,21 +28,42 @@ impl Module {
                     refs.push((i, r as usize));
                     Value::None
                 }
-                ModuleItem::Function(f) => FuncVal::new(tuple.clone(), f.ops).into(),
+                ModuleItem::Import { .. } => Value::None,
+                ModuleItem::Function(f) => f.into_value(tuple.clone()).into(),
             };
             tuple.set(i, val);
         }
         (tuple, refs)
     }
+
+    /// The index of the item exported as `name`.
+    pub fn export(&self, name: &str) -> Option<u32> {
+        let mut exports = self.exports.iter();
+        exports.find(|(n, _)| n == name).map(|&(_, item)| item)
+    }
 }
 
 impl BytesIO for Module {
     fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
         let (b, items) = <Vec<ModuleItem> as BytesIO>::read(b)?;
-        Ok((b, Module { items }))
+        let (b, name) = <Option<String> as BytesIO>::read(b)?;
+        let (b, exports) = <Vec<(String, u32)> as BytesIO>::read(b)?;
+        let module = Module {
+            name,
+            items,
+            exports,
+        };
+        Ok((b, module))
     }
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
-        <Vec<ModuleItem> as BytesIO>::write(&t.items, b)
+        let b = <Vec<ModuleItem> as BytesIO>::write(&t.items, b)?;
+        let b = <Option<String> as BytesIO>::write(&t.name, b)?;
+        <Vec<(String, u32)> as BytesIO>::write(&t.exports, b)
+    }
+    fn encoded_len(t: &Self) -> usize {
+        <Vec<ModuleItem> as BytesIO>::encoded_len(&t.items)
+            + <Option<String> as BytesIO>::encoded_len(&t.name)
+            + <Vec<(String, u32)> as BytesIO>::encoded_len(&t.exports)
     }
 }
 
@@ -43,6 +72,13 @@ pub enum ModuleItem {
     Buffer(Vec<u8>),
     ModuleRef(u32),
     Function(Function),
+    /// The item that `module` exports as `name`, or the module itself if
+    /// `name` is None. The linker resolves it when the program is turned
+    /// into a tuple.
+    Import {
+        module: String,
+        name: Option<String>,
+    },
 }
 
 impl BytesIO for ModuleItem {
@@ -67,6 +103,10 @@ impl BytesIO for ModuleItem {
                 let (b, t) = <Function as BytesIO>::read(b2)?;
                 Ok((b, ModuleItem::Function(t)))
             }
+            4 => {
+                let (b, (module, name)) = <(String, Option<String>) as BytesIO>::read(b2)?;
+                Ok((b, ModuleItem::Import { module, name }))
+            }
             _ => Err(BytesReadError::InvalidValue(b)),
         }
     }
@@ -91,8 +131,26 @@ impl BytesIO for ModuleItem {
                 let b = <u8 as BytesIO>::write(&3, b)?;
                 <Function as BytesIO>::write(t, b)
             }
+            ModuleItem::Import { module, name } => {
+                let b = <u8 as BytesIO>::write(&4, b)?;
+                let b = <String as BytesIO>::write(module, b)?;
+                <Option<String> as BytesIO>::write(name, b)
+            }
         }
     }
+    fn encoded_len(t: &Self) -> usize {
+        let len = match t {
+            ModuleItem::LiteralValue(t) => <LiteralValue as BytesIO>::encoded_len(t),
+            ModuleItem::Buffer(t) => <u32 as BytesIO>::encoded_len(&(t.len() as u32)) + t.len(),
+            ModuleItem::ModuleRef(t) => <u32 as BytesIO>::encoded_len(t),
+            ModuleItem::Function(t) => <Function as BytesIO>::encoded_len(t),
+            ModuleItem::Import { module, name } => {
+                <String as BytesIO>::encoded_len(module)
+                    + <Option<String> as BytesIO>::encoded_len(name)
+            }
+        };
+        <u8 as BytesIO>::encoded_len(&0) + len
+    }
 }
 
 /*
@@ -102,5 +160,6 @@ than hardcoding the ID of each interface/trait, we could have programs fetch the
 ID from the library after it is loaded.
 
 how do want to handle dynamic loading? well, modules are just Tuples, so we don't
-need to implement that here, it will be handled at runtime, not at load time.
+need to implement that here, it is handled at runtime by crate::loader, not at
+load time.
 */
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use super::{BytesIO, BytesReadError};
 
 use crate::CallStack;
@@ -10,6 +12,11 @@ pub trait Operation {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError>;
 }
 
+/// Reads an op from its operands, written the way its Display writes them.
+pub trait ParseOperands: Sized {
+    fn parse_operands(operands: &[&str]) -> Option<Self>;
+}
+
 pub enum OpAction {
     None,
     Jump(i32),
@@ -18,6 +25,7 @@ pub enum OpAction {
     Return(Value),
 }
 
+#[derive(Debug)]
 pub enum OpError {
     StackEmpty,
     LocalRead(u8),
@@ -25,6 +33,31 @@ pub enum OpError {
     IndexWrite(i64),
     IntoType(ValueTryIntoError),
     BadType(ValueType),
+    Overflow,
+    DivideByZero,
+    ViewResize,
+    ZeroStep,
+    UnpackLen { expected: usize, found: usize },
+}
+
+impl fmt::Display for OpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpError::StackEmpty => write!(f, "stack is empty"),
+            OpError::LocalRead(i) => write!(f, "local {} has not been set", i),
+            OpError::IndexRead(i) => write!(f, "cannot read index {}", i),
+            OpError::IndexWrite(i) => write!(f, "cannot write index {}", i),
+            OpError::IntoType(e) => write!(f, "bad operand: {}", e),
+            OpError::BadType(t) => write!(f, "bad operand type {}", t.as_str()),
+            OpError::Overflow => write!(f, "integer overflow"),
+            OpError::DivideByZero => write!(f, "integer division by zero"),
+            OpError::ViewResize => write!(f, "cannot resize a buffer view"),
+            OpError::ZeroStep => write!(f, "range step cannot be zero"),
+            OpError::UnpackLen { expected, found } => {
+                write!(f, "cannot unpack {} items into {} places", found, expected)
+            }
+        }
+    }
 }
 
 impl From<ValueTryIntoError> for OpError {
@@ -34,40 +67,94 @@ impl From<ValueTryIntoError> for OpError {
 }
 
 macro_rules! create_op_type {
-    ($($op:ident),+) => {
+    (@core_name #[$m:meta] $(#[$meta:meta])* $op:ident) => { None };
+    (@core_name $op:ident) => { Some(stringify!($op)) };
+    ($($(#[$meta:meta])* $op:ident),+) => {
+        /// The names of the ops that are always enabled, in order. Optional
+        /// ops are None, whether they are enabled or not.
+        pub(crate) const CORE_OP_NAMES: &[Option<&str>] = &[
+            $(create_op_type!(@core_name $(#[$meta])* $op)),+
+        ];
+
         #[repr(u8)]
         pub enum OpType {
-            $($op),+
+            $($(#[$meta])* $op),+
         }
 
         pub enum Op {
-            $($op($op)),+
+            $($(#[$meta])* $op($op)),+
         }
 
         impl Op {
             pub fn get_type(&self) -> OpType {
                 match self {
                     $(
+                        $(#[$meta])*
                         Op::$op(_) => OpType::$op
                     ),+
                 }
             }
         }
 
+
         impl OpType {
+            pub fn from_name(name: &str) -> Option<OpType> {
+                $(
+                    $(#[$meta])*
+                    {
+                        if name == stringify!($op) {
+                            return Some(OpType::$op);
+                        }
+                    }
+                )+
+                None
+            }
+
             pub fn get_name(&self) -> &'static str {
                 match self {
                     $(
+                        $(#[$meta])*
                         OpType::$op => stringify!($op)
                     ),+
                 }
             }
         }
 
+        impl Op {
+            /// Reads an op of type `ty` from its operands, or returns None
+            /// if they aren't valid for it.
+            pub fn parse(ty: OpType, operands: &[&str]) -> Option<Op> {
+                match ty {
+                    $(
+                        $(#[$meta])*
+                        OpType::$op => <$op as ParseOperands>::parse_operands(operands).map(Op::$op)
+                    ),+
+                }
+            }
+        }
+
+        /// the name of the op, then its operands
+        impl fmt::Display for Op {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let operands = match self {
+                    $(
+                        $(#[$meta])*
+                        Op::$op(op) => op.to_string()
+                    ),+
+                };
+                write!(f, "{}", self.get_type().get_name())?;
+                if !operands.is_empty() {
+                    write!(f, " {}", operands)?;
+                }
+                Ok(())
+            }
+        }
+
         impl Operation for Op {
             fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
                 match self {
                     $(
+                        $(#[$meta])*
                         Op::$op(op) => op.exec(m)
                     ),+
                 }
@@ -79,10 +166,12 @@ macro_rules! create_op_type {
             fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
                 let (b2, n) = <u8 as BytesIO>::read(b)?;
                 $(
+                    $(#[$meta])*
                     const $op: u8 = OpType::$op as u8;
                 )+
                 match n {
                     $(
+                        $(#[$meta])*
                         $op => {
                             let (b, op) = <$op as BytesIO>::read(b2)?;
                             Ok( (b, Op::$op(op)) )
@@ -94,6 +183,7 @@ macro_rules! create_op_type {
             fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
                 match t {
                     $(
+                        $(#[$meta])*
                         Op::$op(op) => {
                             let b = <u8 as BytesIO>::write(&(OpType::$op as u8), b)?;
                             <$op as BytesIO>::write(op, b)
@@ -101,9 +191,19 @@ macro_rules! create_op_type {
                     ),+
                 }
             }
+            fn encoded_len(t: &Self) -> usize {
+                let len = match t {
+                    $(
+                        $(#[$meta])*
+                        Op::$op(op) => <$op as BytesIO>::encoded_len(op)
+                    ),+
+                };
+                <u8 as BytesIO>::encoded_len(&0) + len
+            }
         }
 
         $(
+            $(#[$meta])*
             impl From<$op> for Op {
                 fn from(t: $op) -> Self {
                     Op::$op(t)
@@ -132,5 +232,56 @@ create_op_type!(
     // buffer
     BufferCreate, BufferGetSlice, BufferSetSlice,
     // seq
-    SeqLen, SeqResize, SeqGet, SeqSet, SeqToList, SeqAppend
+    SeqLen, SeqResize, SeqGet, SeqSet, SeqToList, SeqAppend,
+    // new ops are added from here on, so that the others keep their numbers
+    // structural cmp
+    StructEq, StructCmp, StructHash,
+    // checked num and real
+    WrappingAdd, WrappingSub, WrappingMul, SaturatingAdd, SaturatingSub, SaturatingMul,
+    RealToInt,
+    // bool
+    CmpEq, CmpNe, CmpLt, CmpLe, CmpGt, CmpGe, JumpFalse,
+    // buffer
+    BufferRead, BufferWrite, BufferView, BufferMaterialize,
+    // list
+    ListSetSlice, ListInsert, ListRemove, ListSplice, ListReverse, ListSort, ListSortBy, ListFind,
+    // iter, range and seq
+    IterNew, IterNext, RangeCreate, RangeCreateInclusive, SeqUnpack,
+    // optional ops go last, so that the other `OpType`s keep their numbers
+    #[cfg(feature = "bigint")] PromotingAdd,
+    #[cfg(feature = "bigint")] PromotingSub,
+    #[cfg(feature = "bigint")] PromotingMul,
+    #[cfg(feature = "bigint")] BigIntFromBuffer,
+    #[cfg(feature = "bigint")] BigIntToBuffer
 );
+
+impl Op {
+    /// The `dest` of the ops that can jump, which is relative to the op.
+    pub fn jump_dest(&self) -> Option<i32> {
+        match self {
+            Op::Jump(op) => Some(op.dest),
+            Op::JumpZero(op) => Some(op.dest),
+            Op::JumpNeg(op) => Some(op.dest),
+            Op::JumpFalse(op) => Some(op.dest),
+            Op::IterNext(op) => Some(op.dest),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ops_keep_their_numbers() {
+        // compiled programs depend on these
+        assert_eq!(OpType::Add as u8, 0);
+        assert_eq!(OpType::Cmp as u8, 12);
+        assert_eq!(OpType::Jump as u8, 21);
+        assert_eq!(OpType::LiteralCreate as u8, 24);
+        assert_eq!(OpType::BufferSetSlice as u8, 41);
+        assert_eq!(OpType::SeqAppend as u8, 47);
+        assert_eq!(OpType::StructEq as u8, 48);
+    }
+}
@@ -1,8 +1,46 @@
 use std::convert::TryInto;
 
-use crate::datamodel::Buffer;
+#[cfg(feature = "bigint")]
+use crate::datamodel::{BigInt, Value};
+use crate::datamodel::{Buffer, ByteOrder, NumError, NumType};
 
-use super::{CallStack, OpAction, OpError, Operation};
+use super::{index, CallStack, DataIO, OpAction, OpError, Operation};
+
+impl DataIO for ByteOrder {
+    type Target = u8;
+    fn from_bytes(t: u8) -> Option<Self> {
+        Some(match t {
+            0 => ByteOrder::Big,
+            1 => ByteOrder::Little,
+            _ => return None,
+        })
+    }
+    fn into_bytes(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl DataIO for NumType {
+    type Target = u8;
+    fn from_bytes(t: u8) -> Option<Self> {
+        Some(match t {
+            0 => NumType::U8,
+            1 => NumType::U16,
+            2 => NumType::U32,
+            3 => NumType::U64,
+            4 => NumType::I8,
+            5 => NumType::I16,
+            6 => NumType::I32,
+            7 => NumType::I64,
+            8 => NumType::F32,
+            9 => NumType::F64,
+            _ => return None,
+        })
+    }
+    fn into_bytes(&self) -> u8 {
+        *self as u8
+    }
+}
 
 new_op_empty!(BufferCreate);
 impl Operation for BufferCreate {
@@ -22,13 +60,36 @@ impl Operation for BufferGetSlice {
         let a: i64 = m.pop()?.try_into()?;
         let buffer: Buffer = m.pop()?.try_into()?;
         let slice = buffer
-            .get_slice(a as usize, b as usize)
+            .get_slice(index(a, OpError::IndexRead)?, index(b, OpError::IndexRead)?)
             .ok_or(OpError::IndexRead(b))?;
         m.push(slice.into());
         Ok(OpAction::None)
     }
 }
 
+new_op_empty!(BufferView);
+impl Operation for BufferView {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let b: i64 = m.pop()?.try_into()?;
+        let a: i64 = m.pop()?.try_into()?;
+        let buffer: Buffer = m.pop()?.try_into()?;
+        let view = buffer
+            .view(index(a, OpError::IndexRead)?, index(b, OpError::IndexRead)?)
+            .ok_or(OpError::IndexRead(b))?;
+        m.push(view.into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(BufferMaterialize);
+impl Operation for BufferMaterialize {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let buffer: Buffer = m.pop()?.try_into()?;
+        m.push(buffer.materialize().into());
+        Ok(OpAction::None)
+    }
+}
+
 new_op_empty!(BufferSetSlice);
 impl Operation for BufferSetSlice {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
@@ -37,9 +98,292 @@ impl Operation for BufferSetSlice {
         let src_offset: i64 = m.pop()?.try_into()?;
         let src: Buffer = m.pop()?.try_into()?;
         let buffer: Buffer = m.pop()?.try_into()?;
+        let src_offset = index(src_offset, OpError::IndexRead)?;
+        let offset = index(offset, OpError::IndexWrite)?;
+        let len = index(len, OpError::IndexWrite)?;
+        buffer
+            .set_slice(&src, src_offset, offset, len)
+            .ok_or(OpError::IndexWrite(len as i64))?;
+        Ok(OpAction::None)
+    }
+}
+
+new_op! {
+    pub struct BufferRead {
+        pub ty: NumType,
+        pub order: ByteOrder,
+    }
+}
+
+impl Operation for BufferRead {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let offset: i64 = m.pop()?.try_into()?;
+        let buffer: Buffer = m.pop()?.try_into()?;
+        let val = buffer
+            .read_num(index(offset, OpError::IndexRead)?, self.ty, self.order)
+            .map_err(|e| num_error(e, OpError::IndexRead(offset)))?;
+        m.push(val);
+        Ok(OpAction::None)
+    }
+}
+
+new_op! {
+    pub struct BufferWrite {
+        pub ty: NumType,
+        pub order: ByteOrder,
+    }
+}
+
+impl Operation for BufferWrite {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        let offset: i64 = m.pop()?.try_into()?;
+        let buffer: Buffer = m.pop()?.try_into()?;
         buffer
-            .set_slice(&src, src_offset as usize, offset as usize, len as usize)
-            .ok_or(OpError::IndexWrite(len))?;
+            .write_num(
+                index(offset, OpError::IndexWrite)?,
+                self.ty,
+                self.order,
+                &val,
+            )
+            .map_err(|e| num_error(e, OpError::IndexWrite(offset)))?;
+        Ok(OpAction::None)
+    }
+}
+
+fn num_error(e: NumError, out_of_bounds: OpError) -> OpError {
+    match e {
+        NumError::OutOfBounds => out_of_bounds,
+        NumError::Overflow => OpError::Overflow,
+        NumError::BadType(t) => OpError::BadType(t),
+    }
+}
+
+#[cfg(feature = "bigint")]
+new_op! {
+    /// Reads a whole buffer as a two's complement integer.
+    pub struct BigIntFromBuffer {
+        pub order: ByteOrder,
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Operation for BigIntFromBuffer {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let buffer: Buffer = m.pop()?.try_into()?;
+        let bytes = buffer.as_slice();
+        let val = match self.order {
+            ByteOrder::Big => BigInt::from_signed_bytes_be(&bytes),
+            ByteOrder::Little => BigInt::from_signed_bytes_le(&bytes),
+        };
+        m.push(Value::from_bigint(val));
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(feature = "bigint")]
+new_op! {
+    /// Writes an Integer or BigInt to a new buffer as a two's complement
+    /// integer, using as few bytes as possible.
+    pub struct BigIntToBuffer {
+        pub order: ByteOrder,
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Operation for BigIntToBuffer {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        let val = match val.to_bigint() {
+            Some(val) => val,
+            None => return Err(OpError::BadType(val.get_type())),
+        };
+        let bytes = match self.order {
+            ByteOrder::Big => val.to_signed_bytes_be(),
+            ByteOrder::Little => val.to_signed_bytes_le(),
+        };
+        m.push(Buffer::new(bytes).into());
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{exec, exec_pop};
+    use super::*;
+    use crate::datamodel::{Value, ValueType};
+
+    #[test]
+    fn slices_check_bounds() {
+        let buffer = || Buffer::new(vec![1, 2, 3]).into();
+        let int = Value::Integer;
+        let t = exec(BufferGetSlice, vec![buffer(), int(-1), int(2)]);
+        assert!(matches!(t, Err(OpError::IndexRead(-1))));
+        let t = exec(BufferGetSlice, vec![buffer(), int(2), int(1)]);
+        assert!(matches!(t, Err(OpError::IndexRead(1))));
+        let args = vec![buffer(), buffer(), int(0), int(-1), int(1)];
+        assert!(matches!(
+            exec(BufferSetSlice, args),
+            Err(OpError::IndexWrite(-1))
+        ));
+        let args = vec![buffer(), buffer(), int(0), int(1), int(i64::MAX)];
+        assert!(matches!(
+            exec(BufferSetSlice, args),
+            Err(OpError::IndexWrite(_))
+        ));
+    }
+
+    fn read(ty: NumType, order: ByteOrder, b: &Buffer, offset: i64) -> Result<Value, OpError> {
+        let args = vec![b.clone().into(), Value::Integer(offset)];
+        exec_pop(BufferRead { ty, order }, args)
+    }
+
+    fn write(
+        ty: NumType,
+        order: ByteOrder,
+        b: &Buffer,
+        offset: i64,
+        val: Value,
+    ) -> Result<(), OpError> {
+        let args = vec![b.clone().into(), Value::Integer(offset), val];
+        exec(BufferWrite { ty, order }, args).map(|_| ())
+    }
+
+    #[test]
+    fn numbers_are_written_in_order() {
+        let b = Buffer::new(vec![0; 6]);
+        write(
+            NumType::U32,
+            ByteOrder::Big,
+            &b,
+            1,
+            Value::Integer(0x0102_0304),
+        )
+        .unwrap();
+        assert_eq!(&*b.as_slice(), &[0, 1, 2, 3, 4, 0]);
+        write(
+            NumType::U32,
+            ByteOrder::Little,
+            &b,
+            1,
+            Value::Integer(0x0102_0304),
+        )
+        .unwrap();
+        assert_eq!(&*b.as_slice(), &[0, 4, 3, 2, 1, 0]);
+        let t = read(NumType::U16, ByteOrder::Big, &b, 1);
+        assert!(matches!(t, Ok(Value::Integer(0x0403))));
+        let t = read(NumType::U16, ByteOrder::Little, &b, 1);
+        assert!(matches!(t, Ok(Value::Integer(0x0304))));
+    }
+
+    #[test]
+    fn every_type_round_trips() {
+        let ints = [
+            (NumType::U8, 255),
+            (NumType::U16, 65535),
+            (NumType::U32, 0xffff_ffff),
+            (NumType::U64, i64::MAX),
+            (NumType::I8, -128),
+            (NumType::I16, -32768),
+            (NumType::I32, -1 << 31),
+            (NumType::I64, i64::MIN),
+        ];
+        for &(ty, t) in ints.iter() {
+            for &order in [ByteOrder::Big, ByteOrder::Little].iter() {
+                let b = Buffer::new(vec![0; 8]);
+                write(ty, order, &b, 0, Value::Integer(t)).unwrap();
+                let read = read(ty, order, &b, 0);
+                assert!(
+                    matches!(read, Ok(Value::Integer(r)) if r == t),
+                    "{} {}",
+                    ty,
+                    order
+                );
+                assert!(b.as_slice()[ty.size()..].iter().all(|&t| t == 0));
+            }
+        }
+        for &ty in [NumType::F32, NumType::F64].iter() {
+            let b = Buffer::new(vec![0; 8]);
+            write(ty, ByteOrder::Little, &b, 0, Value::Real(-1.5)).unwrap();
+            let t = read(ty, ByteOrder::Little, &b, 0);
+            assert!(matches!(t, Ok(Value::Real(r)) if r == -1.5));
+        }
+        let b = Buffer::new(vec![0xff; 8]);
+        assert!(matches!(
+            read(NumType::I8, ByteOrder::Big, &b, 0),
+            Ok(Value::Integer(-1))
+        ));
+        assert!(matches!(
+            read(NumType::I64, ByteOrder::Big, &b, 0),
+            Ok(Value::Integer(-1))
+        ));
+    }
+
+    #[test]
+    fn u64s_above_integer_max() {
+        let b = Buffer::new(vec![0xff; 8]);
+        let t = read(NumType::U64, ByteOrder::Big, &b, 0);
+        #[cfg(not(feature = "bigint"))]
+        assert!(matches!(t, Err(OpError::Overflow)));
+        #[cfg(feature = "bigint")]
+        assert!(matches!(t, Ok(Value::BigInt(t)) if t == crate::datamodel::BigInt::from(u64::MAX)));
+    }
+
+    #[test]
+    fn reads_and_writes_check_bounds() {
+        let b = Buffer::new(vec![0; 4]);
+        let t = read(NumType::U32, ByteOrder::Big, &b, 1);
+        assert!(matches!(t, Err(OpError::IndexRead(1))));
+        let t = read(NumType::U8, ByteOrder::Big, &b, -1);
+        assert!(matches!(t, Err(OpError::IndexRead(-1))));
+        let t = read(NumType::U8, ByteOrder::Big, &b, 4);
+        assert!(matches!(t, Err(OpError::IndexRead(4))));
+        let t = write(NumType::U16, ByteOrder::Big, &b, 3, Value::Integer(1));
+        assert!(matches!(t, Err(OpError::IndexWrite(3))));
+        let t = write(
+            NumType::U16,
+            ByteOrder::Big,
+            &b,
+            i64::MIN,
+            Value::Integer(1),
+        );
+        assert!(matches!(t, Err(OpError::IndexWrite(i64::MIN))));
+        assert_eq!(&*b.as_slice(), &[0; 4]);
+    }
+
+    #[test]
+    fn values_must_fit_the_type() {
+        let b = Buffer::new(vec![0; 8]);
+        let t = write(NumType::U8, ByteOrder::Big, &b, 0, Value::Integer(256));
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = write(NumType::U64, ByteOrder::Big, &b, 0, Value::Integer(-1));
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = write(NumType::I32, ByteOrder::Big, &b, 0, Value::Real(1.0));
+        assert!(matches!(t, Err(OpError::BadType(ValueType::Real))));
+        // Integers can be written as Reals
+        write(NumType::F64, ByteOrder::Big, &b, 0, Value::Integer(3)).unwrap();
+        assert!(
+            matches!(read(NumType::F64, ByteOrder::Big, &b, 0), Ok(Value::Real(r)) if r == 3.0)
+        );
+    }
+
+    #[test]
+    fn types_and_orders_encode_as_bytes() {
+        for t in 0..=255u8 {
+            match NumType::from_bytes(t) {
+                Some(ty) => {
+                    assert!(t < 10);
+                    assert_eq!(ty.into_bytes(), t);
+                    assert_eq!(ty.to_string().parse::<NumType>(), Ok(ty));
+                }
+                None => assert!(t >= 10),
+            }
+            match ByteOrder::from_bytes(t) {
+                Some(order) => assert_eq!(order.into_bytes(), t),
+                None => assert!(t >= 2),
+            }
+        }
+        assert_eq!(NumType::from_bytes(9), Some(NumType::F64));
+        assert_eq!(ByteOrder::from_bytes(1), Some(ByteOrder::Little));
+    }
+}
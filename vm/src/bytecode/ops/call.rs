@@ -4,7 +4,7 @@ use super::{CallStack, OpAction, OpError, Operation};
 
 new_op! {
     pub struct Call {
-        args: u8,
+        pub args: u8,
     }
 }
 
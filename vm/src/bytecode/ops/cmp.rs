@@ -1,48 +1,183 @@
 use std::cmp::Ordering;
 use std::convert::TryInto;
 
+#[cfg(feature = "bigint")]
+use num_traits::FromPrimitive;
+
+#[cfg(feature = "bigint")]
+use crate::datamodel::BigInt;
 use crate::datamodel::{
-    Buffer, Function, Identity, List, NativeFn, Table, Unknown, Value, ValueTryIntoError, ValueType,
+    Buffer, Function, Identity, Integer, Iter, List, NativeFn, Real, Table, Unknown, Value,
+    ValueTryIntoError, ValueType,
 };
 
 use super::{CallStack, OpAction, OpError, Operation};
 
+/// Compares numbers by value and everything else by identity. Values which
+/// are neither equal nor ordered, like NaN or two different tables, compare
+/// as None, and values of types that can't be compared with each other are
+/// an error.
+fn compare(lhs: Value, rhs: Value) -> Result<Option<Ordering>, OpError> {
+    Ok(match lhs {
+        Value::None => match rhs {
+            Value::None => Some(Ordering::Equal),
+            _ => Some(Ordering::Less),
+        },
+        Value::Bool(lhs) => Some(lhs.cmp(&rhs.try_into()?)),
+        Value::Integer(lhs) => match rhs {
+            Value::Real(rhs) => cmp_int_real(lhs, rhs),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(rhs) => Some(BigInt::from(lhs).cmp(&rhs)),
+            _ => Some(lhs.cmp(&rhs.try_into()?)),
+        },
+        Value::Real(lhs) => match rhs {
+            Value::Integer(rhs) => cmp_int_real(rhs, lhs).map(Ordering::reverse),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(rhs) => cmp_big_real(&rhs, lhs).map(Ordering::reverse),
+            _ => lhs.partial_cmp(&rhs.try_into()?),
+        },
+        Value::Tuple(lhs) => identical(cmp_tuple(lhs.identity(), rhs)?),
+        Value::TupleWeak(lhs) => identical(cmp_tuple(lhs.identity(), rhs)?),
+        Value::Table(lhs) => {
+            identical(lhs.identity() == TryInto::<Table>::try_into(rhs)?.identity())
+        }
+        Value::List(lhs) => identical(lhs.identity() == TryInto::<List>::try_into(rhs)?.identity()),
+        Value::Buffer(lhs) => {
+            identical(lhs.identity() == TryInto::<Buffer>::try_into(rhs)?.identity())
+        }
+        Value::Function(lhs) => {
+            identical(lhs.identity() == TryInto::<Function>::try_into(rhs)?.identity())
+        }
+        Value::NativeFn(lhs) => identical(lhs == TryInto::<NativeFn>::try_into(rhs)?),
+        Value::Unknown(lhs) => {
+            identical(lhs.identity() == TryInto::<Unknown>::try_into(rhs)?.identity())
+        }
+        Value::Range(lhs) => identical(lhs == rhs.try_into()?),
+        Value::Iter(lhs) => identical(lhs.identity() == TryInto::<Iter>::try_into(rhs)?.identity()),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(lhs) => match rhs {
+            Value::Integer(rhs) => Some(lhs.cmp(&BigInt::from(rhs))),
+            Value::Real(rhs) => cmp_big_real(&lhs, rhs),
+            _ => Some(lhs.cmp(&rhs.try_into()?)),
+        },
+    })
+}
+
+fn identical(same: bool) -> Option<Ordering> {
+    match same {
+        true => Some(Ordering::Equal),
+        false => None,
+    }
+}
+
 new_op_empty!(Cmp);
 impl Operation for Cmp {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
         let rhs = m.pop()?;
         let lhs = m.pop()?;
         let result = match lhs {
-            Value::None => match rhs {
-                Value::None => Ordering::Equal.into(),
-                _ => Ordering::Less.into(),
-            },
-            Value::Integer(lhs) => lhs.cmp(&rhs.try_into()?).into(),
-            Value::Real(lhs) => lhs.partial_cmp(&rhs.try_into()?).into(),
-            Value::Tuple(lhs) => cmp_tuple(lhs.identity(), rhs)?.into(),
-            Value::TupleWeak(lhs) => cmp_tuple(lhs.identity(), rhs)?.into(),
-            Value::Table(lhs) => {
-                (lhs.identity() == TryInto::<Table>::try_into(rhs)?.identity()).into()
-            }
-            Value::List(lhs) => {
-                (lhs.identity() == TryInto::<List>::try_into(rhs)?.identity()).into()
-            }
-            Value::Buffer(lhs) => {
-                (lhs.identity() == TryInto::<Buffer>::try_into(rhs)?.identity()).into()
-            }
-            Value::Function(lhs) => {
-                (lhs.identity() == TryInto::<Function>::try_into(rhs)?.identity()).into()
-            }
-            Value::NativeFn(lhs) => (lhs == TryInto::<NativeFn>::try_into(rhs)?).into(),
-            Value::Unknown(lhs) => {
-                (lhs.identity() == TryInto::<Unknown>::try_into(rhs)?.identity()).into()
+            // values compared by identity push 1 if they are the same value
+            // and 0 if not, as they always have, rather than an Ordering
+            Value::Tuple(_)
+            | Value::TupleWeak(_)
+            | Value::Table(_)
+            | Value::List(_)
+            | Value::Buffer(_)
+            | Value::Function(_)
+            | Value::NativeFn(_)
+            | Value::Unknown(_)
+            | Value::Range(_)
+            | Value::Iter(_) => {
+                let same = compare(lhs, rhs)? == Some(Ordering::Equal);
+                Value::Integer(same as Integer)
             }
+            _ => compare(lhs, rhs)?.into(),
         };
         m.push(result);
         Ok(OpAction::None)
     }
 }
 
+macro_rules! impl_cmp_op {
+    ($name:ident, $($ord:ident)|+) => {
+        new_op_empty!($name);
+        impl Operation for $name {
+            fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+                let rhs = m.pop()?;
+                let lhs = m.pop()?;
+                let result = matches!(compare(lhs, rhs)?, $(Some(Ordering::$ord))|+);
+                m.push(result.into());
+                Ok(OpAction::None)
+            }
+        }
+    };
+}
+
+// unlike Cmp, these push a Bool, and unordered values are simply not equal
+impl_cmp_op!(CmpLt, Less);
+impl_cmp_op!(CmpLe, Less | Equal);
+impl_cmp_op!(CmpGt, Greater);
+impl_cmp_op!(CmpGe, Greater | Equal);
+
+/// Like `compare`, but values of types that can't be compared with each
+/// other are just not equal, like they are in structural equality.
+fn equal(lhs: Value, rhs: Value) -> bool {
+    matches!(compare(lhs, rhs), Ok(Some(Ordering::Equal)))
+}
+
+new_op_empty!(CmpEq);
+impl Operation for CmpEq {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let rhs = m.pop()?;
+        let lhs = m.pop()?;
+        m.push(equal(lhs, rhs).into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(CmpNe);
+impl Operation for CmpNe {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let rhs = m.pop()?;
+        let lhs = m.pop()?;
+        m.push((!equal(lhs, rhs)).into());
+        Ok(OpAction::None)
+    }
+}
+
+/// Compares exactly, without rounding `int` to the nearest Real first.
+fn cmp_int_real(int: Integer, real: Real) -> Option<Ordering> {
+    // the bounds are -2^63 and 2^63, which are exact as Reals
+    if real.is_nan() {
+        return None;
+    } else if real >= -(Integer::MIN as Real) {
+        return Some(Ordering::Less);
+    } else if real < Integer::MIN as Real {
+        return Some(Ordering::Greater);
+    }
+    // within the bounds, the integer part of `real` converts exactly
+    let trunc = real.trunc();
+    match int.cmp(&(trunc as Integer)) {
+        Ordering::Equal => 0.0.partial_cmp(&(real - trunc)),
+        ord => Some(ord),
+    }
+}
+
+/// Compares exactly, like `cmp_int_real`.
+#[cfg(feature = "bigint")]
+fn cmp_big_real(big: &BigInt, real: Real) -> Option<Ordering> {
+    if real.is_nan() {
+        return None;
+    } else if real.is_infinite() {
+        return 0.0.partial_cmp(&real);
+    }
+    let trunc = real.trunc();
+    match big.cmp(&BigInt::from_f64(trunc)?) {
+        Ordering::Equal => 0.0.partial_cmp(&(real - trunc)),
+        ord => Some(ord),
+    }
+}
+
 fn cmp_tuple(lhs: usize, rhs: Value) -> Result<bool, ValueTryIntoError> {
     let rhs = match rhs {
         Value::Tuple(rhs) => rhs.identity(),
@@ -57,6 +192,35 @@ fn cmp_tuple(lhs: usize, rhs: Value) -> Result<bool, ValueTryIntoError> {
     Ok(lhs == rhs)
 }
 
+new_op_empty!(StructEq);
+impl Operation for StructEq {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let rhs = m.pop()?;
+        let lhs = m.pop()?;
+        m.push(lhs.structural_eq(&rhs).into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(StructCmp);
+impl Operation for StructCmp {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let rhs = m.pop()?;
+        let lhs = m.pop()?;
+        m.push(lhs.total_cmp(&rhs).into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(StructHash);
+impl Operation for StructHash {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        m.push((val.structural_hash() as i64).into());
+        Ok(OpAction::None)
+    }
+}
+
 new_op_empty!(GetType);
 impl Operation for GetType {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
@@ -66,3 +230,78 @@ impl Operation for GetType {
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec_pop;
+    use super::*;
+
+    #[test]
+    fn different_types_are_not_equal() {
+        let buffer = || Buffer::new(vec![1]).into();
+        let t = exec_pop(CmpEq, vec![Value::Integer(1), buffer()]).unwrap();
+        assert!(matches!(t, Value::Bool(false)));
+        let t = exec_pop(CmpNe, vec![Value::Integer(1), buffer()]).unwrap();
+        assert!(matches!(t, Value::Bool(true)));
+        let t = exec_pop(CmpEq, vec![Value::Integer(1), Value::Real(1.0)]).unwrap();
+        assert!(matches!(t, Value::Bool(true)));
+        assert!(exec_pop(CmpLt, vec![Value::Integer(1), buffer()]).is_err());
+    }
+
+    #[test]
+    fn cmp_of_identities_pushes_whether_they_are_the_same() {
+        let list = List::empty();
+        let t = exec_pop(Cmp, vec![list.clone().into(), list.into()]).unwrap();
+        assert!(matches!(t, Value::Integer(1)));
+        let t = exec_pop(Cmp, vec![List::empty().into(), List::empty().into()]).unwrap();
+        assert!(matches!(t, Value::Integer(0)));
+        let t = exec_pop(Cmp, vec![Value::Integer(1), Value::Integer(2)]).unwrap();
+        assert!(matches!(t, Value::Integer(-1)));
+    }
+
+    #[test]
+    fn integers_and_reals_compare_exactly() {
+        let big = 1 << 53;
+        // 2^53 + 1 is rounded to 2^53 as a Real
+        let t = exec_pop(
+            CmpEq,
+            vec![Value::Integer(big + 1), Value::Real(big as Real)],
+        );
+        assert!(matches!(t, Ok(Value::Bool(false))));
+        let t = exec_pop(Cmp, vec![Value::Integer(big + 1), Value::Real(big as Real)]);
+        assert!(matches!(t, Ok(Value::Integer(1))));
+        let t = exec_pop(
+            CmpLt,
+            vec![Value::Real(big as Real), Value::Integer(big + 1)],
+        );
+        assert!(matches!(t, Ok(Value::Bool(true))));
+        let t = exec_pop(CmpEq, vec![Value::Integer(big), Value::Real(big as Real)]);
+        assert!(matches!(t, Ok(Value::Bool(true))));
+        // Integer::MAX is rounded up to 2^63 as a Real
+        let t = exec_pop(
+            CmpLt,
+            vec![
+                Value::Integer(Integer::MAX),
+                Value::Real(Integer::MAX as Real),
+            ],
+        );
+        assert!(matches!(t, Ok(Value::Bool(true))));
+        let t = exec_pop(CmpGt, vec![Value::Integer(0), Value::Real(-0.5)]);
+        assert!(matches!(t, Ok(Value::Bool(true))));
+    }
+
+    #[test]
+    fn nan_is_unordered() {
+        let nan = || Value::Real(Real::NAN);
+        let t = exec_pop(Cmp, vec![Value::Integer(1), nan()]);
+        assert!(matches!(t, Ok(Value::None)));
+        let t = exec_pop(CmpEq, vec![nan(), nan()]);
+        assert!(matches!(t, Ok(Value::Bool(false))));
+        let t = exec_pop(CmpNe, vec![nan(), Value::Integer(0)]);
+        assert!(matches!(t, Ok(Value::Bool(true))));
+        let t = exec_pop(CmpLt, vec![nan(), Value::Real(1.0)]);
+        assert!(matches!(t, Ok(Value::Bool(false))));
+        let t = exec_pop(CmpGe, vec![nan(), Value::Real(1.0)]);
+        assert!(matches!(t, Ok(Value::Bool(false))));
+    }
+}
@@ -1,17 +1,50 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
-use crate::datamodel::Integer;
+#[cfg(feature = "bigint")]
+use crate::datamodel::BigInt;
+use crate::datamodel::{Bool, Integer, Value};
 
 use super::{CallStack, OpAction, OpError, Operation};
 
 macro_rules! impl_int_op {
-    ($name:ident, $e:expr) => {
+    ($name:ident, $op:tt) => {
+        new_op_empty!($name);
+        impl Operation for $name {
+            fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+                let rhs = m.pop()?;
+                let lhs = m.pop()?;
+                let result = match operands(lhs, rhs)? {
+                    Operands::Bool(lhs, rhs) => (lhs $op rhs).into(),
+                    Operands::Integer(lhs, rhs) => (lhs $op rhs).into(),
+                    #[cfg(feature = "bigint")]
+                    Operands::BigInt(lhs, rhs) => Value::from_bigint(lhs $op rhs),
+                };
+                m.push(result);
+                Ok(OpAction::None)
+            }
+        }
+    };
+}
+
+macro_rules! impl_shift_op {
+    ($name:ident, $int:expr, $big:expr) => {
         new_op_empty!($name);
         impl Operation for $name {
             fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
                 let rhs: Integer = m.pop()?.try_into()?;
-                let lhs: Integer = m.pop()?.try_into()?;
-                let result = $e(lhs, rhs).into();
+                let rhs = u32::try_from(rhs).map_err(|_| OpError::Overflow)?;
+                let result = match m.pop()? {
+                    #[cfg(feature = "bigint")]
+                    Value::BigInt(lhs) => {
+                        let result: Result<BigInt, OpError> = $big(lhs, rhs);
+                        Value::from_bigint(result?)
+                    }
+                    lhs => {
+                        let lhs: Integer = lhs.try_into()?;
+                        let result: Option<Integer> = $int(lhs, rhs);
+                        result.ok_or(OpError::Overflow)?.into()
+                    }
+                };
                 m.push(result);
                 Ok(OpAction::None)
             }
@@ -19,17 +52,116 @@ macro_rules! impl_int_op {
     };
 }
 
-impl_int_op!(Shl, |lhs, rhs| lhs << rhs);
-impl_int_op!(Shr, |lhs, rhs| lhs >> rhs);
-impl_int_op!(And, |lhs, rhs| lhs & rhs);
-impl_int_op!(Or, |lhs, rhs| lhs | rhs);
-impl_int_op!(Xor, |lhs, rhs| lhs ^ rhs);
+enum Operands {
+    Bool(Bool, Bool),
+    Integer(Integer, Integer),
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt, BigInt),
+}
+
+/// Integer operands are promoted to BigInt if the other operand is a BigInt.
+/// Bools can only be combined with Bools.
+fn operands(lhs: Value, rhs: Value) -> Result<Operands, OpError> {
+    Ok(match (lhs, rhs) {
+        (Value::Bool(l), r) => Operands::Bool(l, r.try_into()?),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(l), Value::BigInt(r)) => Operands::BigInt(l, r),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(l), r) => Operands::BigInt(l, TryInto::<Integer>::try_into(r)?.into()),
+        #[cfg(feature = "bigint")]
+        (l, Value::BigInt(r)) => Operands::BigInt(TryInto::<Integer>::try_into(l)?.into(), r),
+        (l, r) => Operands::Integer(l.try_into()?, r.try_into()?),
+    })
+}
+
+/// The most bits that a BigInt shifted left can have, so that one op can't
+/// allocate more than 128KiB.
+#[cfg(feature = "bigint")]
+const MAX_SHL_BITS: u64 = 1 << 20;
+
+#[cfg(feature = "bigint")]
+fn big_shl(lhs: BigInt, rhs: u32) -> Result<BigInt, OpError> {
+    if lhs.bits() + rhs as u64 > MAX_SHL_BITS {
+        return Err(OpError::Overflow);
+    }
+    Ok(lhs << rhs)
+}
+
+#[cfg(feature = "bigint")]
+fn big_shr(lhs: BigInt, rhs: u32) -> Result<BigInt, OpError> {
+    Ok(lhs >> rhs)
+}
+
+// shift amounts outside of `0..64` are an error, except that BigInts can be
+// shifted right by any amount that fits in a u32, and left as long as the
+// result has at most MAX_SHL_BITS bits
+impl_shift_op!(Shl, Integer::checked_shl, big_shl);
+impl_shift_op!(Shr, Integer::checked_shr, big_shr);
+impl_int_op!(And, &);
+impl_int_op!(Or, |);
+impl_int_op!(Xor, ^);
 
 new_op_empty!(Not);
 impl Operation for Not {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
-        let val: Integer = m.pop()?.try_into()?;
-        m.push((!val).into());
+        let val = match m.pop()? {
+            Value::Bool(val) => (!val).into(),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(val) => Value::from_bigint(!val),
+            val => {
+                let val: Integer = val.try_into()?;
+                (!val).into()
+            }
+        };
+        m.push(val);
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec_pop;
+    use super::*;
+
+    #[test]
+    fn shifts_out_of_range_are_errors() {
+        let t = exec_pop(Shl, vec![Value::Integer(1), Value::Integer(63)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MIN))));
+        for rhs in [64, -1].iter() {
+            let t = exec_pop(Shl, vec![Value::Integer(1), Value::Integer(*rhs)]);
+            assert!(matches!(t, Err(OpError::Overflow)));
+            let t = exec_pop(Shr, vec![Value::Integer(1), Value::Integer(*rhs)]);
+            assert!(matches!(t, Err(OpError::Overflow)));
+        }
+    }
+
+    #[test]
+    fn bools_only_combine_with_bools() {
+        let t = exec_pop(Xor, vec![Value::Bool(true), Value::Bool(true)]);
+        assert!(matches!(t, Ok(Value::Bool(false))));
+        assert!(exec_pop(And, vec![Value::Bool(true), Value::Integer(1)]).is_err());
+        assert!(exec_pop(And, vec![Value::Integer(1), Value::Bool(true)]).is_err());
+        let t = exec_pop(Not, vec![Value::Integer(0)]);
+        assert!(matches!(t, Ok(Value::Integer(-1))));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_shifts_are_bounded() {
+        let one = || Value::BigInt(BigInt::from(1) << 64);
+        let t = exec_pop(Shl, vec![one(), Value::Integer(1000)]);
+        assert!(matches!(t, Ok(Value::BigInt(t)) if t.bits() == 1065));
+        let most = (MAX_SHL_BITS - 65) as Integer;
+        let t = exec_pop(Shl, vec![one(), Value::Integer(most)]);
+        assert!(matches!(t, Ok(Value::BigInt(t)) if t.bits() == MAX_SHL_BITS));
+        let t = exec_pop(Shl, vec![one(), Value::Integer(most + 1)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Shl, vec![one(), Value::Integer(u32::MAX as Integer)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        // shifting right only makes them smaller
+        let t = exec_pop(Shr, vec![one(), Value::Integer(u32::MAX as Integer)]);
+        assert!(matches!(t, Ok(Value::Integer(0))));
+        let t = exec_pop(Shr, vec![one(), Value::Integer(60)]);
+        assert!(matches!(t, Ok(Value::Integer(16))));
+    }
+}
@@ -0,0 +1,165 @@
+use std::convert::TryInto;
+
+use crate::datamodel::{Integer, Iter, Range};
+
+use super::{CallStack, OpAction, OpError, Operation};
+
+new_op_empty!(IterNew);
+impl Operation for IterNew {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        let iter = Iter::new(&val).ok_or_else(|| OpError::BadType(val.get_type()))?;
+        m.push(iter.into());
+        Ok(OpAction::None)
+    }
+}
+
+fn range_create(m: &mut CallStack, inclusive: bool) -> Result<OpAction, OpError> {
+    let step: Integer = m.pop()?.try_into()?;
+    let end: Integer = m.pop()?.try_into()?;
+    let start: Integer = m.pop()?.try_into()?;
+    let range = Range::new(start, end, step, inclusive).ok_or(OpError::ZeroStep)?;
+    m.push(range.into());
+    Ok(OpAction::None)
+}
+
+new_op_empty!(RangeCreate);
+impl Operation for RangeCreate {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        range_create(m, false)
+    }
+}
+
+new_op_empty!(RangeCreateInclusive);
+impl Operation for RangeCreateInclusive {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        range_create(m, true)
+    }
+}
+
+new_op! {
+    /// Pops an Iter, and pushes its next item, or jumps if it has none left.
+    pub struct IterNext {
+        pub dest: i32,
+    }
+}
+
+impl Operation for IterNext {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let iter: Iter = m.pop()?.try_into()?;
+        match iter.next() {
+            Some(val) => {
+                m.push(val);
+                Ok(OpAction::None)
+            }
+            None => Ok(OpAction::Jump(self.dest)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec_pop;
+    use super::*;
+    use crate::datamodel::{Buffer, List, Table, Tuple, Value, ValueType};
+
+    /// Runs IterNext on `iter` until it jumps, and returns the items.
+    fn items(iter: Value) -> Vec<Value> {
+        let mut items = Vec::new();
+        let mut m = CallStack::new();
+        loop {
+            m.push(iter.clone());
+            match IterNext::new(5).exec(&mut m).unwrap() {
+                OpAction::None => items.push(m.pop().unwrap()),
+                OpAction::Jump(5) => return items,
+                _ => panic!("unexpected action"),
+            }
+        }
+    }
+
+    fn ints(items: Vec<Value>) -> Vec<Integer> {
+        let int = |t: Value| t.try_into().unwrap();
+        items.into_iter().map(int).collect()
+    }
+
+    fn iter(val: Value) -> Value {
+        exec_pop(IterNew, vec![val]).unwrap()
+    }
+
+    #[test]
+    fn sequences_are_iterated_in_order() {
+        let vals = || (1..4).map(Value::Integer);
+        let tuple = Tuple::from_iter(vals()).into();
+        assert_eq!(ints(items(iter(tuple))), vec![1, 2, 3]);
+        let list = List::new(vals().collect()).into();
+        assert_eq!(ints(items(iter(list))), vec![1, 2, 3]);
+        let buffer = Buffer::new(vec![1, 2, 255]).into();
+        assert_eq!(ints(items(iter(buffer))), vec![1, 2, 255]);
+        let range = Range::new(3, 0, -1, false).unwrap().into();
+        assert_eq!(ints(items(iter(range))), vec![3, 2, 1]);
+        assert!(items(iter(List::empty().into())).is_empty());
+    }
+
+    #[test]
+    fn tables_are_iterated_in_key_order() {
+        let table = Table::new(vec![(7, Value::Integer(70)), (2, Value::Integer(20))]);
+        let entries: Vec<_> = items(iter(table.into()))
+            .into_iter()
+            .map(|t| ints(TryInto::<Tuple>::try_into(t).unwrap().iter().collect()))
+            .collect();
+        assert_eq!(entries, vec![vec![2, 20], vec![7, 70]]);
+        let table = Table::new(vec![(u64::MAX, Value::None)]);
+        assert_eq!(items(iter(table.into())).len(), 1);
+    }
+
+    #[test]
+    fn iters_see_items_added_ahead_of_them() {
+        let list = List::new(vec![Value::Integer(1)]);
+        let it = iter(list.clone().into());
+        let mut m = CallStack::new();
+        m.push(it.clone());
+        IterNext::new(5).exec(&mut m).unwrap();
+        list.insert(1, Value::Integer(2)).unwrap();
+        // iterating an Iter continues from its position
+        assert_eq!(ints(items(iter(it))), vec![2]);
+    }
+
+    #[test]
+    fn other_values_cant_be_iterated() {
+        let t = exec_pop(IterNew, vec![Value::Integer(1)]);
+        assert!(matches!(t, Err(OpError::BadType(ValueType::Integer))));
+        let mut m = CallStack::new();
+        m.push(List::empty().into());
+        assert!(IterNext::new(5).exec(&mut m).is_err());
+    }
+
+    #[test]
+    fn ranges_are_created_with_any_nonzero_step() {
+        let int = Value::Integer;
+        let t = exec_pop(RangeCreate, vec![int(5), int(0), int(-2)]);
+        assert_eq!(ints(items(iter(t.unwrap()))), vec![5, 3, 1]);
+        let t = exec_pop(RangeCreateInclusive, vec![int(5), int(1), int(-2)]);
+        assert_eq!(ints(items(iter(t.unwrap()))), vec![5, 3, 1]);
+        let t = exec_pop(
+            RangeCreateInclusive,
+            vec![int(-2), int(Integer::MIN), int(Integer::MIN)],
+        );
+        assert_eq!(ints(items(iter(t.unwrap()))), vec![-2]);
+        let t = exec_pop(RangeCreate, vec![int(0), int(5), int(0)]);
+        assert!(matches!(t, Err(OpError::ZeroStep)));
+        let t = exec_pop(RangeCreate, vec![int(0), Value::Real(5.0), int(1)]);
+        assert!(matches!(t, Err(OpError::IntoType(_))));
+    }
+
+    #[test]
+    fn ranges_stop_at_the_ends_of_integers() {
+        let int = Value::Integer;
+        let args = vec![int(Integer::MIN + 2), int(Integer::MIN), int(-1)];
+        let t = exec_pop(RangeCreateInclusive, args).unwrap();
+        let min = Integer::MIN;
+        assert_eq!(ints(items(iter(t))), vec![min + 2, min + 1, min]);
+        let args = vec![int(Integer::MAX - 1), int(Integer::MAX), int(7)];
+        let t = exec_pop(RangeCreateInclusive, args).unwrap();
+        assert_eq!(ints(items(iter(t))), vec![Integer::MAX - 1]);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::datamodel::Value;
+use std::convert::TryInto;
+
+use crate::datamodel::{Bool, Value};
 
 use super::{CallStack, OpAction, OpError, Operation};
 
@@ -24,6 +26,7 @@ impl Operation for JumpZero {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
         let is_zero = match m.pop()? {
             Value::None => true,
+            Value::Bool(b) => !b,
             Value::Integer(i) => i == 0,
             Value::Real(r) => r == 0.0,
             _ => false,
@@ -36,6 +39,24 @@ impl Operation for JumpZero {
     }
 }
 
+new_op! {
+    /// Like JumpZero, but the condition must be a Bool.
+    pub struct JumpFalse {
+        pub dest: i32,
+    }
+}
+
+impl Operation for JumpFalse {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let condition: Bool = m.pop()?.try_into()?;
+        if condition {
+            Ok(OpAction::None)
+        } else {
+            Ok(OpAction::Jump(self.dest))
+        }
+    }
+}
+
 new_op! {
     pub struct JumpNeg {
         pub dest: i32,
@@ -47,6 +68,8 @@ impl Operation for JumpNeg {
         let is_zero = match m.pop()? {
             Value::Integer(i) => i < 0,
             Value::Real(r) => r < 0.0,
+            #[cfg(feature = "bigint")]
+            Value::BigInt(i) => i.sign() == num_bigint::Sign::Minus,
             _ => false,
         };
         if is_zero {
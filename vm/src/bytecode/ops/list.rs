@@ -1,12 +1,13 @@
 use std::convert::TryInto;
 
-use crate::datamodel::{List, Value};
+use crate::datamodel::{Integer, List, Value};
+use crate::VirtualMachine;
 
-use super::{CallStack, OpAction, OpError, Operation};
+use super::{index, CallStack, OpAction, OpError, Operation};
 
 new_op! {
     pub struct ListCreate {
-        items: u8,
+        pub items: u8,
     }
 }
 
@@ -17,6 +18,8 @@ impl Operation for ListCreate {
             let item = m.pop()?;
             acc.push(item.clone());
         }
+        // items were pushed in order, so they are popped in reverse
+        acc.reverse();
         m.push(List::new(acc).into());
         Ok(OpAction::None)
     }
@@ -52,9 +55,172 @@ impl Operation for ListGetSlice {
         let a: i64 = m.pop()?.try_into()?;
         let list: List = m.pop()?.try_into()?;
         let slice = list
-            .get_slice(a as usize, b as usize)
+            .get_slice(index(a, OpError::IndexRead)?, index(b, OpError::IndexRead)?)
             .ok_or(OpError::IndexRead(b))?;
         m.push(slice.into());
         Ok(OpAction::None)
     }
 }
+
+new_op_empty!(ListSetSlice);
+impl Operation for ListSetSlice {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let len: i64 = m.pop()?.try_into()?;
+        let offset: i64 = m.pop()?.try_into()?;
+        let src_offset: i64 = m.pop()?.try_into()?;
+        let src: List = m.pop()?.try_into()?;
+        let list: List = m.pop()?.try_into()?;
+        let src_offset = index(src_offset, OpError::IndexRead)?;
+        let offset = index(offset, OpError::IndexWrite)?;
+        let len = index(len, OpError::IndexWrite)?;
+        list.set_slice(&src, src_offset, offset, len)
+            .ok_or(OpError::IndexWrite(len as i64))?;
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListInsert);
+impl Operation for ListInsert {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        let index: i64 = m.pop()?.try_into()?;
+        let list: List = m.pop()?.try_into()?;
+        list.insert(super::index(index, OpError::IndexWrite)?, val)
+            .ok_or(OpError::IndexWrite(index))?;
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListRemove);
+impl Operation for ListRemove {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let index: i64 = m.pop()?.try_into()?;
+        let list: List = m.pop()?.try_into()?;
+        let val = list
+            .remove(super::index(index, OpError::IndexRead)?)
+            .ok_or(OpError::IndexRead(index))?;
+        m.push(val);
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListSplice);
+impl Operation for ListSplice {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let src: List = m.pop()?.try_into()?;
+        let b: i64 = m.pop()?.try_into()?;
+        let a: i64 = m.pop()?.try_into()?;
+        let list: List = m.pop()?.try_into()?;
+        // copy first, in case `src` is `list`
+        let src = src.as_slice().to_vec();
+        let removed = list
+            .splice(
+                index(a, OpError::IndexWrite)?,
+                index(b, OpError::IndexWrite)?,
+                src,
+            )
+            .ok_or(OpError::IndexWrite(b))?;
+        m.push(List::new(removed).into());
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListReverse);
+impl Operation for ListReverse {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let list: List = m.pop()?.try_into()?;
+        list.reverse();
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListSort);
+impl Operation for ListSort {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let list: List = m.pop()?.try_into()?;
+        list.sort_by(Value::total_cmp);
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListSortBy);
+impl Operation for ListSortBy {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let cmp = m.pop()?;
+        let list: List = m.pop()?.try_into()?;
+        // `cmp(a, b)` returns an Integer, which is negative if a < b, zero if
+        // a == b and positive if a > b
+        list.try_sort_by(|a, b| -> Result<_, OpError> {
+            let args = vec![a.clone(), b.clone()];
+            let t: Integer = VirtualMachine::call(cmp.clone(), args)?.try_into()?;
+            Ok(t.cmp(&0))
+        })?;
+        Ok(OpAction::None)
+    }
+}
+
+new_op_empty!(ListFind);
+impl Operation for ListFind {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let val = m.pop()?;
+        let list: List = m.pop()?.try_into()?;
+        // structural equality, so that numbers and strings can be found
+        let index = list.as_slice().iter().position(|t| t.structural_eq(&val));
+        m.push(index.map(|i| i as Integer).into());
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec;
+    use super::*;
+
+    fn list(len: i64) -> Value {
+        List::new((0..len).map(Value::Integer).collect()).into()
+    }
+
+    #[test]
+    fn negative_indices_are_out_of_bounds() {
+        let t = exec(ListInsert, vec![list(3), Value::Integer(-1), Value::None]);
+        assert!(matches!(t, Err(OpError::IndexWrite(-1))));
+        let t = exec(ListRemove, vec![list(3), Value::Integer(-1)]);
+        assert!(matches!(t, Err(OpError::IndexRead(-1))));
+        let t = exec(
+            ListGetSlice,
+            vec![list(3), Value::Integer(-1), Value::Integer(2)],
+        );
+        assert!(matches!(t, Err(OpError::IndexRead(-1))));
+        let args = vec![list(3), Value::Integer(-2), Value::Integer(0), list(0)];
+        assert!(matches!(
+            exec(ListSplice, args),
+            Err(OpError::IndexWrite(-2))
+        ));
+        let args = vec![list(3), list(3), Value::Integer(0), Value::Integer(-1)];
+        let args = [args, vec![Value::Integer(1)]].concat();
+        assert!(matches!(
+            exec(ListSetSlice, args),
+            Err(OpError::IndexWrite(-1))
+        ));
+    }
+
+    #[test]
+    fn reversed_and_overflowing_ranges_are_out_of_bounds() {
+        let t = exec(
+            ListGetSlice,
+            vec![list(3), Value::Integer(1), Value::Integer(0)],
+        );
+        assert!(matches!(t, Err(OpError::IndexRead(0))));
+        let args = vec![list(3), Value::Integer(2), Value::Integer(1), list(0)];
+        assert!(matches!(
+            exec(ListSplice, args),
+            Err(OpError::IndexWrite(1))
+        ));
+        let max = Value::Integer(i64::MAX);
+        let args = vec![list(3), list(3), Value::Integer(1), Value::Integer(0), max];
+        assert!(matches!(
+            exec(ListSetSlice, args),
+            Err(OpError::IndexWrite(_))
+        ));
+    }
+}
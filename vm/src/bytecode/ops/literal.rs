@@ -1,12 +1,27 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "bigint")]
+use crate::datamodel::BigInt;
 use crate::datamodel::Value;
 
-use super::{BytesIO, BytesReadError, CallStack, OpAction, OpError, Operation};
+use super::{BytesIO, BytesReadError, CallStack, OpAction, OpError, Operation, ParseOperands};
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum LiteralValue {
     None,
+    Bool(bool),
     Integer(i64),
     Real(f64),
+    /// stored as signed little endian bytes
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt),
+}
+
+impl From<bool> for LiteralValue {
+    fn from(t: bool) -> LiteralValue {
+        LiteralValue::Bool(t)
+    }
 }
 
 impl From<i64> for LiteralValue {
@@ -15,12 +30,51 @@ impl From<i64> for LiteralValue {
     }
 }
 
+/// none, true, false, an integer, a real with a `.` or exponent, or a bigint
+/// with an `n` suffix
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LiteralValue::None => write!(f, "none"),
+            LiteralValue::Bool(b) => write!(f, "{}", b),
+            LiteralValue::Integer(i) => write!(f, "{}", i),
+            // Debug reads back exactly, and never looks like an integer
+            LiteralValue::Real(r) => write!(f, "{:?}", r),
+            #[cfg(feature = "bigint")]
+            LiteralValue::BigInt(b) => write!(f, "{}n", b),
+        }
+    }
+}
+
+impl FromStr for LiteralValue {
+    type Err = ();
+    fn from_str(s: &str) -> Result<LiteralValue, ()> {
+        match s {
+            "none" => return Ok(LiteralValue::None),
+            "true" => return Ok(LiteralValue::Bool(true)),
+            "false" => return Ok(LiteralValue::Bool(false)),
+            _ => {}
+        }
+        if let Ok(i) = s.parse() {
+            return Ok(LiteralValue::Integer(i));
+        }
+        #[cfg(feature = "bigint")]
+        if let Some(Ok(b)) = s.strip_suffix('n').map(str::parse) {
+            return Ok(LiteralValue::BigInt(b));
+        }
+        s.parse().map(LiteralValue::Real).map_err(|_| ())
+    }
+}
+
 impl LiteralValue {
     pub fn into_val(&self) -> Value {
         match self {
             LiteralValue::None => Value::None,
+            LiteralValue::Bool(b) => Value::Bool(*b),
             LiteralValue::Integer(i) => Value::Integer(*i),
             LiteralValue::Real(r) => Value::Real(*r),
+            #[cfg(feature = "bigint")]
+            LiteralValue::BigInt(b) => Value::from_bigint(b.clone()),
         }
     }
 }
@@ -38,32 +92,97 @@ impl BytesIO for LiteralValue {
                 let (b, real) = <f64 as BytesIO>::read(b2)?;
                 Ok((b, LiteralValue::Real(real)))
             }
+            #[cfg(feature = "bigint")]
+            3 => {
+                let (b, bytes) = <Vec<u8> as BytesIO>::read(b2)?;
+                Ok((
+                    b,
+                    LiteralValue::BigInt(BigInt::from_signed_bytes_le(&bytes)),
+                ))
+            }
+            4 => {
+                let (b, t) = <u8 as BytesIO>::read(b2)?;
+                match t {
+                    0 => Ok((b, LiteralValue::Bool(false))),
+                    1 => Ok((b, LiteralValue::Bool(true))),
+                    _ => Err(BytesReadError::InvalidValue(b2)),
+                }
+            }
             _ => Err(BytesReadError::InvalidValue(b)),
         }
     }
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
         match t {
             LiteralValue::None => Some(<u8 as BytesIO>::write(&0, b)?),
+            LiteralValue::Bool(t) => Some(<(u8, u8) as BytesIO>::write(&(4, *t as u8), b)?),
             LiteralValue::Integer(int) => Some(<(u8, i64) as BytesIO>::write(&(1, *int), b)?),
             LiteralValue::Real(real) => Some(<(u8, f64) as BytesIO>::write(&(2, *real), b)?),
+            #[cfg(feature = "bigint")]
+            LiteralValue::BigInt(big) => {
+                <(u8, Vec<u8>) as BytesIO>::write(&(3, big.to_signed_bytes_le()), b)
+            }
         }
     }
+    fn encoded_len(t: &Self) -> usize {
+        match t {
+            LiteralValue::None => <u8 as BytesIO>::encoded_len(&0),
+            LiteralValue::Bool(t) => <(u8, u8) as BytesIO>::encoded_len(&(4, *t as u8)),
+            LiteralValue::Integer(int) => <(u8, i64) as BytesIO>::encoded_len(&(1, *int)),
+            LiteralValue::Real(real) => <(u8, f64) as BytesIO>::encoded_len(&(2, *real)),
+            #[cfg(feature = "bigint")]
+            LiteralValue::BigInt(big) => {
+                <(u8, Vec<u8>) as BytesIO>::encoded_len(&(3, big.to_signed_bytes_le()))
+            }
+        }
+    }
+}
+
+pub struct LiteralCreate {
+    val: LiteralValue,
+}
+
+impl LiteralCreate {
+    pub fn new(val: LiteralValue) -> LiteralCreate {
+        LiteralCreate { val }
+    }
+
+    pub fn value(&self) -> &LiteralValue {
+        &self.val
+    }
+}
+
+// not `new_op!`, since LiteralValue isn't Copy
+impl BytesIO for LiteralCreate {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b, val) = <LiteralValue as BytesIO>::read(b)?;
+        Ok((b, LiteralCreate { val }))
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        <LiteralValue as BytesIO>::write(&t.val, b)
+    }
+    fn encoded_len(t: &Self) -> usize {
+        <LiteralValue as BytesIO>::encoded_len(&t.val)
+    }
 }
 
-new_op! {
-    pub struct LiteralCreate {
-        val: LiteralValue,
+impl fmt::Display for LiteralCreate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.val)
+    }
+}
+
+impl ParseOperands for LiteralCreate {
+    fn parse_operands(operands: &[&str]) -> Option<Self> {
+        match operands {
+            [val] => Some(LiteralCreate::new(val.parse().ok()?)),
+            _ => None,
+        }
     }
 }
 
 impl Operation for LiteralCreate {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
-        let val = match self.val {
-            LiteralValue::None => Value::None,
-            LiteralValue::Integer(int) => int.into(),
-            LiteralValue::Real(real) => real.into(),
-        };
-        m.push(val);
+        m.push(self.val.into_val());
         Ok(OpAction::None)
     }
 }
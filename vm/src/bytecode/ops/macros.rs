@@ -9,6 +9,24 @@ macro_rules! new_op_empty {
             fn write<'a>(_: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
                 Some(b)
             }
+            fn encoded_len(_: &Self) -> usize {
+                0
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, _: &mut std::fmt::Formatter) -> std::fmt::Result {
+                Ok(())
+            }
+        }
+
+        impl super::ParseOperands for $name {
+            fn parse_operands(operands: &[&str]) -> Option<Self> {
+                match operands {
+                    [] => Some($name),
+                    _ => None,
+                }
+            }
         }
     };
 }
@@ -37,5 +55,32 @@ macro_rules! new_op {
                 ($(self.$field),+)
             }
         }
+
+        /// the operands, separated by spaces
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                let operands: &[&dyn std::fmt::Display] = &[$(&self.$field),+];
+                for (i, operand) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", operand)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl super::ParseOperands for $name {
+            fn parse_operands(operands: &[&str]) -> Option<Self> {
+                let mut operands = operands.iter();
+                let t = $name {
+                    $($field: operands.next()?.parse().ok()?),+
+                };
+                match operands.next() {
+                    Some(_) => None,
+                    None => Some(t),
+                }
+            }
+        }
     };
 }
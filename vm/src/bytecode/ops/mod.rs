@@ -5,6 +5,7 @@ mod buffer;
 mod call;
 mod cmp;
 mod int;
+mod iter;
 mod jump;
 mod list;
 mod literal;
@@ -15,20 +16,63 @@ mod stack;
 mod table;
 mod tuple;
 
-use super::{BytesIO, BytesReadError, DataIO, OpAction, OpError, Operation};
+use std::convert::TryFrom;
+
+use super::{BytesIO, BytesReadError, DataIO, OpAction, OpError, Operation, ParseOperands};
 
 use crate::CallStack;
 
-pub use buffer::{BufferCreate, BufferGetSlice, BufferSetSlice};
+#[cfg(feature = "bigint")]
+pub use buffer::{BigIntFromBuffer, BigIntToBuffer};
+pub use buffer::{
+    BufferCreate, BufferGetSlice, BufferMaterialize, BufferRead, BufferSetSlice, BufferView,
+    BufferWrite,
+};
 pub use call::{Call, Return};
-pub use cmp::{Cmp, GetType};
+pub use cmp::{
+    Cmp, CmpEq, CmpGe, CmpGt, CmpLe, CmpLt, CmpNe, GetType, StructCmp, StructEq, StructHash,
+};
 pub use int::{And, Not, Or, Shl, Shr, Xor};
-pub use jump::{Jump, JumpNeg, JumpZero};
-pub use list::{ListCreate, ListGetSlice, ListPop, ListPush};
+pub use iter::{IterNew, IterNext, RangeCreate, RangeCreateInclusive};
+pub use jump::{Jump, JumpFalse, JumpNeg, JumpZero};
+pub use list::{
+    ListCreate, ListFind, ListGetSlice, ListInsert, ListPop, ListPush, ListRemove, ListReverse,
+    ListSetSlice, ListSort, ListSortBy, ListSplice,
+};
 pub use literal::{LiteralCreate, LiteralValue};
-pub use num::{Add, Div, Mul, Neg, Rem, Sub};
-pub use real::{Ceil, Floor, IntToReal, Round, Trunc};
-pub use seq::{SeqAppend, SeqGet, SeqLen, SeqResize, SeqSet, SeqToList};
+pub use num::{
+    Add, Div, Mul, Neg, Rem, SaturatingAdd, SaturatingMul, SaturatingSub, Sub, WrappingAdd,
+    WrappingMul, WrappingSub,
+};
+#[cfg(feature = "bigint")]
+pub use num::{PromotingAdd, PromotingMul, PromotingSub};
+pub use real::{Ceil, Floor, IntToReal, RealToInt, Round, Rounding, Trunc};
+pub use seq::{SeqAppend, SeqGet, SeqLen, SeqResize, SeqSet, SeqToList, SeqUnpack};
 pub use stack::{StackCopy, StackLoad, StackPop, StackStore, StackSwap};
 pub use table::TableCreate;
 pub use tuple::{TupleCreate, TupleFromList, TupleWeakRef, TupleWeakUpgrade};
+
+/// Converts an index from a script, which is out of bounds if negative.
+fn index(t: i64, err: fn(i64) -> OpError) -> Result<usize, OpError> {
+    usize::try_from(t).map_err(|_| err(t))
+}
+
+/// Runs `op` on a stack with `args` pushed in order, and returns the stack.
+#[cfg(test)]
+fn exec(op: impl Operation, args: Vec<crate::datamodel::Value>) -> Result<CallStack, OpError> {
+    let mut m = CallStack::new();
+    for arg in args {
+        m.push(arg);
+    }
+    op.exec(&mut m)?;
+    Ok(m)
+}
+
+/// Like `exec`, but returns the value on top of the stack.
+#[cfg(test)]
+fn exec_pop(
+    op: impl Operation,
+    args: Vec<crate::datamodel::Value>,
+) -> Result<crate::datamodel::Value, OpError> {
+    exec(op, args)?.pop()
+}
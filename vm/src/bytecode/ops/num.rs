@@ -1,26 +1,33 @@
-use std::convert::TryInto;
+#[cfg(feature = "bigint")]
+use num_traits::{ToPrimitive, Zero};
 
-use crate::datamodel::{Integer, Real, Value};
+#[cfg(feature = "bigint")]
+use crate::datamodel::BigInt;
+use crate::datamodel::{Integer, Real, Value, ValueTryIntoError, ValueType};
 
 use super::{CallStack, OpAction, OpError, Operation};
 
 macro_rules! impl_math_op {
-    ($name:ident, $e:expr) => {
+    ($name:ident, $int:expr, $real:tt) => {
         new_op_empty!($name);
         impl Operation for $name {
             fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
                 let rhs = m.pop()?;
                 let lhs = m.pop()?;
-                let result = match lhs {
-                    Value::Integer(lhs) => {
-                        let rhs: Integer = rhs.try_into()?;
-                        $e(lhs, rhs).into()
+                let result = match operands(lhs, rhs)? {
+                    Operands::Integer(lhs, rhs) => {
+                        let result: Result<_, OpError> = $int(lhs, rhs);
+                        Value::from(result?)
                     }
-                    Value::Real(lhs) => {
-                        let rhs: Real = rhs.try_into()?;
-                        $e(lhs, rhs).into()
+                    Operands::Real(lhs, rhs) => (lhs $real rhs).into(),
+                    #[cfg(feature = "bigint")]
+                    Operands::BigInt(lhs, rhs) => {
+                        // `/` and `%` panic on zero instead of returning None
+                        if rhs.is_zero() && matches!(stringify!($real), "/" | "%") {
+                            return Err(OpError::DivideByZero);
+                        }
+                        Value::from_bigint(lhs $real rhs)
                     }
-                    _ => return Err(OpError::BadType(lhs.get_type())),
                 };
                 m.push(result);
                 Ok(OpAction::None)
@@ -29,22 +36,268 @@ macro_rules! impl_math_op {
     };
 }
 
-impl_math_op!(Add, |lhs, rhs| lhs + rhs);
-impl_math_op!(Sub, |lhs, rhs| lhs - rhs);
-impl_math_op!(Mul, |lhs, rhs| lhs * rhs);
-impl_math_op!(Div, |lhs, rhs| lhs / rhs);
-impl_math_op!(Rem, |lhs, rhs| lhs % rhs);
+enum Operands {
+    Integer(Integer, Integer),
+    Real(Real, Real),
+    #[cfg(feature = "bigint")]
+    BigInt(BigInt, BigInt),
+}
+
+/// Integer operands are promoted to Real if the other operand is a Real, and
+/// to BigInt if the other operand is a BigInt.
+fn operands(lhs: Value, rhs: Value) -> Result<Operands, OpError> {
+    let expected = match (lhs, rhs) {
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(l), Value::BigInt(r)) => return Ok(Operands::BigInt(l, r)),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(l), Value::Integer(r)) => return Ok(Operands::BigInt(l, r.into())),
+        #[cfg(feature = "bigint")]
+        (Value::Integer(l), Value::BigInt(r)) => return Ok(Operands::BigInt(l.into(), r)),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(l), Value::Real(r)) => return Ok(Operands::Real(big_to_real(&l), r)),
+        #[cfg(feature = "bigint")]
+        (Value::Real(l), Value::BigInt(r)) => return Ok(Operands::Real(l, big_to_real(&r))),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(_), rhs) => (rhs, ValueType::BigInt),
+        (Value::Integer(l), Value::Integer(r)) => return Ok(Operands::Integer(l, r)),
+        (Value::Integer(l), Value::Real(r)) => return Ok(Operands::Real(l as Real, r)),
+        (Value::Real(l), Value::Integer(r)) => return Ok(Operands::Real(l, r as Real)),
+        (Value::Real(l), Value::Real(r)) => return Ok(Operands::Real(l, r)),
+        (Value::Integer(_), rhs) => (rhs, ValueType::Integer),
+        (Value::Real(_), rhs) => (rhs, ValueType::Real),
+        (lhs, _) => return Err(OpError::BadType(lhs.get_type())),
+    };
+    Err(ValueTryIntoError {
+        found: expected.0.get_type(),
+        expected: expected.1,
+    }
+    .into())
+}
+
+#[cfg(feature = "bigint")]
+pub(super) fn big_to_real(t: &BigInt) -> Real {
+    // only fails for values beyond the range of a Real
+    t.to_f64().unwrap_or(match t.sign() {
+        num_bigint::Sign::Minus => Real::NEG_INFINITY,
+        _ => Real::INFINITY,
+    })
+}
+
+fn checked(result: Option<Integer>) -> Result<Integer, OpError> {
+    result.ok_or(OpError::Overflow)
+}
+
+fn nonzero(rhs: Integer) -> Result<Integer, OpError> {
+    match rhs {
+        0 => Err(OpError::DivideByZero),
+        _ => Ok(rhs),
+    }
+}
+
+// integer overflow and division by zero are errors
+impl_math_op!(Add, |l, r| checked(Integer::checked_add(l, r)), +);
+impl_math_op!(Sub, |l, r| checked(Integer::checked_sub(l, r)), -);
+impl_math_op!(Mul, |l, r| checked(Integer::checked_mul(l, r)), *);
+impl_math_op!(Div, |l, r| checked(Integer::checked_div(l, nonzero(r)?)), /);
+impl_math_op!(Rem, |l, r| checked(Integer::checked_rem(l, nonzero(r)?)), %);
+
+// integer results wrap around on overflow
+impl_math_op!(WrappingAdd, |l, r| Ok(Integer::wrapping_add(l, r)), +);
+impl_math_op!(WrappingSub, |l, r| Ok(Integer::wrapping_sub(l, r)), -);
+impl_math_op!(WrappingMul, |l, r| Ok(Integer::wrapping_mul(l, r)), *);
+
+// integer results are clamped to Integer::MIN or Integer::MAX on overflow
+impl_math_op!(SaturatingAdd, |l, r| Ok(Integer::saturating_add(l, r)), +);
+impl_math_op!(SaturatingSub, |l, r| Ok(Integer::saturating_sub(l, r)), -);
+impl_math_op!(SaturatingMul, |l, r| Ok(Integer::saturating_mul(l, r)), *);
+
+/// Returns the result of `int`, or the exact result of `big` on overflow.
+#[cfg(feature = "bigint")]
+fn promote(
+    lhs: Integer,
+    rhs: Integer,
+    int: fn(Integer, Integer) -> Option<Integer>,
+    big: fn(BigInt, BigInt) -> BigInt,
+) -> Result<Value, OpError> {
+    Ok(match int(lhs, rhs) {
+        Some(t) => t.into(),
+        None => Value::from_bigint(big(lhs.into(), rhs.into())),
+    })
+}
+
+// integer results are promoted to BigInt on overflow
+#[cfg(feature = "bigint")]
+impl_math_op!(PromotingAdd, |l, r| promote(l, r, Integer::checked_add, |l, r| l + r), +);
+#[cfg(feature = "bigint")]
+impl_math_op!(PromotingSub, |l, r| promote(l, r, Integer::checked_sub, |l, r| l - r), -);
+#[cfg(feature = "bigint")]
+impl_math_op!(PromotingMul, |l, r| promote(l, r, Integer::checked_mul, |l, r| l * r), *);
 
 new_op_empty!(Neg);
 impl Operation for Neg {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
         let val = m.pop()?;
         let val = match val {
-            Value::Integer(val) => (-val).into(),
+            Value::Integer(val) => checked(val.checked_neg())?.into(),
             Value::Real(val) => (-val).into(),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(val) => Value::from_bigint(-val),
             _ => return Err(OpError::BadType(val.get_type())),
         };
         m.push(val);
         Ok(OpAction::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec_pop;
+    use super::*;
+
+    fn int(t: Integer) -> Value {
+        Value::Integer(t)
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let t = exec_pop(Add, vec![int(Integer::MAX), int(1)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Sub, vec![int(Integer::MIN), int(1)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Mul, vec![int(1 << 32), int(1 << 31)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Neg, vec![int(Integer::MIN)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Add, vec![int(Integer::MAX - 1), int(1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MAX))));
+    }
+
+    #[test]
+    fn min_divided_by_minus_one_overflows() {
+        let t = exec_pop(Div, vec![int(Integer::MIN), int(-1)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Rem, vec![int(Integer::MIN), int(-1)]);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = exec_pop(Div, vec![int(-7), int(2)]);
+        assert!(matches!(t, Ok(Value::Integer(-3))));
+        let t = exec_pop(Rem, vec![int(-7), int(2)]);
+        assert!(matches!(t, Ok(Value::Integer(-1))));
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        let t = exec_pop(Div, vec![int(1), int(0)]);
+        assert!(matches!(t, Err(OpError::DivideByZero)));
+        let t = exec_pop(Rem, vec![int(Integer::MIN), int(0)]);
+        assert!(matches!(t, Err(OpError::DivideByZero)));
+        // but not for Reals
+        let t = exec_pop(Div, vec![int(1), Value::Real(0.0)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == Real::INFINITY));
+    }
+
+    #[test]
+    fn wrapping_ops_wrap() {
+        let t = exec_pop(WrappingAdd, vec![int(Integer::MAX), int(1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MIN))));
+        let t = exec_pop(WrappingSub, vec![int(Integer::MIN), int(2)]);
+        assert!(matches!(t, Ok(Value::Integer(t)) if t == Integer::MAX - 1));
+        let t = exec_pop(WrappingMul, vec![int(1 << 62), int(4)]);
+        assert!(matches!(t, Ok(Value::Integer(0))));
+        let t = exec_pop(WrappingMul, vec![int(Integer::MIN), int(-1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MIN))));
+    }
+
+    #[test]
+    fn saturating_ops_clamp() {
+        let t = exec_pop(SaturatingAdd, vec![int(Integer::MAX), int(1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MAX))));
+        let t = exec_pop(SaturatingSub, vec![int(Integer::MIN), int(1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MIN))));
+        let t = exec_pop(SaturatingMul, vec![int(Integer::MIN), int(2)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MIN))));
+        let t = exec_pop(SaturatingMul, vec![int(Integer::MIN), int(-1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MAX))));
+        let t = exec_pop(SaturatingAdd, vec![int(2), int(3)]);
+        assert!(matches!(t, Ok(Value::Integer(5))));
+    }
+
+    #[test]
+    fn operands_must_be_numbers() {
+        let t = exec_pop(Add, vec![Value::None, int(1)]);
+        assert!(matches!(t, Err(OpError::BadType(ValueType::None))));
+        let t = exec_pop(Add, vec![int(1), Value::Bool(true)]);
+        assert!(matches!(
+            t,
+            Err(OpError::IntoType(ValueTryIntoError {
+                found: ValueType::Bool,
+                expected: ValueType::Integer
+            }))
+        ));
+    }
+
+    #[test]
+    fn integers_are_promoted_to_reals() {
+        let t = exec_pop(Add, vec![int(1), Value::Real(0.5)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == 1.5));
+        let t = exec_pop(Div, vec![Value::Real(3.0), int(2)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == 1.5));
+        // Reals don't overflow
+        let t = exec_pop(Mul, vec![int(Integer::MAX), Value::Real(2.0)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == 2.0 * Integer::MAX as Real));
+    }
+
+    #[cfg(feature = "bigint")]
+    fn big(t: BigInt) -> Value {
+        Value::BigInt(t)
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn promoting_ops_promote_on_overflow() {
+        let max = BigInt::from(Integer::MAX);
+        let t = exec_pop(PromotingAdd, vec![int(Integer::MAX), int(1)]);
+        assert!(matches!(t, Ok(Value::BigInt(t)) if t == &max + BigInt::from(1)));
+        let t = exec_pop(PromotingSub, vec![int(Integer::MIN), int(1)]);
+        assert!(
+            matches!(t, Ok(Value::BigInt(t)) if t == BigInt::from(Integer::MIN) - BigInt::from(1))
+        );
+        let t = exec_pop(PromotingMul, vec![int(Integer::MAX), int(Integer::MAX)]);
+        assert!(matches!(t, Ok(Value::BigInt(t)) if t == &max * &max));
+        let t = exec_pop(PromotingAdd, vec![int(1), int(2)]);
+        assert!(matches!(t, Ok(Value::Integer(3))));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_results_that_fit_are_integers() {
+        let over = BigInt::from(Integer::MAX) + BigInt::from(1);
+        let t = exec_pop(Sub, vec![big(over.clone()), int(1)]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MAX))));
+        let t = exec_pop(Add, vec![int(-1), big(over.clone())]);
+        assert!(matches!(t, Ok(Value::Integer(Integer::MAX))));
+        let t = exec_pop(Div, vec![big(over.clone()), big(over.clone())]);
+        assert!(matches!(t, Ok(Value::Integer(1))));
+        // checked ops don't overflow once a BigInt is involved
+        let t = exec_pop(Add, vec![big(over.clone()), int(Integer::MAX)]);
+        assert!(matches!(t, Ok(Value::BigInt(t)) if t == over + BigInt::from(Integer::MAX)));
+        let t = exec_pop(Add, vec![big(BigInt::from(1) << 70), Value::Real(0.5)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == (1u128 << 70) as Real + 0.5));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_division_by_zero_is_an_error() {
+        let over = || big(BigInt::from(Integer::MAX) + BigInt::from(1));
+        let t = exec_pop(Div, vec![over(), int(0)]);
+        assert!(matches!(t, Err(OpError::DivideByZero)));
+        let t = exec_pop(Rem, vec![over(), int(0)]);
+        assert!(matches!(t, Err(OpError::DivideByZero)));
+        let t = exec_pop(Rem, vec![int(5), big(BigInt::from(0))]);
+        assert!(matches!(t, Err(OpError::DivideByZero)));
+        // the other ops don't check for zero
+        let t = exec_pop(Mul, vec![over(), int(0)]);
+        assert!(matches!(t, Ok(Value::Integer(0))));
+        let t = exec_pop(Sub, vec![over(), int(0)]);
+        assert!(matches!(t, Ok(Value::BigInt(_))));
+    }
+}
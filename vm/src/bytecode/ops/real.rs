@@ -1,8 +1,12 @@
 use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
 
+#[cfg(feature = "bigint")]
+use crate::datamodel::Value;
 use crate::datamodel::{Integer, Real};
 
-use super::{CallStack, OpAction, OpError, Operation};
+use super::{CallStack, DataIO, OpAction, OpError, Operation};
 
 macro_rules! impl_real_op {
     ($name:ident, $e:expr) => {
@@ -25,8 +29,148 @@ impl_real_op!(Round, |val: f64| val.round());
 new_op_empty!(IntToReal);
 impl Operation for IntToReal {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
-        let int: Integer = m.pop()?.try_into()?;
-        m.push((int as Real).into());
+        let real = match m.pop()? {
+            #[cfg(feature = "bigint")]
+            Value::BigInt(big) => super::num::big_to_real(&big),
+            int => TryInto::<Integer>::try_into(int)? as Real,
+        };
+        m.push(real.into());
         Ok(OpAction::None)
     }
 }
+
+#[derive(Clone, Copy, Debug)]
+pub enum Rounding {
+    Trunc,
+    Floor,
+    Ceil,
+    Round,
+}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Rounding, ()> {
+        Ok(match s {
+            "Trunc" => Rounding::Trunc,
+            "Floor" => Rounding::Floor,
+            "Ceil" => Rounding::Ceil,
+            "Round" => Rounding::Round,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl DataIO for Rounding {
+    type Target = u8;
+    fn from_bytes(t: u8) -> Option<Self> {
+        Some(match t {
+            0 => Rounding::Trunc,
+            1 => Rounding::Floor,
+            2 => Rounding::Ceil,
+            3 => Rounding::Round,
+            _ => return None,
+        })
+    }
+    fn into_bytes(&self) -> u8 {
+        *self as u8
+    }
+}
+
+new_op! {
+    pub struct RealToInt {
+        pub rounding: Rounding,
+    }
+}
+
+impl Operation for RealToInt {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let real: Real = m.pop()?.try_into()?;
+        let real = match self.rounding {
+            Rounding::Trunc => real.trunc(),
+            Rounding::Floor => real.floor(),
+            Rounding::Ceil => real.ceil(),
+            Rounding::Round => real.round(),
+        };
+        // NaN fails both comparisons, and the bounds are -2^63 and 2^63
+        if !(real >= Integer::MIN as Real && real < -(Integer::MIN as Real)) {
+            return Err(OpError::Overflow);
+        }
+        m.push((real as Integer).into());
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec_pop;
+    use super::*;
+    use crate::datamodel::Value;
+
+    fn to_int(rounding: Rounding, real: Real) -> Result<Value, OpError> {
+        exec_pop(RealToInt::new(rounding), vec![real.into()])
+    }
+
+    #[test]
+    fn real_to_int_rounds() {
+        let cases = [
+            (Rounding::Trunc, [2, -2, 2, -2]),
+            (Rounding::Floor, [2, -3, 2, -3]),
+            (Rounding::Ceil, [3, -2, 3, -2]),
+            (Rounding::Round, [3, -3, 2, -2]),
+        ];
+        for (rounding, expected) in cases.iter() {
+            for (real, expected) in [2.5, -2.5, 2.25, -2.25].iter().zip(expected.iter()) {
+                let t = to_int(*rounding, *real);
+                assert!(
+                    matches!(t, Ok(Value::Integer(t)) if t == *expected),
+                    "{} {}",
+                    rounding,
+                    real
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn real_to_int_rejects_out_of_range_and_nan() {
+        let min = Integer::MIN as Real;
+        assert!(matches!(
+            to_int(Rounding::Trunc, min),
+            Ok(Value::Integer(Integer::MIN))
+        ));
+        for real in [
+            -min,
+            min * 2.0,
+            Real::NAN,
+            Real::INFINITY,
+            Real::NEG_INFINITY,
+        ]
+        .iter()
+        {
+            let t = to_int(Rounding::Trunc, *real);
+            assert!(matches!(t, Err(OpError::Overflow)), "{}", real);
+        }
+        // the next Real below -2^63 is 2^11 less
+        let t = to_int(Rounding::Floor, min - 2048.0);
+        assert!(matches!(t, Err(OpError::Overflow)));
+        let t = to_int(Rounding::Trunc, 9.2e18);
+        assert!(matches!(t, Ok(Value::Integer(9_200_000_000_000_000_000))));
+    }
+
+    #[test]
+    fn reals_are_rounded_to_reals() {
+        let t = exec_pop(Floor, vec![Value::Real(-0.5)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == -1.0));
+        let t = exec_pop(Round, vec![Value::Real(0.5)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == 1.0));
+        let t = exec_pop(IntToReal, vec![Value::Integer(-3)]);
+        assert!(matches!(t, Ok(Value::Real(r)) if r == -3.0));
+        assert!(exec_pop(Ceil, vec![Value::Integer(1)]).is_err());
+    }
+}
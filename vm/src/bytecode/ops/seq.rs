@@ -1,6 +1,6 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
-use crate::datamodel::{Identity, List, Value};
+use crate::datamodel::{Integer, List, Value};
 
 use super::{CallStack, OpAction, OpError, Operation};
 
@@ -9,13 +9,15 @@ impl Operation for SeqLen {
     fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
         let seq = m.pop()?;
         let len = match seq {
-            Value::Tuple(t) => t.len(),
+            Value::Tuple(t) => t.len() as u128,
             // Value::Table(t) => t.len(),
-            Value::List(t) => t.len(),
-            Value::Buffer(t) => t.len(),
+            Value::List(t) => t.len() as u128,
+            Value::Buffer(t) => t.len() as u128,
+            Value::Range(t) => t.len(),
             _ => return Err(OpError::BadType(seq.get_type())),
         };
-        m.push((len as i64).into());
+        let len = Integer::try_from(len).map_err(|_| OpError::Overflow)?;
+        m.push(len.into());
         Ok(OpAction::None)
     }
 }
@@ -27,7 +29,7 @@ impl Operation for SeqResize {
         let seq = m.pop()?;
         match seq {
             Value::List(t) => t.resize(len as usize),
-            Value::Buffer(t) => t.resize(len as usize),
+            Value::Buffer(t) => t.resize(len as usize).ok_or(OpError::ViewResize)?,
             _ => return Err(OpError::BadType(seq.get_type())),
         }
         Ok(OpAction::None)
@@ -40,6 +42,10 @@ fn seq_get(seq: &Value, index: i64) -> Result<Value, OpError> {
         Value::Table(t) => Ok(t.get(index as u64).unwrap_or(Value::None)),
         Value::List(t) => t.get(index as usize).ok_or(OpError::IndexRead(index)),
         Value::Buffer(t) => t.get(index as usize).ok_or(OpError::IndexRead(index)),
+        Value::Range(t) => match u128::try_from(index).ok().and_then(|i| t.get(i)) {
+            Some(t) => Ok(t.into()),
+            None => Err(OpError::IndexRead(index)),
+        },
         _ => return Err(OpError::BadType(seq.get_type())),
     }
 }
@@ -88,6 +94,7 @@ fn seq_to_vec(seq: &Value) -> Result<Vec<Value>, OpError> {
         Value::Table(t) => t.to_vec(),
         Value::List(t) => t.as_slice().to_vec(),
         Value::Buffer(t) => t.as_slice().iter().map(|b| (*b as i64).into()).collect(),
+        Value::Range(t) => (0..t.len()).map(|i| t.get(i).unwrap().into()).collect(),
         _ => return Err(OpError::BadType(seq.get_type())),
     })
 }
@@ -113,18 +120,19 @@ impl Operation for SeqAppend {
             }
             Value::Buffer(buffer) => match src {
                 Value::Buffer(src) => {
-                    if buffer.identity() == src.identity() {
-                        buffer.append(&src.as_slice().to_vec());
+                    if buffer.shares_storage(&src) {
+                        buffer.append(&src.as_slice().to_vec())
                     } else {
-                        buffer.append(&src.as_slice());
+                        buffer.append(&src.as_slice())
                     }
+                    .ok_or(OpError::ViewResize)?;
                 }
                 _ => {
                     let mut acc = Vec::new();
                     for val in seq_to_vec(&src)?.into_iter() {
                         acc.push(TryInto::<i64>::try_into(val)? as u8)
                     }
-                    buffer.append(&acc);
+                    buffer.append(&acc).ok_or(OpError::ViewResize)?;
                 }
             },
             _ => return Err(OpError::BadType(seq.get_type())),
@@ -132,3 +140,63 @@ impl Operation for SeqAppend {
         Ok(OpAction::None)
     }
 }
+
+new_op! {
+    /// Pops a sequence with exactly `items` items, and pushes its items in
+    /// reverse, so that the first item is on top.
+    pub struct SeqUnpack {
+        pub items: u8,
+    }
+}
+
+impl Operation for SeqUnpack {
+    fn exec(&self, m: &mut CallStack) -> Result<OpAction, OpError> {
+        let seq = m.pop()?;
+        let expected = self.items as usize;
+        let items = match &seq {
+            // only make the items of a range once its length is known to
+            // fit, since it can be too long to make
+            Value::Range(t) if t.len() != expected as u128 => {
+                return Err(OpError::UnpackLen {
+                    expected,
+                    found: usize::try_from(t.len()).unwrap_or(usize::MAX),
+                });
+            }
+            _ => seq_to_vec(&seq)?,
+        };
+        if items.len() != expected {
+            return Err(OpError::UnpackLen {
+                expected,
+                found: items.len(),
+            });
+        }
+        for item in items.into_iter().rev() {
+            m.push(item);
+        }
+        Ok(OpAction::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datamodel::Range;
+
+    #[test]
+    fn unpack_checks_range_len_first() {
+        let mut m = CallStack::new();
+        m.push(Range::new(0, 1 << 40, 1, false).unwrap().into());
+        let t = SeqUnpack::new(2).exec(&mut m);
+        assert!(matches!(
+            t,
+            Err(OpError::UnpackLen {
+                expected: 2,
+                found: 0x100_0000_0000
+            })
+        ));
+        m.push(Range::new(3, 5, 1, false).unwrap().into());
+        SeqUnpack::new(2).exec(&mut m).unwrap();
+        assert!(matches!(m.pop(), Ok(Value::Integer(3))));
+        assert!(matches!(m.pop(), Ok(Value::Integer(4))));
+    }
+}
@@ -20,7 +20,7 @@ impl Operation for StackPop {
 
 new_op! {
     pub struct StackLoad {
-        local: u8,
+        pub local: u8,
     }
 }
 
@@ -34,7 +34,7 @@ impl Operation for StackLoad {
 
 new_op! {
     pub struct StackStore {
-        local: u8,
+        pub local: u8,
     }
 }
 
@@ -48,7 +48,7 @@ impl Operation for StackStore {
 
 new_op! {
     pub struct StackSwap {
-        local: u8,
+        pub local: u8,
     }
 }
 
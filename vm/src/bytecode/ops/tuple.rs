@@ -7,7 +7,7 @@ use super::{CallStack, OpAction, OpError, Operation};
 
 new_op! {
     pub struct TupleCreate {
-        items: u8,
+        pub items: u8,
     }
 }
 
@@ -18,6 +18,8 @@ impl Operation for TupleCreate {
             let item = m.pop()?;
             acc.push(RefCell::new(item.clone()));
         }
+        // items were pushed in order, so they are popped in reverse
+        acc.reverse();
         m.push(Tuple::new(acc).into());
         Ok(OpAction::None)
     }
@@ -0,0 +1,492 @@
+use std::fmt;
+
+use super::op::CORE_OP_NAMES;
+use super::verify::{ItemError, VerifyError};
+use super::{BytesIO, BytesReadError, DebugInfo, ModuleItem, Program};
+
+/*
+A `.pnb` file holds a Program, in this layout (numbers are big endian, like
+the rest of BytesIO):
+
+    magic         4 bytes, MAGIC
+    version       u16, FORMAT_VERSION
+    features      u32, FEATURE_* flags for optional VM features the program uses
+    ops           u32, fingerprint of the op numbering
+    section count u16
+    sections      for each section: id u8, length u32, then `length` bytes
+    checksum      u32, CRC-32 of everything before it
+
+The ops fingerprint changes whenever ops are added, removed or reordered, so
+a file from a compiler with a different op set is rejected up front, instead
+of being decoded as the wrong ops. Sections with unknown ids are skipped, so
+that optional data can be added without changing the version.
+
+The program section holds the Program, as BytesIO writes it. The optional
+debug section holds the DebugInfo of the functions that have it, as a u32
+count, then for each function: its module index u32, item index u32 and
+DebugInfo.
+*/
+
+pub const MAGIC: [u8; 4] = *b"\x7fPNB";
+pub const FORMAT_VERSION: u16 = 2;
+
+pub const FEATURE_BIGINT: u32 = 1;
+
+/// the features that this VM was built with
+const SUPPORTED_FEATURES: u32 = if cfg!(feature = "bigint") {
+    FEATURE_BIGINT
+} else {
+    0
+};
+
+pub const SECTION_PROGRAM: u8 = 0;
+pub const SECTION_DEBUG: u8 = 1;
+
+const HEADER_LEN: usize = 4 + 2 + 4 + 4 + 2;
+
+#[derive(Debug)]
+pub enum PnbError {
+    BadMagic,
+    Version(u16),
+    OpsMismatch,
+    Features(u32),
+    Checksum,
+    Truncated,
+    MissingSection(u8),
+    /// a section couldn't be decoded
+    InvalidData,
+    /// the program was decoded, but failed `Program::verify`
+    Verify(VerifyError),
+}
+
+/// An error from reading a `.pnb` file, with the offset in the file where it
+/// was found.
+#[derive(Debug)]
+pub struct LoadError {
+    pub offset: usize,
+    pub error: PnbError,
+}
+
+impl LoadError {
+    fn new(offset: usize, error: PnbError) -> LoadError {
+        LoadError { offset, error }
+    }
+}
+
+impl fmt::Display for PnbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PnbError::BadMagic => write!(f, "not a .pnb file"),
+            PnbError::Version(v) => write!(
+                f,
+                "file has format version {}, but this VM reads version {}",
+                v, FORMAT_VERSION
+            ),
+            PnbError::OpsMismatch => {
+                write!(
+                    f,
+                    "file was built by a compiler with a different set of ops"
+                )
+            }
+            PnbError::Features(t) => {
+                write!(f, "file needs VM features that are not enabled:")?;
+                if t & FEATURE_BIGINT != 0 {
+                    write!(f, " bigint")?;
+                }
+                if t & !FEATURE_BIGINT != 0 {
+                    write!(f, " unknown ({:#x})", t & !FEATURE_BIGINT)?;
+                }
+                Ok(())
+            }
+            PnbError::Checksum => write!(f, "checksum mismatch, the file is corrupt"),
+            PnbError::Truncated => write!(f, "file is truncated"),
+            PnbError::MissingSection(id) => write!(f, "file has no section {}", id),
+            PnbError::InvalidData => write!(f, "invalid data"),
+            PnbError::Verify(e) => write!(f, "invalid bytecode: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at offset {})", self.error, self.offset)
+    }
+}
+
+pub fn write(program: &Program) -> Vec<u8> {
+    let mut sections = vec![(SECTION_PROGRAM, encode(program))];
+    if let Some(debug) = encode_debug(program) {
+        sections.push((SECTION_DEBUG, debug));
+    }
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    out.extend_from_slice(&program.features().to_be_bytes());
+    out.extend_from_slice(&ops_fingerprint().to_be_bytes());
+    out.extend_from_slice(&(sections.len() as u16).to_be_bytes());
+    for (id, data) in sections.iter() {
+        out.push(*id);
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(data);
+    }
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_be_bytes());
+    out
+}
+
+pub fn read(b: &[u8]) -> Result<Program, LoadError> {
+    read_with(b, true)
+}
+
+/// Like `read`, but doesn't run `Program::verify`, so that tools such as the
+/// disassembler can still show invalid bytecode. The header and checksum are
+/// checked all the same.
+pub fn read_unverified(b: &[u8]) -> Result<Program, LoadError> {
+    read_with(b, false)
+}
+
+fn read_with(b: &[u8], verify: bool) -> Result<Program, LoadError> {
+    if b.len() < MAGIC.len() || b[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::new(0, PnbError::BadMagic));
+    }
+    if b.len() < HEADER_LEN + 4 {
+        return Err(LoadError::new(b.len(), PnbError::Truncated));
+    }
+    let (body, checksum) = b.split_at(b.len() - 4);
+    let version = u16::from_be_bytes([body[4], body[5]]);
+    if version != FORMAT_VERSION {
+        return Err(LoadError::new(4, PnbError::Version(version)));
+    }
+    // a checksum mismatch is only reported after the version, since another
+    // version might use a different checksum
+    if crc32(body) != u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+        return Err(LoadError::new(body.len(), PnbError::Checksum));
+    }
+    let features = u32::from_be_bytes([body[6], body[7], body[8], body[9]]);
+    if features & !SUPPORTED_FEATURES != 0 {
+        let missing = features & !SUPPORTED_FEATURES;
+        return Err(LoadError::new(6, PnbError::Features(missing)));
+    }
+    let ops = u32::from_be_bytes([body[10], body[11], body[12], body[13]]);
+    if ops != ops_fingerprint() {
+        return Err(LoadError::new(10, PnbError::OpsMismatch));
+    }
+    let count = u16::from_be_bytes([body[14], body[15]]);
+    let mut offset = HEADER_LEN;
+    let mut program = None;
+    let mut debug = None;
+    for _ in 0..count {
+        // a section that runs past the end is reported at its start
+        let truncated = || LoadError::new(offset, PnbError::Truncated);
+        let head = body.get(offset..offset + 5).ok_or_else(truncated)?;
+        let id = head[0];
+        let len = u32::from_be_bytes([head[1], head[2], head[3], head[4]]) as usize;
+        let data = body
+            .get(offset + 5..offset + 5 + len)
+            .ok_or_else(truncated)?;
+        offset += 5;
+        if id == SECTION_PROGRAM {
+            let p: Program = decode(data, offset)?;
+            if verify {
+                if let Err(e) = p.verify() {
+                    let offset = offset + error_offset(&p, &e);
+                    return Err(LoadError::new(offset, PnbError::Verify(e.error)));
+                }
+            }
+            program = Some(p);
+        } else if id == SECTION_DEBUG {
+            let entries: Vec<DebugEntry> = decode(data, offset)?;
+            debug = Some((offset, entries));
+        }
+        offset += len;
+    }
+    if offset != body.len() {
+        return Err(LoadError::new(offset, PnbError::InvalidData));
+    }
+    let missing = LoadError::new(offset, PnbError::MissingSection(SECTION_PROGRAM));
+    let mut program = program.ok_or(missing)?;
+    if let Some((offset, entries)) = debug {
+        for entry in entries {
+            let module = program.modules.get_mut(entry.module as usize);
+            let item = module.and_then(|m| m.items.get_mut(entry.item as usize));
+            match item {
+                Some(ModuleItem::Function(f)) => f.debug = Some(entry.info),
+                _ => return Err(LoadError::new(offset, PnbError::InvalidData)),
+            }
+        }
+    }
+    Ok(program)
+}
+
+/// The DebugInfo of a function, in the debug section.
+struct DebugEntry {
+    module: u32,
+    item: u32,
+    info: DebugInfo,
+}
+
+impl BytesIO for DebugEntry {
+    fn read<'a>(b: &'a [u8]) -> Result<(&'a [u8], Self), BytesReadError<'a>> {
+        let (b, (module, item)) = <(u32, u32) as BytesIO>::read(b)?;
+        let (b, info) = <DebugInfo as BytesIO>::read(b)?;
+        Ok((b, DebugEntry { module, item, info }))
+    }
+    fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        let b = <(u32, u32) as BytesIO>::write(&(t.module, t.item), b)?;
+        <DebugInfo as BytesIO>::write(&t.info, b)
+    }
+    fn encoded_len(t: &Self) -> usize {
+        <(u32, u32) as BytesIO>::encoded_len(&(t.module, t.item))
+            + <DebugInfo as BytesIO>::encoded_len(&t.info)
+    }
+}
+
+/// Encodes the debug section, which is the same as a `Vec<DebugEntry>`, or
+/// returns None if no function has debug info.
+fn encode_debug(program: &Program) -> Option<Vec<u8>> {
+    let mut entries = Vec::new();
+    for (m, module) in program.modules.iter().enumerate() {
+        for (i, item) in module.items.iter().enumerate() {
+            if let ModuleItem::Function(f) = item {
+                if let Some(info) = &f.debug {
+                    entries.push(((m as u32, i as u32), info));
+                }
+            }
+        }
+    }
+    if entries.is_empty() {
+        return None;
+    }
+    let mut b = encode(&(entries.len() as u32));
+    for (index, info) in entries {
+        <(u32, u32) as BytesIO>::write_to(&index, &mut b).expect("writing to a Vec doesn't fail");
+        <DebugInfo as BytesIO>::write_to(info, &mut b).expect("writing to a Vec doesn't fail");
+    }
+    Some(b)
+}
+
+fn encode<T: BytesIO>(t: &T) -> Vec<u8> {
+    let mut b = Vec::with_capacity(T::encoded_len(t));
+    T::write_to(t, &mut b).expect("writing to a Vec doesn't fail");
+    b
+}
+
+/// Decodes all of `data`, which starts at `offset` in the file.
+fn decode<T: BytesIO>(data: &[u8], offset: usize) -> Result<T, LoadError> {
+    let at = match T::read(data) {
+        Ok(([], t)) => return Ok(t),
+        Ok((rest, _)) => data.len() - rest.len(),
+        Err(BytesReadError::EndOfFile) => data.len(),
+        Err(BytesReadError::InvalidValue(rest)) => data.len() - rest.len(),
+    };
+    Err(LoadError::new(offset + at, PnbError::InvalidData))
+}
+
+/// The offset in an encoded program of the item, or op, that `e` is about.
+fn error_offset(program: &Program, e: &ItemError) -> usize {
+    // Vecs start with their length as a u32
+    let vec_len = <u32 as BytesIO>::encoded_len(&0);
+    let modules = &program.modules[..e.module];
+    let module = &program.modules[e.module];
+    let mut offset = vec_len + modules.iter().map(BytesIO::encoded_len).sum::<usize>();
+    offset += vec_len
+        + module.items[..e.item]
+            .iter()
+            .map(BytesIO::encoded_len)
+            .sum::<usize>();
+    if let (ModuleItem::Function(f), Some(op)) = (&module.items[e.item], e.error.op()) {
+        // the item tag, then the ops
+        offset += 1 + vec_len + f.ops[..op].iter().map(BytesIO::encoded_len).sum::<usize>();
+    }
+    offset
+}
+
+fn ops_fingerprint() -> u32 {
+    let mut names = Vec::new();
+    for name in CORE_OP_NAMES {
+        names.extend_from_slice(name.unwrap_or("").as_bytes());
+        names.push(b',');
+    }
+    crc32(&names)
+}
+
+/// CRC-32 (IEEE), as used by zip and png.
+fn crc32(b: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in b {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::asm::assemble;
+    use crate::bytecode::disasm::disassemble;
+
+    const LISTING: &str = "\
+module main
+    literal 1.5
+    buffer 00ff
+    moduleref 1
+    function
+        StackLoad 0
+        JumpZero done
+        StackLoad 1
+        Return
+    done:
+    end
+    export f 3
+module
+    import main f
+";
+
+    fn program() -> Program {
+        let mut program = assemble(LISTING).unwrap();
+        if let ModuleItem::Function(f) = &mut program.modules[0].items[3] {
+            f.debug = Some(DebugInfo {
+                name: "f".into(),
+                locals: vec![],
+                lines: vec![(0, 1), (2, 3)],
+            });
+        }
+        program
+    }
+
+    /// Changes the header at `at`, and fixes the checksum.
+    fn patch(b: &mut [u8], at: usize, bytes: &[u8]) {
+        b[at..at + bytes.len()].copy_from_slice(bytes);
+        let body = b.len() - 4;
+        let checksum = crc32(&b[..body]);
+        b[body..].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    fn error(b: &[u8]) -> LoadError {
+        Program::from_bytes(b).err().expect("loaded")
+    }
+
+    #[test]
+    fn programs_round_trip() {
+        let program = program();
+        let b = program.to_bytes();
+        let read = Program::from_bytes(&b).unwrap();
+        assert_eq!(disassemble(&read), disassemble(&program));
+        assert_eq!(read.to_bytes(), b);
+        match &read.modules[0].items[3] {
+            ModuleItem::Function(f) => {
+                let debug = f.debug.as_ref().unwrap();
+                assert_eq!(debug.name, "f");
+                assert_eq!(debug.lines, vec![(0, 1), (2, 3)]);
+            }
+            _ => panic!("not a function"),
+        }
+    }
+
+    #[test]
+    fn stripped_programs_have_no_debug_section() {
+        let mut program = program();
+        let with_debug = program.to_bytes();
+        program.strip_debug();
+        let b = program.to_bytes();
+        assert!(b.len() < with_debug.len());
+        assert_eq!(u16::from_be_bytes([b[14], b[15]]), 1);
+        let read = Program::from_bytes(&b).unwrap();
+        assert!(matches!(&read.modules[0].items[3], ModuleItem::Function(f) if f.debug.is_none()));
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let b = program().to_bytes();
+        for at in [HEADER_LEN + 8, b.len() - 1].iter() {
+            let mut corrupt = b.clone();
+            corrupt[*at] ^= 0x10;
+            let e = error(&corrupt);
+            assert!(matches!(e.error, PnbError::Checksum));
+            assert_eq!(e.offset, b.len() - 4);
+        }
+    }
+
+    #[test]
+    fn other_op_sets_are_rejected() {
+        let mut b = program().to_bytes();
+        let ops = ops_fingerprint().wrapping_add(1);
+        patch(&mut b, 10, &ops.to_be_bytes());
+        let e = error(&b);
+        assert!(matches!(e.error, PnbError::OpsMismatch));
+        assert_eq!(e.offset, 10);
+    }
+
+    #[test]
+    fn headers_are_checked() {
+        let b = program().to_bytes();
+        assert!(matches!(error(b"\x7fELF").error, PnbError::BadMagic));
+        assert!(matches!(error(&b[..HEADER_LEN]).error, PnbError::Truncated));
+
+        let mut other = b.clone();
+        patch(&mut other, 4, &(FORMAT_VERSION + 1).to_be_bytes());
+        assert!(matches!(error(&other).error, PnbError::Version(v) if v == FORMAT_VERSION + 1));
+
+        let mut other = b;
+        patch(&mut other, 6, &0x8000_0000u32.to_be_bytes());
+        assert!(matches!(
+            error(&other).error,
+            PnbError::Features(0x8000_0000)
+        ));
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let mut b = program().to_bytes();
+        b.truncate(b.len() - 4);
+        let count = u16::from_be_bytes([b[14], b[15]]) + 1;
+        b[14..16].copy_from_slice(&count.to_be_bytes());
+        b.extend_from_slice(&[200, 0, 0, 0, 2, 0xab, 0xcd]);
+        let checksum = crc32(&b);
+        b.extend_from_slice(&checksum.to_be_bytes());
+        assert!(Program::from_bytes(&b).is_ok());
+    }
+
+    #[test]
+    fn unverified_programs_are_rejected_at_the_op() {
+        let src = "module\n  function\n    StackLoad 0\n    JumpZero a\n    StackLoad 0\n  a:\n    Return\n  end\n";
+        let b = assemble(src).unwrap().to_bytes();
+        let e = error(&b);
+        assert!(matches!(
+            e.error,
+            PnbError::Verify(VerifyError::StackDepth { op: 3, .. })
+        ));
+        // the header, the section head, the module count, the item count, the
+        // item tag, the op count, then StackLoad, JumpZero and StackLoad
+        assert_eq!(e.offset, HEADER_LEN + 5 + 4 + 4 + 1 + 4 + 2 + 5 + 2);
+        // but can still be read without verifying
+        let program = Program::from_bytes_unverified(&b).unwrap();
+        assert_eq!(program.to_bytes(), b);
+    }
+
+    #[test]
+    fn damaged_files_are_errors_instead_of_panics() {
+        let b = program().to_bytes();
+        for len in 0..b.len() {
+            assert!(Program::from_bytes(&b[..len]).is_err());
+        }
+        for at in MAGIC.len()..b.len() - 4 {
+            for bit in 0..8 {
+                let mut damaged = b.clone();
+                damaged[at] ^= 1 << bit;
+                patch(&mut damaged, at, &[]);
+                let _ = Program::from_bytes(&damaged);
+            }
+        }
+    }
+
+    #[test]
+    fn crc32_matches_the_standard() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
@@ -1,4 +1,7 @@
-use super::{BytesIO, BytesReadError, Module};
+use super::link::{Externs, LinkError, NoExterns, Target};
+#[cfg(feature = "bigint")]
+use super::{ops::LiteralValue, Op};
+use super::{pnb, pnb::LoadError, BytesIO, BytesReadError, Module, ModuleItem};
 
 use crate::datamodel::{Tuple, Value};
 
@@ -7,7 +10,15 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn into_tuple(self) -> Tuple {
+    /// Links the program, and creates a tuple of the tuples of its modules.
+    pub fn into_tuple(self) -> Result<Tuple, LinkError> {
+        self.into_tuple_with(&mut NoExterns)
+    }
+
+    /// Like `into_tuple`, but imports of modules that aren't in the program
+    /// are linked against `externs`.
+    pub fn into_tuple_with(self, externs: &mut dyn Externs) -> Result<Tuple, LinkError> {
+        let links = self.link_with(externs)?;
         let len = self.modules.len();
         let tuple = Tuple::empty(len);
         let mut refs = Vec::new();
@@ -16,12 +27,80 @@ impl Program {
             refs.push((module.clone(), mrefs));
             tuple.set(i, module.into());
         }
-        for (module, mrefs) in refs {
-            for (l, r) in mrefs {
-                module.set(l, tuple.get(r).unwrap_or(Value::None));
+        for (module, mrefs) in refs.iter() {
+            for &(l, r) in mrefs {
+                // the linker checked that every ModuleRef is in range
+                module.set(l, tuple.get(r).unwrap());
             }
         }
-        tuple
+        // imports resolve to modules or items that aren't imports, so they
+        // all have their values by now
+        for link in links {
+            let val = match link.target {
+                Target::Module(m) => tuple.get(m),
+                Target::Item(m, i) => refs[m].0.get(i),
+                Target::Extern(val) => Some(val),
+            };
+            refs[link.module]
+                .0
+                .set(link.item, val.unwrap_or(Value::None));
+        }
+        Ok(tuple)
+    }
+
+    /// Encodes the program as a `.pnb` file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        pnb::write(self)
+    }
+
+    /// Decodes a `.pnb` file, checking its header and checksum.
+    pub fn from_bytes(b: &[u8]) -> Result<Program, LoadError> {
+        pnb::read(b)
+    }
+
+    /// Like `from_bytes`, but doesn't verify the bytecode.
+    pub fn from_bytes_unverified(b: &[u8]) -> Result<Program, LoadError> {
+        pnb::read_unverified(b)
+    }
+
+    /// Removes the debug info of every function.
+    pub fn strip_debug(&mut self) {
+        for module in &mut self.modules {
+            for item in &mut module.items {
+                if let ModuleItem::Function(f) = item {
+                    f.debug = None;
+                }
+            }
+        }
+    }
+
+    /// The `pnb::FEATURE_*` flags for the optional VM features that this
+    /// program needs.
+    pub fn features(&self) -> u32 {
+        match self.uses_bigint() {
+            true => pnb::FEATURE_BIGINT,
+            false => 0,
+        }
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    fn uses_bigint(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "bigint")]
+    fn uses_bigint(&self) -> bool {
+        let mut items = self.modules.iter().flat_map(|m| m.items.iter());
+        items.any(|item| match item {
+            ModuleItem::LiteralValue(LiteralValue::BigInt(_)) => true,
+            ModuleItem::Function(f) => f.ops.iter().any(|op| match op {
+                Op::LiteralCreate(op) => matches!(op.value(), LiteralValue::BigInt(_)),
+                Op::PromotingAdd(_) | Op::PromotingSub(_) | Op::PromotingMul(_) => true,
+                Op::BigIntFromBuffer(_) | Op::BigIntToBuffer(_) => true,
+                _ => false,
+            }),
+            _ => false,
+        })
     }
 }
 
@@ -33,4 +112,7 @@ impl BytesIO for Program {
     fn write<'a>(t: &Self, b: &'a mut [u8]) -> Option<&'a mut [u8]> {
         <Vec<Module> as BytesIO>::write(&t.modules, b)
     }
+    fn encoded_len(t: &Self) -> usize {
+        <Vec<Module> as BytesIO>::encoded_len(&t.modules)
+    }
 }
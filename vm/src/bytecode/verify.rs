@@ -0,0 +1,350 @@
+use std::fmt;
+
+use super::{Function, ModuleItem, Op, Program};
+
+/*
+The verifier checks bytecode for errors that the VM would otherwise only find
+while running it, or not at all:
+
+- every jump lands on an op in its function, or just after the last op, which
+  returns None
+- the operand stack has the same depth at an op on every path that reaches it,
+  so a loop can't grow the stack
+- every ModuleRef refers to a module in the program
+
+That is all it guarantees. In particular, verified bytecode can still fail
+with StackEmpty: functions don't say how many args they take, and the args are
+pushed onto the stack they start with. So depths are counted from the depth at
+entry, and a function that pops its args starts by going below zero.
+`FunctionInfo::args` is how far it goes, but Call ops aren't checked against
+the function they call, which is only known at runtime, so a call with fewer
+args than that can still run out of values to pop. Local indices aren't
+checked either, so StackLoad of a local that was never stored is still a
+LocalRead error.
+*/
+
+#[derive(Debug)]
+pub enum VerifyError {
+    /// the op at `op` jumps to `target`, which isn't in the function
+    BadJump {
+        op: usize,
+        target: isize,
+    },
+    /// the stack can be `expected` or `found` values deep at `op`, depending
+    /// on how it is reached
+    StackDepth {
+        op: usize,
+        expected: isize,
+        found: isize,
+    },
+    BadModuleRef(u32),
+}
+
+impl VerifyError {
+    /// The index of the op that the error is about, if it is in a function.
+    pub fn op(&self) -> Option<usize> {
+        match self {
+            VerifyError::BadJump { op, .. } | VerifyError::StackDepth { op, .. } => Some(*op),
+            VerifyError::BadModuleRef(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::BadJump { op, target } => {
+                write!(f, "op {} jumps to {}, outside of the function", op, target)
+            }
+            VerifyError::StackDepth {
+                op,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the stack is {} deep at op {} on one path, but {} deep on another",
+                expected, op, found
+            ),
+            VerifyError::BadModuleRef(module) => {
+                write!(f, "reference to module {}, which doesn't exist", module)
+            }
+        }
+    }
+}
+
+/// A VerifyError in a program, with the item it was found in.
+#[derive(Debug)]
+pub struct ItemError {
+    pub module: usize,
+    pub item: usize,
+    pub error: VerifyError,
+}
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "module {}, item {}: {}",
+            self.module, self.item, self.error
+        )
+    }
+}
+
+pub struct FunctionInfo {
+    /// the number of values the function pops from the stack it started
+    /// with, which is the fewest args it can be called with
+    pub args: usize,
+    /// the most values that are on the stack at once, when the function is
+    /// called with `args` args
+    pub max_stack: usize,
+}
+
+enum Flow {
+    Next,
+    Jump(i32),
+    /// jumps or goes to the next op
+    Branch(i32),
+    End,
+}
+
+/// How an op changes the stack: it pops `pops` values, then pushes `pushes`
+/// values unless it jumps.
+struct Effect {
+    pops: isize,
+    pushes: isize,
+    flow: Flow,
+}
+
+fn effect(op: &Op) -> Effect {
+    let flow = match op {
+        Op::Jump(op) => Flow::Jump(op.dest),
+        Op::JumpZero(op) => Flow::Branch(op.dest),
+        Op::JumpNeg(op) => Flow::Branch(op.dest),
+        Op::JumpFalse(op) => Flow::Branch(op.dest),
+        Op::IterNext(op) => Flow::Branch(op.dest),
+        Op::Return(_) => Flow::End,
+        _ => Flow::Next,
+    };
+    let (pops, pushes) = match op {
+        Op::Add(_) | Op::Sub(_) | Op::Mul(_) | Op::Div(_) | Op::Rem(_) => (2, 1),
+        Op::WrappingAdd(_) | Op::WrappingSub(_) | Op::WrappingMul(_) => (2, 1),
+        Op::SaturatingAdd(_) | Op::SaturatingSub(_) | Op::SaturatingMul(_) => (2, 1),
+        Op::Shl(_) | Op::Shr(_) | Op::And(_) | Op::Or(_) | Op::Xor(_) => (2, 1),
+        Op::Cmp(_) | Op::CmpEq(_) | Op::CmpNe(_) | Op::CmpLt(_) | Op::CmpLe(_) => (2, 1),
+        Op::CmpGt(_) | Op::CmpGe(_) | Op::StructEq(_) | Op::StructCmp(_) => (2, 1),
+        Op::Neg(_) | Op::Not(_) | Op::StructHash(_) | Op::GetType(_) => (1, 1),
+        Op::IntToReal(_) | Op::RealToInt(_) => (1, 1),
+        Op::Floor(_) | Op::Ceil(_) | Op::Trunc(_) | Op::Round(_) => (1, 1),
+        Op::Call(op) => (op.args as isize + 1, 1),
+        Op::Return(_) => (1, 0),
+        Op::Jump(_) => (0, 0),
+        Op::JumpZero(_) | Op::JumpNeg(_) | Op::JumpFalse(_) => (1, 0),
+        Op::LiteralCreate(_) | Op::StackLoad(_) => (0, 1),
+        Op::StackCopy(_) => (1, 2),
+        Op::StackPop(_) | Op::StackStore(_) => (1, 0),
+        Op::StackSwap(_) => (1, 1),
+        Op::TupleCreate(op) => (op.items as isize, 1),
+        Op::TupleFromList(_) | Op::TupleWeakRef(_) | Op::TupleWeakUpgrade(_) => (1, 1),
+        Op::TableCreate(_) => (1, 1),
+        Op::ListCreate(op) => (op.items as isize, 1),
+        Op::ListPush(_) => (2, 0),
+        Op::ListPop(_) => (1, 1),
+        Op::ListGetSlice(_) => (3, 1),
+        Op::ListSetSlice(_) => (5, 0),
+        Op::ListInsert(_) => (3, 0),
+        Op::ListRemove(_) | Op::ListFind(_) => (2, 1),
+        Op::ListSplice(_) => (4, 1),
+        Op::ListReverse(_) | Op::ListSort(_) => (1, 0),
+        Op::ListSortBy(_) => (2, 0),
+        Op::BufferCreate(_) | Op::BufferMaterialize(_) => (1, 1),
+        Op::BufferGetSlice(_) | Op::BufferView(_) => (3, 1),
+        Op::BufferSetSlice(_) => (5, 0),
+        Op::BufferRead(_) => (2, 1),
+        Op::BufferWrite(_) => (3, 0),
+        Op::SeqLen(_) | Op::SeqToList(_) => (1, 1),
+        Op::SeqResize(_) | Op::SeqAppend(_) => (2, 0),
+        Op::SeqGet(_) => (2, 1),
+        Op::SeqSet(_) => (3, 0),
+        Op::SeqUnpack(op) => (1, op.items as isize),
+        Op::IterNew(_) | Op::IterNext(_) => (1, 1),
+        Op::RangeCreate(_) | Op::RangeCreateInclusive(_) => (3, 1),
+        #[cfg(feature = "bigint")]
+        Op::PromotingAdd(_) | Op::PromotingSub(_) | Op::PromotingMul(_) => (2, 1),
+        #[cfg(feature = "bigint")]
+        Op::BigIntFromBuffer(_) | Op::BigIntToBuffer(_) => (1, 1),
+    };
+    Effect { pops, pushes, flow }
+}
+
+impl Function {
+    pub fn verify(&self) -> Result<FunctionInfo, VerifyError> {
+        let len = self.ops.len();
+        // the depth of the stack before each op, from the depth at entry
+        let mut depths: Vec<Option<isize>> = vec![None; len];
+        let mut todo = Vec::new();
+        if len > 0 {
+            depths[0] = Some(0);
+            todo.push(0);
+        }
+        let (mut lowest, mut highest) = (0, 0);
+        while let Some(i) = todo.pop() {
+            let depth = depths[i].unwrap();
+            let effect = effect(&self.ops[i]);
+            let popped = depth - effect.pops;
+            let pushed = popped + effect.pushes;
+            lowest = lowest.min(popped);
+            highest = highest.max(pushed);
+            let mut visit = |target: isize, depth: isize| {
+                if target < 0 || target > len as isize {
+                    return Err(VerifyError::BadJump { op: i, target });
+                }
+                // running past the last op returns, whatever is on the stack
+                let target = target as usize;
+                match depths.get(target) {
+                    Some(None) => {
+                        depths[target] = Some(depth);
+                        todo.push(target);
+                    }
+                    Some(&Some(expected)) if expected != depth => {
+                        return Err(VerifyError::StackDepth {
+                            op: target,
+                            expected,
+                            found: depth,
+                        });
+                    }
+                    _ => {}
+                }
+                Ok(())
+            };
+            let next = i as isize + 1;
+            // jumps are relative to the jump op
+            match effect.flow {
+                Flow::Next => visit(next, pushed)?,
+                Flow::Jump(dest) => visit(i as isize + dest as isize, popped)?,
+                Flow::Branch(dest) => {
+                    visit(i as isize + dest as isize, popped)?;
+                    visit(next, pushed)?;
+                }
+                Flow::End => {}
+            }
+        }
+        Ok(FunctionInfo {
+            args: -lowest as usize,
+            max_stack: (highest - lowest) as usize,
+        })
+    }
+}
+
+impl Program {
+    /// Verifies every function, and checks that every ModuleRef refers to a
+    /// module in the program.
+    pub fn verify(&self) -> Result<(), ItemError> {
+        for (m, module) in self.modules.iter().enumerate() {
+            for (i, item) in module.items.iter().enumerate() {
+                let result = match item {
+                    ModuleItem::Function(f) => f.verify().map(|_| ()),
+                    ModuleItem::ModuleRef(r) if *r as usize >= self.modules.len() => {
+                        Err(VerifyError::BadModuleRef(*r))
+                    }
+                    _ => Ok(()),
+                };
+                result.map_err(|error| ItemError {
+                    module: m,
+                    item: i,
+                    error,
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::asm::assemble;
+
+    fn function(ops: &str) -> Function {
+        let src = format!("module\n  function\n{}\n  end\n", ops);
+        let mut program = assemble(&src).unwrap();
+        match program.modules.remove(0).items.remove(0) {
+            ModuleItem::Function(f) => f,
+            _ => panic!("not a function"),
+        }
+    }
+
+    #[test]
+    fn args_and_max_stack_are_counted() {
+        // returns the sum of an arg and the product of two more
+        let info = function("Mul\n Add\n Return").verify().unwrap();
+        assert_eq!(info.args, 3);
+        assert_eq!(info.max_stack, 3);
+
+        let info = function("StackLoad 0\n StackLoad 0\n StackLoad 0\n TupleCreate 3\n Return")
+            .verify()
+            .unwrap();
+        assert_eq!(info.args, 0);
+        assert_eq!(info.max_stack, 3);
+        assert_eq!(function("").verify().unwrap().max_stack, 0);
+    }
+
+    #[test]
+    fn loops_with_the_same_depth_are_valid() {
+        let f =
+            function("StackLoad 0\n top:\n StackCopy\n JumpZero out\n Jump top\n out:\n Return");
+        let info = f.verify().unwrap();
+        assert_eq!(info.args, 0);
+        assert_eq!(info.max_stack, 2);
+    }
+
+    #[test]
+    fn growing_loops_are_rejected() {
+        let f = function("top:\n StackLoad 0\n Jump top");
+        assert!(matches!(
+            f.verify(),
+            Err(VerifyError::StackDepth {
+                op: 0,
+                expected: 0,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn jumps_must_stay_in_the_function() {
+        let mut f = function("Jump end\n end:");
+        assert!(f.verify().is_ok());
+        if let Op::Jump(op) = &mut f.ops[0] {
+            op.dest = 2;
+        }
+        assert!(matches!(
+            f.verify(),
+            Err(VerifyError::BadJump { op: 0, target: 2 })
+        ));
+        if let Op::Jump(op) = &mut f.ops[0] {
+            op.dest = -1;
+        }
+        assert!(matches!(
+            f.verify(),
+            Err(VerifyError::BadJump { op: 0, target: -1 })
+        ));
+    }
+
+    #[test]
+    fn module_refs_are_checked() {
+        let program =
+            assemble("module\n  moduleref 0\nmodule\n  literal 1\n  moduleref 2\n").unwrap();
+        let e = program.verify().unwrap_err();
+        assert_eq!((e.module, e.item), (1, 1));
+        assert!(matches!(e.error, VerifyError::BadModuleRef(2)));
+        assert_eq!(e.error.op(), None);
+    }
+
+    #[test]
+    fn programs_report_the_item() {
+        let program = assemble("module\n  literal 1\n  function\n    StackLoad 0\n    JumpZero a\n    StackLoad 0\n  a:\n    Return\n  end\n").unwrap();
+        let e = program.verify().unwrap_err();
+        assert_eq!((e.module, e.item), (0, 1));
+        assert_eq!(e.error.op(), Some(3));
+    }
+}
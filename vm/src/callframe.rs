@@ -27,7 +27,9 @@ impl CallFrame {
     }
 
     pub fn jump(&mut self, index: i32) {
-        self.cursor = (self.cursor as isize + index as isize) as usize;
+        // jumps are relative to the jump op, but the cursor has already
+        // been moved past it
+        self.cursor = (self.cursor as isize - 1 + index as isize) as usize;
     }
 
     pub fn exec(&mut self) -> Result<OpAction, OpError> {
@@ -39,3 +41,52 @@ impl CallFrame {
         op.exec(&mut self.stack)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::{ops, Op};
+    use crate::datamodel::{Function, Tuple, Value};
+    use crate::VirtualMachine;
+
+    fn run(ops: Vec<Op>) -> Value {
+        let function = Function::new(Tuple::empty(0), ops);
+        VirtualMachine::call(function.into(), Vec::new()).unwrap()
+    }
+
+    fn int(t: i64) -> Op {
+        ops::LiteralCreate::new(t.into()).into()
+    }
+
+    #[test]
+    fn jumps_are_relative_to_the_jump_op() {
+        // forward over the first return
+        let ops = vec![
+            ops::Jump::new(3).into(),
+            int(1),
+            ops::Return.into(),
+            int(2),
+            ops::Return.into(),
+        ];
+        assert!(matches!(run(ops), Value::Integer(2)));
+        // forward, then back to the first return
+        let ops = vec![
+            ops::Jump::new(3).into(),
+            int(1),
+            ops::Return.into(),
+            ops::Jump::new(-2).into(),
+        ];
+        assert!(matches!(run(ops), Value::Integer(1)));
+        // a conditional jump that isn't taken, then one that is
+        let ops = vec![
+            int(1),
+            ops::JumpZero::new(4).into(),
+            int(0),
+            ops::JumpZero::new(3).into(),
+            int(1),
+            ops::Return.into(),
+            int(2),
+            ops::Return.into(),
+        ];
+        assert!(matches!(run(ops), Value::Integer(2)));
+    }
+}
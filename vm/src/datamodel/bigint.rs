@@ -0,0 +1,23 @@
+use num_traits::ToPrimitive;
+
+use super::{BigInt, Value};
+
+impl Value {
+    /// Integers that fit in an `Integer` are always stored as one, so a
+    /// `Value::BigInt` made here is never in `Integer::MIN..=Integer::MAX`.
+    pub fn from_bigint(t: BigInt) -> Value {
+        match t.to_i64() {
+            Some(t) => Value::Integer(t),
+            None => Value::BigInt(t),
+        }
+    }
+
+    /// Returns `Integer`s and `BigInt`s as a `BigInt`.
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Integer(t) => Some(BigInt::from(*t)),
+            Value::BigInt(t) => Some(t.clone()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Integer, Real, Value, ValueType};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+/// The fixed size numbers that can be read from and written to bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl fmt::Display for NumType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for ByteOrder {
+    type Err = ();
+    fn from_str(s: &str) -> Result<ByteOrder, ()> {
+        Ok(match s {
+            "Big" => ByteOrder::Big,
+            "Little" => ByteOrder::Little,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl FromStr for NumType {
+    type Err = ();
+    fn from_str(s: &str) -> Result<NumType, ()> {
+        Ok(match s {
+            "U8" => NumType::U8,
+            "U16" => NumType::U16,
+            "U32" => NumType::U32,
+            "U64" => NumType::U64,
+            "I8" => NumType::I8,
+            "I16" => NumType::I16,
+            "I32" => NumType::I32,
+            "I64" => NumType::I64,
+            "F32" => NumType::F32,
+            "F64" => NumType::F64,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum NumError {
+    /// there are fewer bytes than the size of the number
+    OutOfBounds,
+    /// the number doesn't fit in the value, or the value in the number
+    Overflow,
+    BadType(ValueType),
+}
+
+macro_rules! decode {
+    ($t:ty, $b:expr, $order:expr) => {{
+        let b = $b
+            .get(..std::mem::size_of::<$t>())
+            .ok_or(NumError::OutOfBounds)?;
+        let b = b.try_into().unwrap();
+        match $order {
+            ByteOrder::Big => <$t>::from_be_bytes(b),
+            ByteOrder::Little => <$t>::from_le_bytes(b),
+        }
+    }};
+}
+
+macro_rules! encode {
+    ($t:expr, $b:expr, $order:expr) => {{
+        let t = $t;
+        let bytes = match $order {
+            ByteOrder::Big => t.to_be_bytes(),
+            ByteOrder::Little => t.to_le_bytes(),
+        };
+        let b = $b.get_mut(..bytes.len()).ok_or(NumError::OutOfBounds)?;
+        b.copy_from_slice(&bytes);
+    }};
+}
+
+impl NumType {
+    pub fn size(self) -> usize {
+        match self {
+            NumType::U8 | NumType::I8 => 1,
+            NumType::U16 | NumType::I16 => 2,
+            NumType::U32 | NumType::I32 | NumType::F32 => 4,
+            NumType::U64 | NumType::I64 | NumType::F64 => 8,
+        }
+    }
+
+    /// Reads a number from the start of `b`. U64s above `Integer::MAX` are
+    /// an overflow, unless the `bigint` feature is enabled.
+    pub fn read(self, b: &[u8], order: ByteOrder) -> Result<Value, NumError> {
+        Ok(match self {
+            NumType::U8 => (decode!(u8, b, order) as Integer).into(),
+            NumType::U16 => (decode!(u16, b, order) as Integer).into(),
+            NumType::U32 => (decode!(u32, b, order) as Integer).into(),
+            NumType::U64 => {
+                let t = decode!(u64, b, order);
+                match Integer::try_from(t) {
+                    Ok(t) => t.into(),
+                    #[cfg(feature = "bigint")]
+                    Err(_) => Value::BigInt(t.into()),
+                    #[cfg(not(feature = "bigint"))]
+                    Err(_) => return Err(NumError::Overflow),
+                }
+            }
+            NumType::I8 => (decode!(i8, b, order) as Integer).into(),
+            NumType::I16 => (decode!(i16, b, order) as Integer).into(),
+            NumType::I32 => (decode!(i32, b, order) as Integer).into(),
+            NumType::I64 => decode!(i64, b, order).into(),
+            NumType::F32 => (decode!(f32, b, order) as Real).into(),
+            NumType::F64 => decode!(f64, b, order).into(),
+        })
+    }
+
+    /// Writes a number to the start of `b`. Integers that are out of range
+    /// for the type are an overflow; Reals can only be written as F32 or F64.
+    pub fn write(self, b: &mut [u8], order: ByteOrder, val: &Value) -> Result<(), NumError> {
+        match self {
+            NumType::F32 => encode!(real(val)? as f32, b, order),
+            NumType::F64 => encode!(real(val)?, b, order),
+            NumType::U8 => encode!(int::<u8>(val)?, b, order),
+            NumType::U16 => encode!(int::<u16>(val)?, b, order),
+            NumType::U32 => encode!(int::<u32>(val)?, b, order),
+            NumType::U64 => encode!(int::<u64>(val)?, b, order),
+            NumType::I8 => encode!(int::<i8>(val)?, b, order),
+            NumType::I16 => encode!(int::<i16>(val)?, b, order),
+            NumType::I32 => encode!(int::<i32>(val)?, b, order),
+            NumType::I64 => encode!(int::<i64>(val)?, b, order),
+        }
+        Ok(())
+    }
+}
+
+fn int<T: TryFrom<i128>>(val: &Value) -> Result<T, NumError> {
+    let t = match val {
+        Value::Integer(t) => *t as i128,
+        #[cfg(feature = "bigint")]
+        Value::BigInt(t) => num_traits::ToPrimitive::to_i128(t).ok_or(NumError::Overflow)?,
+        _ => return Err(NumError::BadType(val.get_type())),
+    };
+    T::try_from(t).map_err(|_| NumError::Overflow)
+}
+
+fn real(val: &Value) -> Result<Real, NumError> {
+    match val {
+        Value::Real(t) => Ok(*t),
+        Value::Integer(t) => Ok(*t as Real),
+        _ => Err(NumError::BadType(val.get_type())),
+    }
+}
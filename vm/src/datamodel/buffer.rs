@@ -1,17 +1,28 @@
 use std::cell::{Ref, RefCell};
+use std::ops::Range;
 use std::rc::Rc;
 
-use super::{Identity, Value};
+use super::{ByteOrder, Identity, NumError, NumType, Value};
 
+/// A byte buffer, or a view of a range of another buffer's bytes.
+///
+/// A view shares storage with its base, so writes through either one are seen
+/// by both. The range of a view is fixed when it is created; if the base is
+/// later shrunk, the view only sees the part of its range that still exists,
+/// and if the base grows again, it sees the new bytes. Views can't be resized
+/// or appended to themselves, and have an identity distinct from their base.
 #[derive(Clone)]
 pub struct Buffer {
     items: Rc<RefCell<Vec<u8>>>,
+    /// offset and length of the range of `items` this view covers
+    view: Option<Rc<(usize, usize)>>,
 }
 
 impl Buffer {
     pub fn new(v: Vec<u8>) -> Buffer {
         Buffer {
             items: Rc::new(RefCell::new(v)),
+            view: None,
         }
     }
 
@@ -19,41 +30,81 @@ impl Buffer {
         Buffer::new(Vec::new())
     }
 
+    /// The range of the storage that is visible through this buffer.
+    fn range(&self, items: &[u8]) -> Range<usize> {
+        match self.view.as_deref() {
+            Some(&(offset, len)) => {
+                let start = offset.min(items.len());
+                start..(offset + len).min(items.len())
+            }
+            None => 0..items.len(),
+        }
+    }
+
+    pub fn is_view(&self) -> bool {
+        self.view.is_some()
+    }
+
+    /// Returns true if both buffers are, or are views of, the same storage.
+    pub fn shares_storage(&self, other: &Buffer) -> bool {
+        Rc::ptr_eq(&self.items, &other.items)
+    }
+
     pub fn as_slice(&self) -> Ref<[u8]> {
-        Ref::map(self.items.borrow(), |items| &items[..])
+        Ref::map(self.items.borrow(), |items| &items[self.range(items)])
     }
 
     pub fn len(&self) -> usize {
-        self.items.borrow().len()
+        self.as_slice().len()
     }
 
-    pub fn resize(&self, len: usize) {
+    /// Returns None if this is a view.
+    pub fn resize(&self, len: usize) -> Option<()> {
+        if self.is_view() {
+            return None;
+        }
         let mut items = self.items.borrow_mut();
         items.resize(len, 0);
+        Some(())
     }
 
     pub fn get(&self, index: usize) -> Option<Value> {
-        let items = self.items.borrow();
-        let item = items.get(index)?;
-        Some(Value::Integer(*item as i64))
+        let item = *self.as_slice().get(index)?;
+        Some(Value::Integer(item as i64))
     }
 
     pub fn set(&self, index: usize, value: u8) -> Option<Value> {
         let mut items = self.items.borrow_mut();
-        let item = items.get_mut(index)?;
+        let range = self.range(&items);
+        let item = items[range].get_mut(index)?;
         let tmp = *item;
         *item = value;
         Some(Value::Integer(tmp as i64))
     }
 
+    /// Copies the bytes in `a..b` to a new buffer.
     pub fn get_slice(&self, a: usize, b: usize) -> Option<Buffer> {
-        let items = self.items.borrow();
-        let v = items.get(a..b)?.to_vec();
+        let v = self.as_slice().get(a..b)?.to_vec();
+        Some(Buffer::new(v))
+    }
+
+    /// Returns a view of the bytes in `a..b`, without copying them.
+    pub fn view(&self, a: usize, b: usize) -> Option<Buffer> {
+        let range = self.range(&self.items.borrow());
+        if a > b || b > range.len() {
+            return None;
+        }
         Some(Buffer {
-            items: Rc::new(RefCell::new(v)),
+            items: self.items.clone(),
+            view: Some(Rc::new((range.start + a, b - a))),
         })
     }
 
+    /// Copies the bytes visible through this buffer to a new buffer.
+    pub fn materialize(&self) -> Buffer {
+        Buffer::new(self.as_slice().to_vec())
+    }
+
     pub fn set_slice(
         &self,
         src: &Buffer,
@@ -61,29 +112,70 @@ impl Buffer {
         offset: usize,
         len: usize,
     ) -> Option<()> {
+        let src_end = src_offset.checked_add(len)?;
+        let end = offset.checked_add(len)?;
         let mut items = self.items.borrow_mut();
-        if Rc::ptr_eq(&self.items, &src.items) {
-            if len + src_offset > items.len() || len + offset > items.len() {
+        let dst_range = self.range(&items);
+        if self.shares_storage(src) {
+            let src_range = src.range(&items);
+            if src_end > src_range.len() || end > dst_range.len() {
                 return None;
             }
+            let (src_offset, offset) = (src_range.start + src_offset, dst_range.start + offset);
             items.copy_within(src_offset..len + src_offset, offset);
         } else {
-            let dst = items.get_mut(offset..len + offset)?;
-            dst.copy_from_slice(src.items.borrow().get(src_offset..len + src_offset)?);
+            let dst = items[dst_range].get_mut(offset..end)?;
+            dst.copy_from_slice(src.as_slice().get(src_offset..src_end)?);
         }
         Some(())
     }
 
-    pub fn append(&self, t: &[u8]) {
+    pub fn read_num(
+        &self,
+        offset: usize,
+        ty: NumType,
+        order: ByteOrder,
+    ) -> Result<Value, NumError> {
+        let items = self.as_slice();
+        ty.read(items.get(offset..).ok_or(NumError::OutOfBounds)?, order)
+    }
+
+    pub fn write_num(
+        &self,
+        offset: usize,
+        ty: NumType,
+        order: ByteOrder,
+        val: &Value,
+    ) -> Result<(), NumError> {
+        let mut items = self.items.borrow_mut();
+        let range = self.range(&items);
+        ty.write(
+            items[range]
+                .get_mut(offset..)
+                .ok_or(NumError::OutOfBounds)?,
+            order,
+            val,
+        )
+    }
+
+    /// Returns None if this is a view.
+    pub fn append(&self, t: &[u8]) -> Option<()> {
+        if self.is_view() {
+            return None;
+        }
         let mut b = self.items.borrow_mut();
         let len = b.len();
         b.resize(len + t.len(), 0);
         b[len..].copy_from_slice(t);
+        Some(())
     }
 }
 
 impl Identity for Buffer {
     fn identity(&self) -> usize {
-        Rc::as_ptr(&self.items) as usize
+        match &self.view {
+            Some(view) => Rc::as_ptr(view) as usize,
+            None => Rc::as_ptr(&self.items) as usize,
+        }
     }
 }
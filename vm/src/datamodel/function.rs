@@ -8,6 +8,8 @@ use super::{Identity, Tuple};
 pub struct Function {
     pub module: Tuple,
     pub ops: Rc<[Op]>,
+    /// the name from the function's DebugInfo, if it had any
+    pub name: Option<Rc<str>>,
 }
 
 impl Function {
@@ -15,6 +17,7 @@ impl Function {
         Function {
             module,
             ops: Rc::from(ops),
+            name: None,
         }
     }
 }
@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Buffer, Identity, List, Range, Table, Tuple, Value};
+
+enum Source {
+    Tuple(Tuple, usize),
+    List(List, usize),
+    Buffer(Buffer, usize),
+    Range(Range, u128),
+    /// the smallest key that hasn't been visited yet, or None once every key
+    /// has been visited
+    Table(Table, Option<u64>),
+}
+
+/// A cursor over a sequence. The sequence isn't copied, so items that are
+/// added or changed ahead of the cursor will be seen. Tables are visited in
+/// key order, as `(key, value)` tuples.
+#[derive(Clone)]
+pub struct Iter {
+    source: Rc<RefCell<Source>>,
+}
+
+impl Iter {
+    /// Returns None if `val` can't be iterated. Iterating an Iter continues
+    /// from its current position.
+    pub fn new(val: &Value) -> Option<Iter> {
+        let source = match val {
+            Value::Tuple(t) => Source::Tuple(t.clone(), 0),
+            Value::List(t) => Source::List(t.clone(), 0),
+            Value::Buffer(t) => Source::Buffer(t.clone(), 0),
+            Value::Range(t) => Source::Range(*t, 0),
+            Value::Table(t) => Source::Table(t.clone(), Some(0)),
+            Value::Iter(t) => return Some(t.clone()),
+            _ => return None,
+        };
+        Some(Iter {
+            source: Rc::new(RefCell::new(source)),
+        })
+    }
+
+    pub fn next(&self) -> Option<Value> {
+        let mut source = self.source.borrow_mut();
+        match &mut *source {
+            Source::Tuple(t, i) => next_index(i, |i| t.get(i)),
+            Source::List(t, i) => next_index(i, |i| t.get(i)),
+            Source::Buffer(t, i) => next_index(i, |i| t.get(i)),
+            Source::Range(t, i) => {
+                let val = t.get(*i)?;
+                *i += 1;
+                Some(val.into())
+            }
+            Source::Table(t, next) => {
+                let (key, val) = t.next_entry((*next)?)?;
+                *next = key.checked_add(1);
+                let key = Value::Integer(key as i64);
+                Some(Tuple::from_iter(vec![key, val].into_iter()).into())
+            }
+        }
+    }
+}
+
+fn next_index(i: &mut usize, get: impl FnOnce(usize) -> Option<Value>) -> Option<Value> {
+    let val = get(*i)?;
+    *i += 1;
+    Some(val)
+}
+
+impl Identity for Iter {
+    fn identity(&self) -> usize {
+        Rc::as_ptr(&self.source) as usize
+    }
+}
@@ -1,4 +1,6 @@
 use std::cell::{Ref, RefCell};
+use std::cmp::Ordering;
+use std::convert::Infallible;
 use std::mem;
 use std::rc::Rc;
 
@@ -54,27 +56,77 @@ impl List {
         })
     }
 
-    // pub fn set_slice(
-    //     &self,
-    //     src: &List,
-    //     src_offset: usize,
-    //     offset: usize,
-    //     len: usize,
-    // ) -> Option<()> {
-    //     let mut items = self.items.borrow_mut();
-    //     if Rc::ptr_eq(&self.items, &src.items) {
-    //         if len + src_offset > items.len() || len + offset > items.len() {
-    //             return None;
-    //         }
-    //         for i in 0..len {
-    //             items[offset + i] = items[src_offset + i].clone();
-    //         }
-    //     } else {
-    //         let dst = items.get_mut(offset..len + offset)?;
-    //         dst.clone_from_slice(src.items.borrow().get(src_offset..len + src_offset)?);
-    //     }
-    //     Some(())
-    // }
+    pub fn set_slice(
+        &self,
+        src: &List,
+        src_offset: usize,
+        offset: usize,
+        len: usize,
+    ) -> Option<()> {
+        let src_end = src_offset.checked_add(len)?;
+        let end = offset.checked_add(len)?;
+        if Rc::ptr_eq(&self.items, &src.items) {
+            let mut items = self.items.borrow_mut();
+            let tmp = items.get(src_offset..src_end)?.to_vec();
+            items.get_mut(offset..end)?.clone_from_slice(&tmp);
+        } else {
+            let src = src.items.borrow();
+            let mut items = self.items.borrow_mut();
+            let dst = items.get_mut(offset..end)?;
+            dst.clone_from_slice(src.get(src_offset..src_end)?);
+        }
+        Some(())
+    }
+
+    pub fn insert(&self, index: usize, val: Value) -> Option<()> {
+        let mut items = self.items.borrow_mut();
+        if index > items.len() {
+            return None;
+        }
+        items.insert(index, val);
+        Some(())
+    }
+
+    pub fn remove(&self, index: usize) -> Option<Value> {
+        let mut items = self.items.borrow_mut();
+        if index >= items.len() {
+            return None;
+        }
+        Some(items.remove(index))
+    }
+
+    /// Replaces the items in `a..b` with `src`, and returns the old items.
+    pub fn splice(&self, a: usize, b: usize, src: Vec<Value>) -> Option<Vec<Value>> {
+        let mut items = self.items.borrow_mut();
+        if a > b || b > items.len() {
+            return None;
+        }
+        Some(items.splice(a..b, src).collect())
+    }
+
+    pub fn reverse(&self) {
+        self.items.borrow_mut().reverse();
+    }
+
+    /// Sorts a copy of the items, then replaces the items with it, so `cmp`
+    /// can safely look at this list. The sort is stable, and `cmp` doesn't
+    /// have to be a total order; if it isn't, the order is unspecified.
+    pub fn sort_by(&self, mut cmp: impl FnMut(&Value, &Value) -> Ordering) {
+        let sorted: Result<(), Infallible> = self.try_sort_by(|a, b| Ok(cmp(a, b)));
+        sorted.unwrap_or_else(|e| match e {})
+    }
+
+    /// Like `sort_by`, but stops at the first error from `cmp` and returns
+    /// it, leaving the items as they were.
+    pub fn try_sort_by<E>(
+        &self,
+        mut cmp: impl FnMut(&Value, &Value) -> Result<Ordering, E>,
+    ) -> Result<(), E> {
+        let items = self.as_slice().to_vec();
+        let items = merge_sort(items, &mut cmp)?;
+        *self.items.borrow_mut() = items;
+        Ok(())
+    }
 
     pub fn push(&self, val: Value) {
         self.items.borrow_mut().push(val);
@@ -89,8 +141,108 @@ impl List {
     }
 }
 
+/// A stable merge sort. Unlike `slice::sort_by`, it can't panic or lose items
+/// when `cmp` isn't a total order, such as when it comes from a script.
+fn merge_sort<E>(
+    mut items: Vec<Value>,
+    cmp: &mut impl FnMut(&Value, &Value) -> Result<Ordering, E>,
+) -> Result<Vec<Value>, E> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, cmp)?;
+    let right = merge_sort(right, cmp)?;
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // equal items are taken from the left, to keep their order
+        let next = match cmp(a, b)? {
+            Ordering::Greater => right.next(),
+            _ => left.next(),
+        };
+        out.extend(next);
+    }
+    out.extend(left);
+    out.extend(right);
+    Ok(out)
+}
+
 impl Identity for List {
     fn identity(&self) -> usize {
         Rc::as_ptr(&self.items) as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    fn ints(list: &List) -> Vec<i64> {
+        let items = list.as_slice();
+        items
+            .iter()
+            .map(|t| t.clone().try_into().unwrap())
+            .collect()
+    }
+
+    fn list(items: &[i64]) -> List {
+        List::new(items.iter().map(|&t| Value::Integer(t)).collect())
+    }
+
+    #[test]
+    fn sort_is_stable() {
+        let t = list(&[3, 10, 2, 13, 1, 12]);
+        // compares by the last digit only
+        t.sort_by(|a, b| {
+            let (a, b): (i64, i64) = (a.clone().try_into().unwrap(), b.clone().try_into().unwrap());
+            (a % 10).cmp(&(b % 10))
+        });
+        assert_eq!(ints(&t), [10, 1, 2, 12, 3, 13]);
+    }
+
+    #[test]
+    fn sort_tolerates_inconsistent_cmp() {
+        let items: Vec<i64> = (0..100).collect();
+        let t = list(&items);
+        let mut n = 0;
+        t.sort_by(|_, _| {
+            n += 1;
+            [Ordering::Less, Ordering::Greater, Ordering::Equal][n % 3]
+        });
+        let mut sorted = ints(&t);
+        sorted.sort_unstable();
+        assert_eq!(sorted, items);
+    }
+
+    #[test]
+    fn sort_stops_at_error() {
+        let t = list(&[3, 1, 2]);
+        let mut n = 0;
+        let sorted = t.try_sort_by(|a, b| {
+            n += 1;
+            match n {
+                1 => Ok(a.total_cmp(b)),
+                _ => Err(n),
+            }
+        });
+        assert_eq!(sorted, Err(2));
+        assert_eq!(ints(&t), [3, 1, 2]);
+    }
+
+    #[test]
+    fn set_slice_checks_bounds() {
+        let t = list(&[1, 2, 3]);
+        let src = list(&[4, 5]);
+        assert_eq!(t.set_slice(&src, 0, usize::MAX, 2), None);
+        assert_eq!(t.set_slice(&src, usize::MAX, 0, 2), None);
+        assert_eq!(t.set_slice(&t, 2, 0, 2), None);
+        assert_eq!(t.set_slice(&src, 0, 1, 2), Some(()));
+        assert_eq!(ints(&t), [1, 4, 5]);
+        assert_eq!(t.set_slice(&t, 1, 0, 2), Some(()));
+        assert_eq!(ints(&t), [4, 5, 5]);
+    }
+}
@@ -1,13 +1,29 @@
+#[cfg(feature = "bigint")]
+mod bigint;
+mod binary;
 mod buffer;
 mod function;
+mod iter;
 mod list;
+mod pretty;
+mod range;
+#[cfg(feature = "serde")]
+mod serde;
+mod structural;
 mod table;
 mod tuple;
 mod value;
 
+pub use binary::{ByteOrder, NumError, NumType};
 pub use buffer::Buffer;
 pub use function::Function;
+pub use iter::Iter;
 pub use list::List;
+pub use pretty::PrettyPrinter;
+pub use range::Range;
+pub use structural::StableHasher;
 pub use table::Table;
 pub use tuple::{Tuple, TupleWeak};
-pub use value::{Identity, Integer, NativeFn, Real, Unknown, Value, ValueTryIntoError, ValueType};
+#[cfg(feature = "bigint")]
+pub use value::BigInt;
+pub use value::{Bool, Identity, Integer, NativeFn, Real, Unknown, Value, ValueTryIntoError, ValueType};
@@ -0,0 +1,326 @@
+use std::fmt::{self, Write};
+
+use super::{Buffer, Function, Identity, List, Range, Table, Tuple, TupleWeak, Value};
+
+/// Formats values for humans. Nested aggregates that contain themselves are
+/// printed once, with a `#n ` label in front, and every place that refers back
+/// to them prints `<cycle #n>` instead. Unknown values print as `<unknown>`,
+/// since all that is known of them is that they are `dyn Any`, which has no
+/// type name.
+#[derive(Clone, Copy)]
+pub struct PrettyPrinter {
+    /// spaces per nesting level; zero prints everything on a single line
+    pub indent: usize,
+    /// aggregates nested deeper than this are elided as `(..)`, `[..]` or `{..}`
+    pub max_depth: usize,
+    /// items of an aggregate past this count are elided as `..`
+    pub max_items: usize,
+    /// the most items printed in all, after which the rest are elided as
+    /// `..`; shared aggregates are printed once per use, so without it a
+    /// small value can print exponentially many items
+    pub max_total_items: usize,
+}
+
+impl PrettyPrinter {
+    pub fn new() -> PrettyPrinter {
+        PrettyPrinter {
+            indent: 0,
+            max_depth: 32,
+            max_items: usize::MAX,
+            max_total_items: 10_000,
+        }
+    }
+
+    pub fn print(&self, val: &Value) -> String {
+        let mut p = Printer {
+            config: self,
+            out: String::new(),
+            path: Vec::new(),
+            labels: 0,
+            items: self.max_total_items,
+        };
+        p.write_value(val, 0);
+        p.out
+    }
+
+    pub fn write(&self, f: &mut impl Write, val: &Value) -> fmt::Result {
+        f.write_str(&self.print(val))
+    }
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> Self {
+        PrettyPrinter::new()
+    }
+}
+
+struct PathEntry {
+    id: usize,
+    /// where this aggregate starts in the output, so that a label can be
+    /// inserted in front of it once a cycle back to it is found
+    start: usize,
+    label: Option<usize>,
+}
+
+struct Printer<'a> {
+    config: &'a PrettyPrinter,
+    out: String,
+    path: Vec<PathEntry>,
+    labels: usize,
+    /// how many more items can be printed
+    items: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn newline(&mut self, depth: usize) {
+        if self.config.indent > 0 {
+            self.out.push('\n');
+            for _ in 0..depth * self.config.indent {
+                self.out.push(' ');
+            }
+        }
+    }
+
+    fn separator(&mut self) {
+        match self.config.indent {
+            0 => self.out.push_str(", "),
+            _ => self.out.push(','),
+        }
+    }
+
+    /// Returns the label of `id` if it is one of its own ancestors.
+    fn back_reference(&mut self, id: usize) -> Option<usize> {
+        let index = self.path.iter().position(|e| e.id == id)?;
+        if let Some(label) = self.path[index].label {
+            return Some(label);
+        }
+        self.labels += 1;
+        let label = self.labels;
+        let text = format!("#{} ", label);
+        self.out.insert_str(self.path[index].start, &text);
+        for entry in &mut self.path[index + 1..] {
+            entry.start += text.len();
+        }
+        self.path[index].label = Some(label);
+        Some(label)
+    }
+
+    fn write_aggregate<T>(
+        &mut self,
+        id: usize,
+        depth: usize,
+        (open, close): (&str, &str),
+        items: Vec<T>,
+        mut write_item: impl FnMut(&mut Self, T, usize),
+    ) {
+        if let Some(label) = self.back_reference(id) {
+            write!(self.out, "<cycle #{}>", label).unwrap();
+            return;
+        }
+        if depth >= self.config.max_depth {
+            write!(self.out, "{}..{}", open, close).unwrap();
+            return;
+        }
+        self.path.push(PathEntry {
+            id,
+            start: self.out.len(),
+            label: None,
+        });
+        self.out.push_str(open);
+        let len = items.len();
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                self.separator();
+            }
+            self.newline(depth + 1);
+            if i >= self.config.max_items || self.items == 0 {
+                self.out.push_str("..");
+                break;
+            }
+            self.items -= 1;
+            write_item(self, item, depth + 1);
+        }
+        if len > 0 {
+            self.newline(depth);
+        }
+        self.out.push_str(close);
+        self.path.pop();
+    }
+
+    fn write_value(&mut self, val: &Value, depth: usize) {
+        match val {
+            Value::None => self.out.push_str("None"),
+            Value::Bool(t) => write!(self.out, "{}", t).unwrap(),
+            Value::Integer(t) => write!(self.out, "{}", t).unwrap(),
+            Value::Real(t) => write!(self.out, "{:?}", t).unwrap(),
+            Value::Tuple(t) => self.write_tuple(t, depth),
+            Value::TupleWeak(t) => self.write_weak(t, depth),
+            Value::Table(t) => self.write_table(t, depth),
+            Value::List(t) => self.write_list(t, depth),
+            Value::Buffer(t) => self.write_buffer(t),
+            Value::Function(t) => write_function(&mut self.out, t),
+            Value::NativeFn(t) => write!(self.out, "<native fn {:p}>", *t as *const ()).unwrap(),
+            Value::Unknown(_) => self.out.push_str("<unknown>"),
+            Value::Iter(_) => self.out.push_str("<iter>"),
+            Value::Range(t) => write_range(&mut self.out, t),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(t) => write!(self.out, "{}", t).unwrap(),
+        }
+    }
+
+    fn write_tuple(&mut self, t: &Tuple, depth: usize) {
+        let items: Vec<Value> = t.iter().collect();
+        // keep single item tuples distinguishable from parentheses
+        let close = match (items.len(), self.config.indent) {
+            (1, 0) => ",)",
+            _ => ")",
+        };
+        self.write_aggregate(t.identity(), depth, ("(", close), items, |p, v, d| {
+            p.write_value(&v, d)
+        });
+    }
+
+    fn write_weak(&mut self, t: &TupleWeak, depth: usize) {
+        match t.upgrade() {
+            Some(t) => {
+                self.out.push_str("Weak(");
+                self.write_tuple(&t, depth);
+                self.out.push(')');
+            }
+            None => self.out.push_str("Weak(<dropped>)"),
+        }
+    }
+
+    fn write_table(&mut self, t: &Table, depth: usize) {
+        let items = t.entries();
+        self.write_aggregate(t.identity(), depth, ("{", "}"), items, |p, (k, v), d| {
+            write!(p.out, "{}: ", k).unwrap();
+            p.write_value(&v, d);
+        });
+    }
+
+    fn write_list(&mut self, t: &List, depth: usize) {
+        let items = t.as_slice().to_vec();
+        self.write_aggregate(t.identity(), depth, ("[", "]"), items, |p, v, d| {
+            p.write_value(&v, d)
+        });
+    }
+
+    fn write_buffer(&mut self, t: &Buffer) {
+        let bytes = t.as_slice();
+        self.out.push_str("b\"");
+        for (i, b) in bytes.iter().enumerate() {
+            if i >= self.config.max_items {
+                self.out.push_str("..");
+                break;
+            }
+            for c in std::ascii::escape_default(*b) {
+                self.out.push(c as char);
+            }
+        }
+        self.out.push('"');
+    }
+}
+
+fn write_range(out: &mut String, t: &Range) {
+    let dots = if t.inclusive { "..=" } else { ".." };
+    write!(out, "{}{}{}", t.start, dots, t.end).unwrap();
+    if t.step != 1 {
+        write!(out, " step {}", t.step).unwrap();
+    }
+}
+
+fn write_function(out: &mut String, t: &Function) {
+    match &t.name {
+        Some(name) => write!(out, "<function {}>", name).unwrap(),
+        None => write!(out, "<function {} ops>", t.ops.len()).unwrap(),
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut p = PrettyPrinter::new();
+        if f.alternate() {
+            p.indent = 4;
+        }
+        p.write(f, self)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        PrettyPrinter::new().write(f, self)
+    }
+}
+
+macro_rules! impl_debug_via_value {
+    ($($t:ident),+) => {
+        $(
+            impl fmt::Debug for $t {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    fmt::Debug::fmt(&Value::$t(self.clone()), f)
+                }
+            }
+        )+
+    };
+}
+
+impl_debug_via_value!(Tuple, TupleWeak, Table, List, Buffer);
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        write_function(&mut out, self);
+        f.write_str(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::bytecode::{self, DebugInfo};
+
+    #[test]
+    fn opaque_values() {
+        let p = PrettyPrinter::new();
+        assert_eq!(p.print(&Value::Unknown(Rc::new(5))), "<unknown>");
+        let f = bytecode::Function {
+            ops: Vec::new(),
+            debug: None,
+        };
+        let val = f.into_value(Tuple::empty(0)).into();
+        assert_eq!(p.print(&val), "<function 0 ops>");
+        let debug = DebugInfo {
+            name: "fib".to_string(),
+            locals: Vec::new(),
+            lines: Vec::new(),
+        };
+        let f = bytecode::Function {
+            ops: Vec::new(),
+            debug: Some(debug),
+        };
+        let val = f.into_value(Tuple::empty(0)).into();
+        assert_eq!(p.print(&val), "<function fib>");
+    }
+
+    #[test]
+    fn shared_items_are_printed_within_the_budget() {
+        // printed in full, this would be 2^32 lists
+        let mut val: Value = List::empty().into();
+        for _ in 0..32 {
+            val = List::new(vec![val.clone(), val]).into();
+        }
+        let mut p = PrettyPrinter::new();
+        p.max_total_items = 4;
+        // the outermost list isn't an item
+        assert_eq!(p.print(&val), "[[[[[..], ..], ..], ..], ..]");
+        let text = PrettyPrinter::new().print(&val);
+        assert_eq!(text.matches('[').count(), 10_001);
+
+        let list = List::new(vec![1.into(), 2.into(), 3.into()]).into();
+        p.max_total_items = 2;
+        assert_eq!(p.print(&list), "[1, 2, ..]");
+    }
+}
@@ -0,0 +1,102 @@
+use std::convert::TryFrom;
+
+use super::Integer;
+
+/// The integers from `start` towards `end`, counting by `step`. Ranges are
+/// plain values, so creating and copying one doesn't allocate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Range {
+    pub start: Integer,
+    pub end: Integer,
+    pub step: Integer,
+    /// whether `end` is included, if `step` lands on it
+    pub inclusive: bool,
+}
+
+impl Range {
+    /// Returns None if `step` is zero.
+    pub fn new(start: Integer, end: Integer, step: Integer, inclusive: bool) -> Option<Range> {
+        if step == 0 {
+            return None;
+        }
+        Some(Range {
+            start,
+            end,
+            step,
+            inclusive,
+        })
+    }
+
+    /// Ranges can have up to 2^64 items, which is too many for a `usize` on
+    /// some targets, so the length is a `u128`.
+    pub fn len(&self) -> u128 {
+        // distance from start to end, in the direction of step
+        let (dist, step) = match self.step > 0 {
+            true => (self.end as i128 - self.start as i128, self.step as i128),
+            false => (self.start as i128 - self.end as i128, -(self.step as i128)),
+        };
+        let dist = match self.inclusive {
+            true => dist,
+            false => dist - 1,
+        };
+        if dist < 0 {
+            0
+        } else {
+            (dist / step + 1) as u128
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: u128) -> Option<Integer> {
+        if index >= self.len() {
+            return None;
+        }
+        let t = self.start as i128 + index as i128 * self.step as i128;
+        Some(Integer::try_from(t).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: Integer, end: Integer, step: Integer, inclusive: bool) -> Range {
+        Range::new(start, end, step, inclusive).unwrap()
+    }
+
+    #[test]
+    fn zero_steps_are_rejected() {
+        assert!(Range::new(0, 1, 0, false).is_none());
+    }
+
+    #[test]
+    fn lengths_count_towards_end() {
+        assert_eq!(range(0, 10, 3, false).len(), 4);
+        assert_eq!(range(0, 9, 3, false).len(), 3);
+        assert_eq!(range(0, 9, 3, true).len(), 4);
+        assert_eq!(range(10, 0, -3, false).len(), 4);
+        assert_eq!(range(9, 0, -3, true).len(), 4);
+        assert_eq!(range(0, 0, 1, true).len(), 1);
+        assert!(range(0, 0, 1, false).is_empty());
+        assert!(range(0, 10, -1, false).is_empty());
+        assert!(range(10, 0, 1, true).is_empty());
+    }
+
+    #[test]
+    fn lengths_can_exceed_integers() {
+        let full = range(Integer::MIN, Integer::MAX, 1, true);
+        assert_eq!(full.len(), 1 << 64);
+        assert_eq!(full.get(0), Some(Integer::MIN));
+        assert_eq!(full.get((1 << 64) - 1), Some(Integer::MAX));
+        assert_eq!(full.get(1 << 64), None);
+        let down = range(Integer::MAX, Integer::MIN, Integer::MIN, true);
+        assert_eq!(down.len(), 2);
+        assert_eq!(down.get(1), Some(-1));
+        let down = range(Integer::MAX, Integer::MIN, -1, false);
+        assert_eq!(down.len(), (1 << 64) - 1);
+        assert_eq!(down.get((1 << 64) - 2), Some(Integer::MIN + 1));
+    }
+}
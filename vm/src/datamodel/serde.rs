@@ -0,0 +1,804 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+
+use ::serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, SeqAccess, VariantAccess, Visitor,
+};
+use ::serde::ser::{self, SerializeTupleVariant, Serializer};
+use ::serde::{Deserialize, Serialize};
+
+#[cfg(feature = "bigint")]
+use super::BigInt;
+use super::{Buffer, Identity, List, Range, Table, Tuple, TupleWeak, Value, ValueType};
+
+/*
+Values form a graph, not a tree: the same Tuple, Table, List or Buffer can be
+reachable from several places, and tuples can even contain themselves. To keep
+that shape, every aggregate is written either as `Def(id, ..)` the first time
+it is seen, or as `Ref(id)` afterwards, where `id` is its `Identity::identity()`.
+
+The ids only have meaning inside a single document, so the tables that track
+them are reset whenever a top level (de)serialize call starts.
+
+Buffer views are written like any other buffer, with the bytes they can see,
+so after a round trip they no longer share storage with their base.
+*/
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static SEEN: RefCell<HashSet<u64>> = RefCell::new(HashSet::new());
+    static REFS: RefCell<HashMap<u64, Value>> = RefCell::new(HashMap::new());
+}
+
+struct Scope;
+
+impl Scope {
+    fn enter() -> Scope {
+        DEPTH.with(|d| d.set(d.get() + 1));
+        Scope
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let depth = DEPTH.with(|d| {
+            d.set(d.get() - 1);
+            d.get()
+        });
+        if depth == 0 {
+            SEEN.with(|s| s.borrow_mut().clear());
+            REFS.with(|r| r.borrow_mut().clear());
+        }
+    }
+}
+
+fn first_visit(id: u64) -> bool {
+    SEEN.with(|s| s.borrow_mut().insert(id))
+}
+
+fn register(id: u64, val: Value) {
+    REFS.with(|r| r.borrow_mut().insert(id, val));
+}
+
+fn lookup<E: de::Error>(id: u64) -> Result<Value, E> {
+    REFS.with(|r| r.borrow().get(&id).cloned())
+        .ok_or_else(|| E::custom(format!("reference to undefined id {}", id)))
+}
+
+fn expect<E: de::Error>(found: &Value, expected: ValueType) -> E {
+    E::custom(format!(
+        "reference has type {}, expected {}",
+        found.get_type().as_str(),
+        expected.as_str()
+    ))
+}
+
+// serialize
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let _scope = Scope::enter();
+        let ty = self.get_type();
+        let name = ty.as_str();
+        let index = ty as u32;
+        match self {
+            Value::None => s.serialize_unit_variant("Value", index, name),
+            Value::Bool(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::Integer(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::Real(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::Tuple(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::TupleWeak(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::Table(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::List(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::Buffer(t) => s.serialize_newtype_variant("Value", index, name, t),
+            Value::Range(t) => {
+                let t = (t.start, t.end, t.step, t.inclusive);
+                s.serialize_newtype_variant("Value", index, name, &t)
+            }
+            Value::Function(_) | Value::NativeFn(_) | Value::Unknown(_) | Value::Iter(_) => Err(
+                ser::Error::custom(format!("cannot serialize value of type {}", name)),
+            ),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(t) => s.serialize_newtype_variant("Value", index, name, &BigIntBytes(t)),
+        }
+    }
+}
+
+fn serialize_aggregate<S, F>(
+    s: S,
+    name: &'static str,
+    id: usize,
+    len: usize,
+    f: F,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    F: FnOnce(&mut S::SerializeTupleVariant) -> Result<(), S::Error>,
+{
+    let _scope = Scope::enter();
+    let id = id as u64;
+    if !first_visit(id) {
+        return s.serialize_newtype_variant(name, 0, "Ref", &id);
+    }
+    let mut tv = s.serialize_tuple_variant(name, 1, "Def", len)?;
+    tv.serialize_field(&id)?;
+    f(&mut tv)?;
+    tv.end()
+}
+
+struct TupleItems<'a>(&'a Tuple);
+
+impl Serialize for TupleItems<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(self.0.iter())
+    }
+}
+
+impl Serialize for Tuple {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_aggregate(s, "Tuple", self.identity(), 3, |tv| {
+            tv.serialize_field(&(self.len() as u64))?;
+            tv.serialize_field(&TupleItems(self))
+        })
+    }
+}
+
+impl Serialize for TupleWeak {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.upgrade().serialize(s)
+    }
+}
+
+struct TableItems<'a>(&'a Table);
+
+impl Serialize for TableItems<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(self.0.entries())
+    }
+}
+
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_aggregate(s, "Table", self.identity(), 2, |tv| {
+            tv.serialize_field(&TableItems(self))
+        })
+    }
+}
+
+struct ListItems<'a>(&'a List);
+
+impl Serialize for ListItems<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        // clone the items first, so that the list isn't borrowed while its
+        // items (which may include the list itself) are being serialized
+        let items = self.0.as_slice().to_vec();
+        s.collect_seq(items)
+    }
+}
+
+impl Serialize for List {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_aggregate(s, "List", self.identity(), 2, |tv| {
+            tv.serialize_field(&ListItems(self))
+        })
+    }
+}
+
+struct BufferBytes<'a>(&'a Buffer);
+
+impl Serialize for BufferBytes<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(&self.0.as_slice())
+    }
+}
+
+impl Serialize for Buffer {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_aggregate(s, "Buffer", self.identity(), 2, |tv| {
+            tv.serialize_field(&BufferBytes(self))
+        })
+    }
+}
+
+/// BigInts are written as signed little endian bytes.
+#[cfg(feature = "bigint")]
+struct BigIntBytes<'a>(&'a BigInt);
+
+#[cfg(feature = "bigint")]
+impl Serialize for BigIntBytes<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(&self.0.to_signed_bytes_le())
+    }
+}
+
+// deserialize
+
+const VALUE_VARIANTS: &[&str] = &[
+    "None",
+    "Integer",
+    "Real",
+    "Tuple",
+    "TupleWeak",
+    "Table",
+    "List",
+    "Buffer",
+    "Function",
+    "NativeFn",
+    "Unknown",
+    "Bool",
+    "Iter",
+    "Range",
+    "BigInt",
+];
+
+const AGGREGATE_VARIANTS: &[&str] = &["Ref", "Def"];
+
+/// Variant tag of an enum, accepted either by name or by index.
+struct Tag {
+    index: u32,
+}
+
+impl Tag {
+    fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+        variants: &'static [&'static str],
+    ) -> Result<Tag, D::Error> {
+        d.deserialize_identifier(TagVisitor { variants })
+    }
+}
+
+struct TagVisitor {
+    variants: &'static [&'static str],
+}
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "one of {:?}", self.variants)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Tag, E> {
+        match v as usize {
+            i if i < self.variants.len() => Ok(Tag { index: i as u32 }),
+            _ => Err(E::invalid_value(de::Unexpected::Unsigned(v), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Tag, E> {
+        match self.variants.iter().position(|n| *n == v) {
+            Some(i) => Ok(Tag { index: i as u32 }),
+            None => Err(E::unknown_variant(v, self.variants)),
+        }
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Tag, E> {
+        match std::str::from_utf8(v) {
+            Ok(v) => self.visit_str(v),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(v), &self)),
+        }
+    }
+}
+
+struct TagSeed(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for TagSeed {
+    type Value = Tag;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Tag, D::Error> {
+        Tag::deserialize(d, self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Value, D::Error> {
+        let _scope = Scope::enter();
+        d.deserialize_enum("Value", VALUE_VARIANTS, ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Value")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let (tag, v) = data.variant_seed(TagSeed(VALUE_VARIANTS))?;
+        Ok(match tag.index {
+            0 => {
+                v.unit_variant()?;
+                Value::None
+            }
+            1 => Value::Integer(v.newtype_variant()?),
+            2 => Value::Real(v.newtype_variant()?),
+            3 => Value::Tuple(v.newtype_variant()?),
+            4 => Value::TupleWeak(v.newtype_variant()?),
+            5 => Value::Table(v.newtype_variant()?),
+            6 => Value::List(v.newtype_variant()?),
+            7 => Value::Buffer(v.newtype_variant()?),
+            11 => Value::Bool(v.newtype_variant()?),
+            13 => {
+                let (start, end, step, inclusive) = v.newtype_variant()?;
+                let range = Range::new(start, end, step, inclusive)
+                    .ok_or_else(|| de::Error::custom("range step cannot be zero"))?;
+                Value::Range(range)
+            }
+            #[cfg(feature = "bigint")]
+            14 => Value::from_bigint(BigInt::from_signed_bytes_le(
+                &v.newtype_variant_seed(BytesSeed)?,
+            )),
+            _ => {
+                return Err(de::Error::custom(format!(
+                    "cannot deserialize value of type {}",
+                    VALUE_VARIANTS[tag.index as usize]
+                )))
+            }
+        })
+    }
+}
+
+/// Shared `Ref(id)` / `Def(id, ..)` decoding for aggregate values.
+trait Aggregate: Sized + Clone + Into<Value> {
+    const NAME: &'static str;
+    /// number of fields in the `Def` variant, including the id
+    const DEF_LEN: usize;
+
+    fn from_value<E: de::Error>(val: Value) -> Result<Self, E>;
+
+    /// Reads the rest of a `Def` variant. Implementations must register the
+    /// new value under `id` before reading any nested values, so that cyclic
+    /// references back to it can be resolved.
+    fn read_def<'de, A: SeqAccess<'de>>(id: u64, seq: &mut A) -> Result<Self, A::Error>;
+}
+
+fn deserialize_aggregate<'de, D: Deserializer<'de>, T: Aggregate>(d: D) -> Result<T, D::Error> {
+    let _scope = Scope::enter();
+    d.deserialize_enum(T::NAME, AGGREGATE_VARIANTS, AggregateVisitor(PhantomData))
+}
+
+struct AggregateVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Aggregate> Visitor<'de> for AggregateVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a {}", T::NAME)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<T, A::Error> {
+        let (tag, v) = data.variant_seed(TagSeed(AGGREGATE_VARIANTS))?;
+        match tag.index {
+            0 => T::from_value(lookup(v.newtype_variant()?)?),
+            _ => v.tuple_variant(T::DEF_LEN, self),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let id = next(&mut seq, 0)?;
+        T::read_def(id, &mut seq)
+    }
+}
+
+/// The most items of type `T` to allocate for up front, when the number
+/// comes from the input, like serde's own `size_hint::cautious`.
+fn cautious_len<T>() -> usize {
+    const MAX_BYTES: usize = 1024 * 1024;
+    MAX_BYTES / mem::size_of::<T>().max(1)
+}
+
+fn next<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(seq: &mut A, i: usize) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(i, &"a complete Def variant"))
+}
+
+fn next_seed<'de, A: SeqAccess<'de>, S: DeserializeSeed<'de>>(
+    seq: &mut A,
+    i: usize,
+    seed: S,
+) -> Result<S::Value, A::Error> {
+    seq.next_element_seed(seed)?
+        .ok_or_else(|| de::Error::invalid_length(i, &"a complete Def variant"))
+}
+
+macro_rules! impl_aggregate_from_value {
+    ($t:ident) => {
+        fn from_value<E: de::Error>(val: Value) -> Result<Self, E> {
+            match val {
+                Value::$t(t) => Ok(t),
+                _ => Err(expect(&val, ValueType::$t)),
+            }
+        }
+    };
+}
+
+/// Calls `f` for every element of a sequence, without collecting them.
+struct ForEach<F>(F);
+
+impl<'de, F: FnMut(usize, Value) -> Result<(), String>> DeserializeSeed<'de> for ForEach<F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<(), D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(usize, Value) -> Result<(), String>> Visitor<'de> for ForEach<F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let mut i = 0;
+        while let Some(val) = seq.next_element()? {
+            (self.0)(i, val).map_err(de::Error::custom)?;
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregate for Tuple {
+    const NAME: &'static str = "Tuple";
+    const DEF_LEN: usize = 3;
+
+    impl_aggregate_from_value!(Tuple);
+
+    /// The length comes from the input, so only a tuple that is small
+    /// enough is made before its items are read. A larger one is made once
+    /// they have all been read, and until then a stand-in is registered
+    /// under its id, which is replaced with it in the items afterwards.
+    fn read_def<'de, A: SeqAccess<'de>>(id: u64, seq: &mut A) -> Result<Self, A::Error> {
+        let len: u64 = next(seq, 1)?;
+        let short = |found: usize| {
+            let msg = format!("tuple has {} items, expected {}", found, len);
+            de::Error::custom(msg)
+        };
+        if len <= cautious_len::<RefCell<Value>>() as u64 {
+            let tuple = Tuple::empty(len as usize);
+            register(id, tuple.clone().into());
+            let mut found = 0;
+            next_seed(
+                seq,
+                2,
+                ForEach(|i, val| {
+                    found = i + 1;
+                    match tuple.set(i, val) {
+                        Some(_) => Ok(()),
+                        None => Err(format!("tuple has more than {} items", len)),
+                    }
+                }),
+            )?;
+            if found != tuple.len() {
+                return Err(short(found));
+            }
+            return Ok(tuple);
+        }
+        let stand_in = Tuple::empty(0);
+        register(id, stand_in.clone().into());
+        let mut items = Vec::new();
+        next_seed(
+            seq,
+            2,
+            ForEach(|_, val| {
+                if items.len() as u64 == len {
+                    return Err(format!("tuple has more than {} items", len));
+                }
+                items.push(RefCell::new(val));
+                Ok(())
+            }),
+        )?;
+        if items.len() as u64 != len {
+            return Err(short(items.len()));
+        }
+        let tuple = Tuple::new(items);
+        register(id, tuple.clone().into());
+        // REFS no longer has the stand-in, so anything else that has it got
+        // it from a Ref in the items
+        if stand_in.is_shared() {
+            replace_stand_in(&stand_in, &tuple);
+        }
+        Ok(tuple)
+    }
+}
+
+/// Replaces `stand_in` with `tuple` wherever the items of `tuple` refer to
+/// it, directly or through other aggregates.
+fn replace_stand_in(stand_in: &Tuple, tuple: &Tuple) {
+    let id = stand_in.identity();
+    let replace = |val: &Value| match val {
+        Value::Tuple(t) if t.identity() == id => Some(Value::Tuple(tuple.clone())),
+        Value::TupleWeak(t) if t.identity() == id => Some(Value::TupleWeak(tuple.downgrade())),
+        _ => None,
+    };
+    // a work stack instead of recursion, since the items can be nested
+    // deeply, and the ids of the aggregates that have been visited
+    let mut stack: Vec<Value> = vec![tuple.clone().into()];
+    let mut seen = HashSet::new();
+    while let Some(val) = stack.pop() {
+        match val {
+            Value::Tuple(t) if seen.insert(t.identity()) => {
+                for (i, item) in t.iter().enumerate() {
+                    match replace(&item) {
+                        Some(new) => {
+                            t.set(i, new);
+                        }
+                        None => stack.push(item),
+                    }
+                }
+            }
+            Value::TupleWeak(t) => stack.extend(t.upgrade().map(Value::Tuple)),
+            Value::List(t) if seen.insert(t.identity()) => {
+                let items = t.as_slice().to_vec();
+                for (i, item) in items.into_iter().enumerate() {
+                    match replace(&item) {
+                        Some(new) => {
+                            t.set(i, new);
+                        }
+                        None => stack.push(item),
+                    }
+                }
+            }
+            Value::Table(t) if seen.insert(t.identity()) => {
+                for (key, item) in t.entries() {
+                    match replace(&item) {
+                        Some(new) => {
+                            t.set(key, new);
+                        }
+                        None => stack.push(item),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Aggregate for Table {
+    const NAME: &'static str = "Table";
+    const DEF_LEN: usize = 2;
+
+    impl_aggregate_from_value!(Table);
+
+    fn read_def<'de, A: SeqAccess<'de>>(id: u64, seq: &mut A) -> Result<Self, A::Error> {
+        let table = Table::new(Vec::new());
+        register(id, table.clone().into());
+        next_seed(seq, 1, TableEntries(&table))?;
+        Ok(table)
+    }
+}
+
+struct TableEntries<'a>(&'a Table);
+
+impl<'de> DeserializeSeed<'de> for TableEntries<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<(), D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TableEntries<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of (key, value) pairs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some((key, val)) = seq.next_element::<(u64, Value)>()? {
+            self.0.set(key, val);
+        }
+        Ok(())
+    }
+}
+
+impl Aggregate for List {
+    const NAME: &'static str = "List";
+    const DEF_LEN: usize = 2;
+
+    impl_aggregate_from_value!(List);
+
+    fn read_def<'de, A: SeqAccess<'de>>(id: u64, seq: &mut A) -> Result<Self, A::Error> {
+        let list = List::empty();
+        register(id, list.clone().into());
+        next_seed(
+            seq,
+            1,
+            ForEach(|_, val| {
+                list.push(val);
+                Ok(())
+            }),
+        )?;
+        Ok(list)
+    }
+}
+
+impl Aggregate for Buffer {
+    const NAME: &'static str = "Buffer";
+    const DEF_LEN: usize = 2;
+
+    impl_aggregate_from_value!(Buffer);
+
+    fn read_def<'de, A: SeqAccess<'de>>(id: u64, seq: &mut A) -> Result<Self, A::Error> {
+        let buffer = Buffer::empty();
+        register(id, buffer.clone().into());
+        let bytes = next_seed(seq, 1, BytesSeed)?;
+        buffer.append(&bytes);
+        Ok(buffer)
+    }
+}
+
+struct BytesSeed;
+
+impl<'de> DeserializeSeed<'de> for BytesSeed {
+    type Value = Vec<u8>;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Vec<u8>, D::Error> {
+        d.deserialize_byte_buf(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BytesSeed {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let len = seq.size_hint().unwrap_or(0).min(cautious_len::<u8>());
+        let mut acc = Vec::with_capacity(len);
+        while let Some(b) = seq.next_element()? {
+            acc.push(b);
+        }
+        Ok(acc)
+    }
+}
+
+macro_rules! impl_deserialize_aggregate {
+    ($($t:ident),+) => {
+        $(
+            impl<'de> Deserialize<'de> for $t {
+                fn deserialize<D: Deserializer<'de>>(d: D) -> Result<$t, D::Error> {
+                    deserialize_aggregate(d)
+                }
+            }
+        )+
+    };
+}
+
+impl_deserialize_aggregate!(Tuple, Table, List, Buffer);
+
+impl<'de> Deserialize<'de> for TupleWeak {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<TupleWeak, D::Error> {
+        let tuple: Option<Tuple> = Deserialize::deserialize(d)?;
+        // a weak reference whose target wasn't alive is restored as an
+        // already dropped tuple, so it still fails to upgrade
+        Ok(tuple.unwrap_or_else(|| Tuple::empty(0)).downgrade())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(val: &Value) -> Value {
+        let json = serde_json::to_string(val).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn range_round_trips() {
+        let range = Range::new(-3, 10, 2, true).unwrap();
+        match round_trip(&Value::Range(range)) {
+            Value::Range(t) => assert_eq!(t, range),
+            t => panic!("expected a Range, found {}", t.get_type().as_str()),
+        }
+    }
+
+    #[test]
+    fn tags_match_value_types() {
+        for &ty in &[
+            ValueType::None,
+            ValueType::Integer,
+            ValueType::Bool,
+            ValueType::Range,
+        ] {
+            assert_eq!(VALUE_VARIANTS[ty as usize], ty.as_str());
+        }
+        let json = serde_json::to_string(&Value::Integer(5)).unwrap();
+        assert_eq!(json, r#"{"Integer":5}"#);
+        match round_trip(&Value::Bool(true)) {
+            Value::Bool(t) => assert!(t),
+            t => panic!("expected a Bool, found {}", t.get_type().as_str()),
+        }
+    }
+
+    #[test]
+    fn tuple_len_is_not_trusted() {
+        let json = r#"{"Tuple":{"Def":[0,1,[{"Integer":1}]]}}"#;
+        assert!(serde_json::from_str::<Value>(json).is_ok());
+        let json = r#"{"Tuple":{"Def":[0,1000000000000,[{"Integer":1}]]}}"#;
+        assert!(serde_json::from_str::<Value>(json).is_err());
+        // too large to make before its items are read
+        let items = (0..100_000).map(|i| RefCell::new(Value::Integer(i)));
+        let t = Tuple::new(items.collect());
+        match round_trip(&Value::Tuple(t)) {
+            Value::Tuple(t) => assert_eq!(t.len(), 100_000),
+            t => panic!("expected a Tuple, found {}", t.get_type().as_str()),
+        }
+    }
+
+    #[test]
+    fn short_tuples_are_rejected() {
+        let json = r#"{"Tuple":{"Def":[0,2,[{"Integer":1}]]}}"#;
+        assert!(serde_json::from_str::<Value>(json).is_err());
+        let json = r#"{"Tuple":{"Def":[0,1,[]]}}"#;
+        assert!(serde_json::from_str::<Value>(json).is_err());
+        let json = r#"{"Tuple":{"Def":[0,0,[]]}}"#;
+        assert!(serde_json::from_str::<Value>(json).is_ok());
+    }
+
+    #[test]
+    fn large_tuples_can_refer_to_themselves() {
+        let len = cautious_len::<RefCell<Value>>() + 1;
+        let t = Tuple::empty(len);
+        let list = List::empty();
+        t.set(0, t.clone().into());
+        t.set(1, t.downgrade().into());
+        t.set(2, list.clone().into());
+        list.push(t.clone().into());
+        let t = match round_trip(&Value::Tuple(t)) {
+            Value::Tuple(t) => t,
+            t => panic!("expected a Tuple, found {}", t.get_type().as_str()),
+        };
+        assert_eq!(t.len(), len);
+        let refers_to_t = |val: Option<Value>| match val {
+            Some(Value::Tuple(u)) => u.identity() == t.identity(),
+            Some(Value::TupleWeak(u)) => u.identity() == t.identity(),
+            _ => false,
+        };
+        assert!(refers_to_t(t.get(0)));
+        assert!(refers_to_t(t.get(1)));
+        match t.get(2) {
+            Some(Value::List(list)) => assert!(refers_to_t(list.get(0))),
+            _ => panic!("expected a List"),
+        }
+        // break the cycles, so the tuple is dropped
+        t.set(0, Value::None);
+        t.set(2, Value::None);
+    }
+
+    #[test]
+    fn zero_step_range_is_rejected() {
+        let json = r#"{"Range":[0,10,0,false]}"#;
+        assert!(serde_json::from_str::<Value>(json).is_err());
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_round_trips() {
+        let big = BigInt::from(i64::MAX) * BigInt::from(1000);
+        match round_trip(&Value::BigInt(big.clone())) {
+            Value::BigInt(t) => assert_eq!(t, big),
+            t => panic!("expected a BigInt, found {}", t.get_type().as_str()),
+        }
+    }
+}
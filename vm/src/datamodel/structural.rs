@@ -0,0 +1,398 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::Hasher;
+
+#[cfg(feature = "bigint")]
+use super::BigInt;
+use super::{Identity, Tuple, Value};
+
+/*
+Structural comparison looks inside aggregates instead of comparing them by
+`Identity::identity()`. Values of different types are ordered by their
+`ValueType`, so `None < Bool < Integer < Real < ...`, except that Integers
+and BigInts are ordered by value; aggregates of the same
+type are ordered lexicographically by their items. Reals are ordered with
+`f64::total_cmp`, so NaN equals itself and `-0.0 < 0.0`. Functions, native
+functions and unknown values have no inner structure, so they are compared by
+identity, which is consistent within a run but not across runs.
+
+Comparing a pair of aggregates that is already being compared higher up
+(because one of them contains itself) treats that pair as equal, so cyclic
+values compare without recursing forever. Pairs that have been found equal
+aren't compared again, so aggregates that share items compare in time
+proportional to the number of distinct pairs, not the number of paths to them.
+The pairs being compared are kept on a work stack instead of the call stack,
+so values nested arbitrarily deep compare without overflowing it.
+
+Hashing can't do that and stay consistent with comparison, since `a = [a]`
+and `b = [[b]]` are equal. Instead it only looks at a bounded prefix of the
+items, nested up to `HASH_DEPTH` deep, which is the same for equal values.
+*/
+
+/// 64 bit FNV-1a, which unlike `DefaultHasher` is stable across builds.
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    pub fn new() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write(&i.to_le_bytes());
+    }
+}
+
+/// Aggregates nested deeper than this don't contribute to the hash, which
+/// keeps hashing of cyclic values finite.
+const HASH_DEPTH: usize = 8;
+
+/// The most items of aggregates that contribute to the hash, which keeps
+/// hashing of large or cyclic values fast.
+const HASH_ITEMS: usize = 1024;
+
+impl Value {
+    pub fn structural_eq(&self, other: &Value) -> bool {
+        self.total_cmp(other) == Ordering::Equal
+    }
+
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        let mut c = Comparison {
+            visiting: HashSet::new(),
+            equal: HashSet::new(),
+        };
+        c.cmp(self, other)
+    }
+
+    pub fn structural_hash(&self) -> u64 {
+        let mut h = Hashing {
+            h: StableHasher::new(),
+            items: HASH_ITEMS,
+        };
+        h.value(self, 0);
+        h.h.finish()
+    }
+}
+
+struct Comparison {
+    visiting: HashSet<(usize, usize)>,
+    /// pairs that have already been compared, and were equal
+    equal: HashSet<(usize, usize)>,
+}
+
+/// The items of a pair of aggregates that are being compared.
+enum Items {
+    Tuple(Tuple, Tuple),
+    List(Vec<Value>, Vec<Value>),
+    Table(Vec<(u64, Value)>, Vec<(u64, Value)>),
+}
+
+/// A pair of aggregates on the work stack of a Comparison, which keeps deeply
+/// nested values from overflowing the call stack.
+struct Frame {
+    ids: (usize, usize),
+    items: Items,
+    /// the index of the next pair of items
+    next: usize,
+}
+
+impl Frame {
+    /// The next pair of items to compare, or the ordering of the aggregates
+    /// if it no longer depends on their items.
+    fn next(&mut self) -> Result<(Value, Value), Ordering> {
+        let i = self.next;
+        self.next += 1;
+        match &self.items {
+            Items::Tuple(l, r) => match (l.get(i), r.get(i)) {
+                (Some(l), Some(r)) => Ok((l, r)),
+                _ => Err(l.len().cmp(&r.len())),
+            },
+            Items::List(l, r) => match (l.get(i), r.get(i)) {
+                (Some(l), Some(r)) => Ok((l.clone(), r.clone())),
+                _ => Err(l.len().cmp(&r.len())),
+            },
+            Items::Table(l, r) => match (l.get(i), r.get(i)) {
+                (Some((lk, lv)), Some((rk, rv))) => match lk.cmp(rk) {
+                    Ordering::Equal => Ok((lv.clone(), rv.clone())),
+                    ord => Err(ord),
+                },
+                _ => Err(l.len().cmp(&r.len())),
+            },
+        }
+    }
+}
+
+impl Comparison {
+    fn cmp(&mut self, lhs: &Value, rhs: &Value) -> Ordering {
+        let mut stack = Vec::new();
+        let mut ord = self.enter(lhs, rhs, &mut stack);
+        loop {
+            match ord {
+                // a new frame, or an equal pair of items
+                None | Some(Ordering::Equal) => {}
+                // a pair that is unequal ends the whole comparison
+                Some(ord) => return ord,
+            }
+            let frame = match stack.last_mut() {
+                Some(frame) => frame,
+                None => return Ordering::Equal,
+            };
+            ord = match frame.next() {
+                Ok((l, r)) => self.enter(&l, &r, &mut stack),
+                Err(ord) => {
+                    let ids = stack.pop().unwrap().ids;
+                    self.visiting.remove(&ids);
+                    if ord == Ordering::Equal {
+                        // since an unequal pair ends the comparison, a pair
+                        // that was equal assuming the pairs above it are
+                        // stays equal
+                        self.equal.insert(ids);
+                    }
+                    Some(ord)
+                }
+            };
+        }
+    }
+
+    /// Compares values without items, or pushes a frame for a pair of
+    /// aggregates and returns None.
+    fn enter(&mut self, lhs: &Value, rhs: &Value, stack: &mut Vec<Frame>) -> Option<Ordering> {
+        let ord = match (lhs, rhs) {
+            (Value::None, Value::None) => Ordering::Equal,
+            (Value::Bool(l), Value::Bool(r)) => l.cmp(r),
+            (Value::Integer(l), Value::Integer(r)) => l.cmp(r),
+            (Value::Real(l), Value::Real(r)) => l.total_cmp(r),
+            (Value::Tuple(l), Value::Tuple(r)) => {
+                return self.push((l.identity(), r.identity()), stack, || {
+                    Items::Tuple(l.clone(), r.clone())
+                })
+            }
+            (Value::TupleWeak(l), Value::TupleWeak(r)) => match (l.upgrade(), r.upgrade()) {
+                (Some(l), Some(r)) => {
+                    return self.push((l.identity(), r.identity()), stack, || Items::Tuple(l, r))
+                }
+                (l, r) => l.is_some().cmp(&r.is_some()),
+            },
+            (Value::Table(l), Value::Table(r)) => {
+                return self.push((l.identity(), r.identity()), stack, || {
+                    Items::Table(l.entries(), r.entries())
+                })
+            }
+            (Value::List(l), Value::List(r)) => {
+                return self.push((l.identity(), r.identity()), stack, || {
+                    Items::List(l.as_slice().to_vec(), r.as_slice().to_vec())
+                })
+            }
+            (Value::Buffer(l), Value::Buffer(r)) => l.as_slice()[..].cmp(&r.as_slice()[..]),
+            (Value::Function(l), Value::Function(r)) => l.identity().cmp(&r.identity()),
+            (Value::NativeFn(l), Value::NativeFn(r)) => (*l as usize).cmp(&(*r as usize)),
+            (Value::Unknown(l), Value::Unknown(r)) => l.identity().cmp(&r.identity()),
+            (Value::Iter(l), Value::Iter(r)) => l.identity().cmp(&r.identity()),
+            (Value::Range(l), Value::Range(r)) => {
+                (l.start, l.end, l.step, l.inclusive).cmp(&(r.start, r.end, r.step, r.inclusive))
+            }
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(l), Value::BigInt(r)) => l.cmp(r),
+            // integers are ordered by value, whichever of the two types holds them
+            #[cfg(feature = "bigint")]
+            (Value::Integer(l), Value::BigInt(r)) => BigInt::from(*l).cmp(r),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(l), Value::Integer(r)) => l.cmp(&BigInt::from(*r)),
+            _ => (lhs.get_type() as u8).cmp(&(rhs.get_type() as u8)),
+        };
+        Some(ord)
+    }
+
+    /// Pushes a frame for the items of a pair of aggregates, unless the pair
+    /// is equal without comparing them, because the aggregates are the same,
+    /// have been found equal already, or are being compared further down the
+    /// stack.
+    fn push(
+        &mut self,
+        ids: (usize, usize),
+        stack: &mut Vec<Frame>,
+        items: impl FnOnce() -> Items,
+    ) -> Option<Ordering> {
+        if ids.0 == ids.1 || self.equal.contains(&ids) || !self.visiting.insert(ids) {
+            return Some(Ordering::Equal);
+        }
+        stack.push(Frame {
+            ids,
+            items: items(),
+            next: 0,
+        });
+        None
+    }
+}
+
+struct Hashing {
+    h: StableHasher,
+    /// how many more items of aggregates can be hashed
+    items: usize,
+}
+
+impl Hashing {
+    fn value(&mut self, val: &Value, depth: usize) {
+        let h = &mut self.h;
+        h.write_u8(val.get_type() as u8);
+        match val {
+            Value::None => {}
+            Value::Bool(t) => h.write_u8(*t as u8),
+            Value::Integer(t) => h.write_i64(*t),
+            Value::Real(t) => h.write_u64(t.to_bits()),
+            Value::Tuple(t) => self.tuple(t, depth),
+            Value::TupleWeak(t) => {
+                if let Some(t) = t.upgrade() {
+                    self.tuple(&t, depth);
+                }
+            }
+            Value::Table(t) => {
+                let entries = t.entries();
+                self.seq(entries.len(), depth, |this, i| {
+                    let (key, val) = &entries[i];
+                    this.h.write_u64(*key);
+                    this.value(val, depth + 1);
+                });
+            }
+            Value::List(t) => {
+                let items = t.as_slice().to_vec();
+                self.seq(items.len(), depth, |this, i| {
+                    this.value(&items[i], depth + 1)
+                });
+            }
+            Value::Buffer(t) => {
+                let bytes = t.as_slice();
+                h.write_u64(bytes.len() as u64);
+                h.write(&bytes);
+            }
+            Value::Function(t) => h.write_usize(t.identity()),
+            Value::NativeFn(t) => h.write_usize(*t as usize),
+            Value::Unknown(t) => h.write_usize(t.identity()),
+            Value::Iter(t) => h.write_usize(t.identity()),
+            Value::Range(t) => {
+                h.write_i64(t.start);
+                h.write_i64(t.end);
+                h.write_i64(t.step);
+                h.write_u8(t.inclusive as u8);
+            }
+            #[cfg(feature = "bigint")]
+            Value::BigInt(t) => {
+                let bytes = t.to_signed_bytes_le();
+                h.write_u64(bytes.len() as u64);
+                h.write(&bytes);
+            }
+        }
+    }
+
+    fn tuple(&mut self, t: &Tuple, depth: usize) {
+        self.seq(t.len(), depth, |this, i| {
+            this.value(&t.get(i).unwrap(), depth + 1)
+        });
+    }
+
+    /// Hashes the length of an aggregate, then as many of its items as
+    /// the depth and the remaining items allow.
+    fn seq(&mut self, len: usize, depth: usize, mut item: impl FnMut(&mut Self, usize)) {
+        if depth >= HASH_DEPTH {
+            return;
+        }
+        self.h.write_u64(len as u64);
+        for i in 0..len {
+            if self.items == 0 {
+                return;
+            }
+            self.items -= 1;
+            item(self, i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::List;
+    use super::*;
+
+    #[test]
+    fn cyclic_values_hash_like_they_compare() {
+        let a = List::empty();
+        a.push(a.clone().into());
+        let b = List::empty();
+        b.push(List::new(vec![b.clone().into()]).into());
+        let (a, b): (Value, Value) = (a.into(), b.into());
+        assert!(a.structural_eq(&b));
+        assert_eq!(a.structural_hash(), b.structural_hash());
+    }
+
+    #[test]
+    fn wide_cyclic_values_hash_quickly() {
+        // without a bound on the items, this would hash 100^8 of them
+        let t = List::empty();
+        for _ in 0..100 {
+            t.push(t.clone().into());
+        }
+        let t: Value = t.into();
+        assert_eq!(t.structural_hash(), t.clone().structural_hash());
+    }
+
+    #[test]
+    fn shared_items_compare_quickly() {
+        // without remembering equal pairs, this would compare 2^64 of them
+        let dag = || {
+            let mut t: Value = List::empty().into();
+            for _ in 0..64 {
+                t = List::new(vec![t.clone(), t]).into();
+            }
+            t
+        };
+        assert!(dag().structural_eq(&dag()));
+    }
+
+    #[test]
+    fn deeply_nested_values_compare() {
+        let nested = |leaf: Value| {
+            let mut t = List::new(vec![leaf]);
+            for _ in 0..200_000 {
+                t = List::new(vec![t.into()]);
+            }
+            t
+        };
+        // dropping the lists recursively would overflow the stack too
+        let unnest = |mut t: List| {
+            while let Some(Value::List(inner)) = t.pop() {
+                t = inner;
+            }
+        };
+        let (a, b, c) = (nested(1.into()), nested(1.into()), nested(2.into()));
+        let (va, vb, vc): (Value, Value, Value) =
+            (a.clone().into(), b.clone().into(), c.clone().into());
+        assert!(va.structural_eq(&vb));
+        assert_eq!(va.total_cmp(&vc), Ordering::Less);
+        assert_eq!(vc.total_cmp(&vb), Ordering::Greater);
+        drop((va, vb, vc));
+        unnest(a);
+        unnest(b);
+        unnest(c);
+    }
+}
@@ -31,6 +31,10 @@ impl Table {
             .collect()
     }
 
+    pub fn entries(&self) -> Vec<(u64, Value)> {
+        self.items.borrow().clone()
+    }
+
     pub fn get(&self, key: u64) -> Option<Value> {
         let items = self.items.borrow();
         let index = items.binary_search_by_key(&key, |(k, _v)| *k).ok()?;
@@ -38,6 +42,16 @@ impl Table {
         Some(val.clone())
     }
 
+    /// Returns the entry with the smallest key that is at least `key`.
+    pub fn next_entry(&self, key: u64) -> Option<(u64, Value)> {
+        let items = self.items.borrow();
+        let index = match items.binary_search_by_key(&key, |(k, _v)| *k) {
+            Ok(index) => index,
+            Err(index) => index,
+        };
+        items.get(index).cloned()
+    }
+
     pub fn set(&self, key: u64, mut value: Value) -> Option<Value> {
         let mut items = self.items.borrow_mut();
         match items.binary_search_by_key(&key, |(k, _)| *k) {
@@ -47,6 +47,13 @@ impl Tuple {
     pub fn set(&self, index: usize, value: Value) -> Option<Value> {
         Some(self.items.get(index)?.replace(value))
     }
+
+    /// Whether anything other than this Tuple refers to its items, strongly
+    /// or weakly.
+    #[cfg(feature = "serde")]
+    pub(super) fn is_shared(&self) -> bool {
+        Rc::strong_count(&self.items) > 1 || Rc::weak_count(&self.items) > 0
+    }
 }
 
 impl Identity for Tuple {
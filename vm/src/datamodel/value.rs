@@ -1,50 +1,54 @@
 use std::any::Any;
 use std::cmp::Ordering;
 use std::convert::TryInto;
+use std::fmt;
 use std::rc::Rc;
 
-use super::{Buffer, Function, List, Table, Tuple, TupleWeak};
+use super::{Buffer, Function, Iter, List, Range, Table, Tuple, TupleWeak};
 
+pub type Bool = bool;
 pub type Integer = i64;
 pub type Real = f64;
 pub type Unknown = Rc<dyn Any>;
+#[cfg(feature = "bigint")]
+pub type BigInt = num_bigint::BigInt;
 
 pub type NativeFn = fn(Vec<Value>) -> Value;
 
 macro_rules! create_value_enum {
-    ($($n:ident),+) => {
+    ($($(#[$meta:meta])* $n:ident),+) => {
         #[derive(Clone)]
         pub enum Value {
             None,
-            $($n($n)),+
+            $($(#[$meta])* $n($n)),+
         }
 
         impl Value {
             pub fn get_type(&self) -> ValueType {
                 match self {
                     Value::None => ValueType::None,
-                    $(Value::$n(_) => ValueType::$n),+
+                    $($(#[$meta])* Value::$n(_) => ValueType::$n),+
                 }
             }
         }
 
         #[repr(u8)]
-        #[derive(PartialEq)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
         pub enum ValueType {
             None,
-            $($n),+
+            $($(#[$meta])* $n),+
         }
 
         impl ValueType {
             pub fn as_str(&self) -> &'static str {
                 match self {
                     ValueType::None => "None",
-                    $(ValueType::$n => stringify!($n)),+
+                    $($(#[$meta])* ValueType::$n => stringify!($n)),+
                 }
             }
         }
 
-        $(create_value_enum!(conversion $n);)+
+        $($(#[$meta])* create_value_enum!(conversion $n);)+
     };
     (conversion $t:ident) => {
         impl From< $t > for Value {
@@ -81,15 +85,32 @@ macro_rules! create_value_enum {
     };
 }
 
+// new variants are added at the end, and optional variants go last, so that
+// the other `ValueType`s keep their numbers
 create_value_enum! {
-    Integer, Real, Tuple, TupleWeak, Table, List, Buffer, Function, NativeFn, Unknown
+    Integer, Real, Tuple, TupleWeak, Table, List, Buffer, Function, NativeFn, Unknown, Bool, Iter,
+    Range,
+    #[cfg(feature = "bigint")]
+    BigInt
 }
 
+#[derive(Debug)]
 pub struct ValueTryIntoError {
     pub found: ValueType,
     pub expected: ValueType,
 }
 
+impl fmt::Display for ValueTryIntoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "expected {}, found {}",
+            self.expected.as_str(),
+            self.found.as_str()
+        )
+    }
+}
+
 pub trait Identity {
     fn identity(&self) -> usize;
 }
@@ -100,15 +121,6 @@ impl Identity for Unknown {
     }
 }
 
-impl From<bool> for Value {
-    fn from(t: bool) -> Self {
-        match t {
-            true => 1.into(),
-            false => 0.into(),
-        }
-    }
-}
-
 impl From<Ordering> for Value {
     fn from(t: Ordering) -> Self {
         Value::Integer(match t {
@@ -127,3 +139,18 @@ impl<T: Into<Value>> From<Option<T>> for Value {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types_keep_their_numbers() {
+        // scripts and serialized values depend on these
+        assert_eq!(ValueType::None as u8, 0);
+        assert_eq!(ValueType::Integer as u8, 1);
+        assert_eq!(ValueType::Buffer as u8, 7);
+        assert_eq!(ValueType::Unknown as u8, 10);
+        assert_eq!(ValueType::Bool as u8, 11);
+    }
+}
@@ -0,0 +1,698 @@
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::hash::Hasher;
+
+#[cfg(feature = "bigint")]
+use crate::datamodel::BigInt;
+use crate::datamodel::{
+    Buffer, Identity, List, NativeFn, StableHasher, Table, Tuple, Value, ValueType,
+};
+
+/*
+JSON values map onto the datamodel like this:
+
+    null            <-> None
+    true / false    <-> Bool
+    number          <-> Integer if it has no fraction or exponent and fits
+                        in an i64, otherwise Real (or BigInt, if the
+                        `bigint` feature is enabled and it is an integer)
+    string          <-> Buffer holding the UTF-8 bytes
+    array           <-> List (a Tuple is also written as an array)
+    object          <-> Table
+
+Tables are keyed by integers, so an object member named `k` is stored under
+`key_hash(k)`, and its value is the pair `(k, value)`, so that the name is
+not lost. Two different names with the same hash are an error, rather than
+one silently replacing the other. Scripts can find a member with the
+`key_hash` native. When writing a table, entries which don't have that shape
+are written with the decimal key as their name instead.
+
+The natives are in `NATIVES`, which a `Loader` provides as the module `json`.
+*/
+
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// The most bytes that `json_stringify` writes. Shared values are written
+/// once per use, so a small value can have an exponentially long text.
+pub const DEFAULT_MAX_LEN: usize = 16 * 1024 * 1024;
+
+/// Hashes an object member name into a table key.
+pub fn key_hash(key: &[u8]) -> u64 {
+    let mut h = StableHasher::new();
+    h.write(key);
+    h.finish()
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    /// byte offset into the input where the error was found
+    pub offset: usize,
+    pub kind: JsonErrorKind,
+}
+
+#[derive(Debug)]
+pub enum JsonErrorKind {
+    UnexpectedEnd,
+    UnexpectedByte(u8),
+    InvalidNumber,
+    InvalidEscape,
+    InvalidUtf8,
+    TrailingCharacters,
+    DepthLimit,
+    /// two different member names of an object have the same `key_hash`
+    KeyCollision,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            JsonErrorKind::UnexpectedEnd => write!(f, "unexpected end of input")?,
+            JsonErrorKind::UnexpectedByte(b) => write!(f, "unexpected character {:?}", b as char)?,
+            JsonErrorKind::InvalidNumber => write!(f, "invalid number")?,
+            JsonErrorKind::InvalidEscape => write!(f, "invalid escape sequence")?,
+            JsonErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8")?,
+            JsonErrorKind::TrailingCharacters => write!(f, "trailing characters")?,
+            JsonErrorKind::DepthLimit => write!(f, "nesting too deep")?,
+            JsonErrorKind::KeyCollision => write!(f, "member name has the same hash as another")?,
+        }
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonWriteError {
+    BadType(ValueType),
+    NonFiniteReal,
+    InvalidUtf8,
+    Cycle,
+    DepthLimit,
+    /// the text would be longer than the maximum length
+    TooLong,
+}
+
+impl fmt::Display for JsonWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonWriteError::BadType(t) => write!(f, "cannot write {} as json", t.as_str()),
+            JsonWriteError::NonFiniteReal => write!(f, "cannot write NaN or infinity as json"),
+            JsonWriteError::InvalidUtf8 => write!(f, "buffer is not valid UTF-8"),
+            JsonWriteError::Cycle => write!(f, "value contains a cycle"),
+            JsonWriteError::DepthLimit => write!(f, "nesting too deep"),
+            JsonWriteError::TooLong => write!(f, "text is too long"),
+        }
+    }
+}
+
+// parse
+
+pub fn parse(b: &[u8], max_depth: usize) -> Result<Value, JsonError> {
+    if let Err(e) = std::str::from_utf8(b) {
+        return Err(JsonError {
+            offset: e.valid_up_to(),
+            kind: JsonErrorKind::InvalidUtf8,
+        });
+    }
+    let mut p = Parser {
+        b,
+        pos: 0,
+        depth: 0,
+        max_depth,
+    };
+    let val = p.parse_value()?;
+    p.skip_whitespace();
+    if p.pos < b.len() {
+        return Err(p.error(JsonErrorKind::TrailingCharacters));
+    }
+    Ok(val)
+}
+
+struct Parser<'a> {
+    b: &'a [u8],
+    pos: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: JsonErrorKind) -> JsonError {
+        JsonError {
+            offset: self.pos,
+            kind,
+        }
+    }
+
+    fn unexpected(&self) -> JsonError {
+        match self.b.get(self.pos) {
+            Some(b) => self.error(JsonErrorKind::UnexpectedByte(*b)),
+            None => self.error(JsonErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.b.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), JsonError> {
+        if self.peek() != Some(c) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn expect_word(&mut self, word: &[u8]) -> Result<(), JsonError> {
+        for c in word {
+            self.expect(*c)?;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self) -> Result<(), JsonError> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(JsonErrorKind::DepthLimit));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => {
+                self.expect_word(b"null")?;
+                Ok(Value::None)
+            }
+            Some(b't') => {
+                self.expect_word(b"true")?;
+                Ok(true.into())
+            }
+            Some(b'f') => {
+                self.expect_word(b"false")?;
+                Ok(false.into())
+            }
+            Some(b'"') => Ok(Buffer::new(self.parse_string()?).into()),
+            Some(b'[') => self.parse_array(),
+            Some(b'{') => self.parse_object(),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, JsonError> {
+        self.enter()?;
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+        } else {
+            loop {
+                items.push(self.parse_value()?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b']') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(List::new(items).into())
+    }
+
+    fn parse_object(&mut self) -> Result<Value, JsonError> {
+        self.enter()?;
+        self.expect(b'{')?;
+        let table = Table::new(Vec::new());
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some(b'"') {
+                    return Err(self.unexpected());
+                }
+                let start = self.pos;
+                let key = self.parse_string()?;
+                let hash = key_hash(&key);
+                // a member with the same name replaces the earlier one
+                let entry = table.get(hash);
+                if let Some((name, _)) = entry.and_then(|t| named_entry(hash, &t)) {
+                    if name.as_slice()[..] != key[..] {
+                        return Err(JsonError {
+                            offset: start,
+                            kind: JsonErrorKind::KeyCollision,
+                        });
+                    }
+                }
+                self.skip_whitespace();
+                self.expect(b':')?;
+                let val = self.parse_value()?;
+                let entry = Tuple::new(vec![
+                    RefCell::new(Buffer::new(key).into()),
+                    RefCell::new(val),
+                ]);
+                table.set(hash, entry.into());
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b'}') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(table.into())
+    }
+
+    fn parse_string(&mut self) -> Result<Vec<u8>, JsonError> {
+        self.expect(b'"')?;
+        let mut acc = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.unexpected()),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(acc);
+                }
+                Some(b'\\') => {
+                    let c = self.parse_escape()?;
+                    let mut tmp = [0; 4];
+                    acc.extend_from_slice(c.encode_utf8(&mut tmp).as_bytes());
+                }
+                Some(b) if b < 0x20 => return Err(self.unexpected()),
+                Some(b) => {
+                    acc.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, JsonError> {
+        let start = self.pos;
+        self.pos += 1;
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let hi = self.parse_hex4()?;
+                let code = if (0xd800..0xdc00).contains(&hi) {
+                    // high surrogate, must be followed by an escaped low surrogate
+                    if self.b.get(self.pos..self.pos + 2) != Some(b"\\u") {
+                        return Err(self.invalid_escape(start));
+                    }
+                    self.pos += 2;
+                    let lo = self.parse_hex4()?;
+                    if !(0xdc00..0xe000).contains(&lo) {
+                        return Err(self.invalid_escape(start));
+                    }
+                    0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)
+                } else {
+                    hi
+                };
+                return std::char::from_u32(code).ok_or_else(|| self.invalid_escape(start));
+            }
+            Some(_) => return Err(self.invalid_escape(start)),
+            None => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn invalid_escape(&self, start: usize) -> JsonError {
+        JsonError {
+            offset: start,
+            kind: JsonErrorKind::InvalidEscape,
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let mut acc = 0;
+        for _ in 0..4 {
+            let digit = match self.peek() {
+                Some(c @ b'0'..=b'9') => c - b'0',
+                Some(c @ b'a'..=b'f') => c - b'a' + 10,
+                Some(c @ b'A'..=b'F') => c - b'A' + 10,
+                _ => return Err(self.unexpected()),
+            };
+            acc = acc * 16 + digit as u32;
+            self.pos += 1;
+        }
+        Ok(acc)
+    }
+
+    fn skip_digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn parse_number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        let mut is_int = true;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => {
+                self.skip_digits();
+            }
+            _ => return Err(self.error(JsonErrorKind::InvalidNumber)),
+        }
+        if self.peek() == Some(b'.') {
+            is_int = false;
+            self.pos += 1;
+            if self.skip_digits() == 0 {
+                return Err(self.error(JsonErrorKind::InvalidNumber));
+            }
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            is_int = false;
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.skip_digits() == 0 {
+                return Err(self.error(JsonErrorKind::InvalidNumber));
+            }
+        }
+        // the input was checked to be valid UTF-8, and numbers are ASCII
+        let text = std::str::from_utf8(&self.b[start..self.pos]).unwrap();
+        if is_int {
+            if let Ok(int) = text.parse::<i64>() {
+                return Ok(int.into());
+            }
+            #[cfg(feature = "bigint")]
+            {
+                if let Ok(int) = text.parse::<BigInt>() {
+                    return Ok(Value::from_bigint(int));
+                }
+            }
+        }
+        match text.parse::<f64>() {
+            Ok(real) => Ok(real.into()),
+            Err(_) => Err(JsonError {
+                offset: start,
+                kind: JsonErrorKind::InvalidNumber,
+            }),
+        }
+    }
+}
+
+// stringify
+
+/// Writes `val` as json, with aggregates nested at most `max_depth` deep, and
+/// at most `max_len` bytes.
+pub fn stringify(val: &Value, max_depth: usize, max_len: usize) -> Result<String, JsonWriteError> {
+    let mut w = Writer {
+        out: String::new(),
+        path: Vec::new(),
+        max_depth,
+        max_len,
+    };
+    w.write_value(val)?;
+    match w.out.len() > max_len {
+        true => Err(JsonWriteError::TooLong),
+        false => Ok(w.out),
+    }
+}
+
+struct Writer {
+    out: String,
+    /// identities of the aggregates currently being written, used to detect
+    /// cycles (values which are merely shared are written once per use)
+    path: Vec<usize>,
+    max_depth: usize,
+    max_len: usize,
+}
+
+impl Writer {
+    fn enter(&mut self, id: usize) -> Result<(), JsonWriteError> {
+        if self.path.contains(&id) {
+            return Err(JsonWriteError::Cycle);
+        }
+        if self.path.len() >= self.max_depth {
+            return Err(JsonWriteError::DepthLimit);
+        }
+        self.path.push(id);
+        Ok(())
+    }
+
+    fn exit(&mut self) {
+        self.path.pop();
+    }
+
+    fn write_value(&mut self, val: &Value) -> Result<(), JsonWriteError> {
+        // checked before each value, so a text that is too long is stopped
+        // at most one scalar past the maximum
+        if self.out.len() > self.max_len {
+            return Err(JsonWriteError::TooLong);
+        }
+        match val {
+            Value::None => self.out.push_str("null"),
+            Value::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Value::Integer(i) => write!(self.out, "{}", i).unwrap(),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(i) => write!(self.out, "{}", i).unwrap(),
+            Value::Real(r) => {
+                if !r.is_finite() {
+                    return Err(JsonWriteError::NonFiniteReal);
+                }
+                write!(self.out, "{:?}", r).unwrap();
+            }
+            Value::Tuple(t) => {
+                self.enter(t.identity())?;
+                let items: Vec<Value> = t.iter().collect();
+                self.write_array(&items)?;
+                self.exit();
+            }
+            Value::TupleWeak(t) => match t.upgrade() {
+                Some(t) => self.write_value(&t.into())?,
+                None => self.out.push_str("null"),
+            },
+            Value::List(t) => {
+                self.enter(t.identity())?;
+                let items = t.as_slice().to_vec();
+                self.write_array(&items)?;
+                self.exit();
+            }
+            Value::Table(t) => {
+                self.enter(t.identity())?;
+                self.write_object(t)?;
+                self.exit();
+            }
+            Value::Buffer(t) => self.write_string(&t.as_slice())?,
+            _ => return Err(JsonWriteError::BadType(val.get_type())),
+        }
+        Ok(())
+    }
+
+    fn write_array(&mut self, items: &[Value]) -> Result<(), JsonWriteError> {
+        self.out.push('[');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.write_value(item)?;
+        }
+        self.out.push(']');
+        Ok(())
+    }
+
+    fn write_object(&mut self, table: &Table) -> Result<(), JsonWriteError> {
+        self.out.push('{');
+        for (i, (key, val)) in table.entries().into_iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            match named_entry(key, &val) {
+                Some((name, val)) => {
+                    self.write_string(&name.as_slice())?;
+                    self.out.push(':');
+                    self.write_value(&val)?;
+                }
+                None => {
+                    write!(self.out, "\"{}\":", key).unwrap();
+                    self.write_value(&val)?;
+                }
+            }
+        }
+        self.out.push('}');
+        Ok(())
+    }
+
+    fn write_string(&mut self, b: &[u8]) -> Result<(), JsonWriteError> {
+        let s = std::str::from_utf8(b).map_err(|_| JsonWriteError::InvalidUtf8)?;
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if (c as u32) < 0x20 => write!(self.out, "\\u{:04x}", c as u32).unwrap(),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+        Ok(())
+    }
+}
+
+/// Splits a table entry written by `parse` back into its name and value.
+fn named_entry(key: u64, val: &Value) -> Option<(Buffer, Value)> {
+    let pair = match val {
+        Value::Tuple(t) if t.len() == 2 => t,
+        _ => return None,
+    };
+    let name = match pair.get(0)? {
+        Value::Buffer(b) => b,
+        _ => return None,
+    };
+    if key_hash(&name.as_slice()) != key {
+        return None;
+    }
+    Some((name, pair.get(1)?))
+}
+
+// natives
+
+/// The natives, by the names that the `json` module exports them as.
+pub const NATIVES: &[(&str, NativeFn)] = &[
+    ("parse", json_parse),
+    ("stringify", json_stringify),
+    ("key_hash", json_key_hash),
+];
+
+/// Native `json_parse(buffer)`. Returns `(value, None)` on success, or
+/// `(None, message)` where message is a Buffer describing the error.
+pub fn json_parse(mut args: Vec<Value>) -> Value {
+    // native args are reversed, so the first argument is the last item
+    let result = match args.pop() {
+        Some(Value::Buffer(b)) => {
+            parse(&b.as_slice(), DEFAULT_MAX_DEPTH).map_err(|e| e.to_string())
+        }
+        Some(val) => Err(format!(
+            "expected Buffer, found {}",
+            val.get_type().as_str()
+        )),
+        None => Err("expected Buffer, found nothing".to_string()),
+    };
+    native_result(result)
+}
+
+/// Native `json_stringify(value)`. Returns `(buffer, None)` on success, or
+/// `(None, message)` where message is a Buffer describing the error.
+pub fn json_stringify(mut args: Vec<Value>) -> Value {
+    let val = args.pop().unwrap_or(Value::None);
+    let result = stringify(&val, DEFAULT_MAX_DEPTH, DEFAULT_MAX_LEN)
+        .map(|s| Buffer::new(s.into_bytes()).into())
+        .map_err(|e| e.to_string());
+    native_result(result)
+}
+
+/// Native `json_key_hash(buffer)`. Returns the table key that `json_parse`
+/// stores the object member named `buffer` under, or None if the argument
+/// isn't a Buffer.
+pub fn json_key_hash(mut args: Vec<Value>) -> Value {
+    match args.pop() {
+        Some(Value::Buffer(b)) => Value::Integer(key_hash(&b.as_slice()) as i64),
+        _ => Value::None,
+    }
+}
+
+fn native_result(result: Result<Value, String>) -> Value {
+    let (val, err) = match result {
+        Ok(val) => (val, Value::None),
+        Err(msg) => (Value::None, Buffer::new(msg.into_bytes()).into()),
+    };
+    Tuple::new(vec![RefCell::new(val), RefCell::new(err)]).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(s: &str) -> Value {
+        Buffer::new(s.as_bytes().to_vec()).into()
+    }
+
+    #[test]
+    fn members_are_keyed_by_key_hash() {
+        let table = match parse(br#"{"a": 1, "b": 2, "a": 3}"#, DEFAULT_MAX_DEPTH) {
+            Ok(Value::Table(t)) => t,
+            _ => panic!("expected a table"),
+        };
+        assert_eq!(table.entries().len(), 2);
+        let key = match json_key_hash(vec![buffer("a")]) {
+            Value::Integer(key) => key as u64,
+            _ => panic!("expected an Integer"),
+        };
+        let (name, val) = named_entry(key, &table.get(key).unwrap()).unwrap();
+        assert_eq!(&name.as_slice()[..], b"a");
+        assert!(matches!(val, Value::Integer(3)));
+        assert!(matches!(
+            json_key_hash(vec![Value::Integer(1)]),
+            Value::None
+        ));
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = r#"{"list":[1,2.5,true,null],"name":"x\ny"}"#;
+        let val = parse(text.as_bytes(), DEFAULT_MAX_DEPTH).unwrap();
+        let out = stringify(&val, DEFAULT_MAX_DEPTH, DEFAULT_MAX_LEN).unwrap();
+        let again = parse(out.as_bytes(), DEFAULT_MAX_DEPTH).unwrap();
+        assert!(val.structural_eq(&again));
+    }
+
+    #[test]
+    fn shared_values_are_written_within_the_max_len() {
+        // written in full, this would be 2^64 arrays
+        let mut val: Value = List::empty().into();
+        for _ in 0..64 {
+            val = List::new(vec![val.clone(), val]).into();
+        }
+        let e = stringify(&val, DEFAULT_MAX_DEPTH, 1000).unwrap_err();
+        assert!(matches!(e, JsonWriteError::TooLong));
+        // and a buffer that is longer than the max len on its own
+        let e = stringify(&buffer("abcdef"), DEFAULT_MAX_DEPTH, 4).unwrap_err();
+        assert!(matches!(e, JsonWriteError::TooLong));
+        assert_eq!(
+            stringify(&buffer("ab"), DEFAULT_MAX_DEPTH, 4).unwrap(),
+            "\"ab\""
+        );
+    }
+
+    #[test]
+    fn errors_have_the_offset() {
+        let e = parse(b"[1, x]", DEFAULT_MAX_DEPTH).unwrap_err();
+        assert_eq!(e.offset, 4);
+        assert!(matches!(e.kind, JsonErrorKind::UnexpectedByte(b'x')));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod bytecode;
 pub mod datamodel;
+pub mod json;
+pub mod loader;
 
 mod callframe;
 mod callstack;
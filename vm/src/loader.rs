@@ -0,0 +1,799 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use crate::bytecode::ops::LiteralValue;
+use crate::bytecode::{
+    self, Externs, ItemError, LinkError, LoadError, Module, ModuleItem, Program,
+};
+use crate::datamodel::{Buffer, Function, NativeFn, Tuple, Value};
+use crate::json;
+
+/*
+A Loader loads programs while scripts are running, and keeps the modules it
+has loaded, so that later programs can import them. Modules are just tuples,
+so a loaded module is the same as one that was created up front.
+
+A program is loaded from the bytes of a `.pnb` file, or by the name of a
+module, which the loader asks its resolver for. Its first module is the one
+that is returned, but every module with a name is kept, and imports of
+modules that the program doesn't have are linked against the kept modules,
+loading them through the resolver if they haven't been loaded yet. Imports are
+how a program refers to loaded modules: ModuleRefs are positions in the
+program, so they only refer to modules in the same program, and one that is
+out of range is a LinkError. Every program is verified before it is linked,
+and so is the new version of a module in `reload`.
+
+Functions in a kept module can be swapped with `replace_function`, which
+changes the item in the module's tuple. Code that looks up the function in
+the module calls the new one from then on, but frames that are already
+running the old one keep running it, and modules that imported the old one
+keep it.
+
+`reload` does that for every function of a module, with a new version of it,
+such as one recompiled from an edited script. The functions are bound to the
+tuple of the loaded module, so they share its data items, which keep their
+values unless `reload_with` migrates them. Imports are linked again, and
+ModuleRefs are left alone, since they refer to modules by their position in
+the program that the module was loaded with. The tuple can't grow, so the new
+version can't have more items than the loaded one, and since frames running
+the old functions see the new items, the items should keep their indices.
+
+Scripts load modules with the `load_buffer` and `load_name` natives, which
+take the loader as their first arg, as the Value from `loader_value`.
+
+Natives are given to scripts as modules too, with `add_natives`, which makes
+a module that exports each of them. A new loader has the natives of
+`crate::json` as the module `json`, so scripts can import `json.parse`.
+*/
+
+#[derive(Debug)]
+pub enum LoaderError {
+    Load(LoadError),
+    Link(LinkError),
+    /// a program given to `load_program`, or the new version of a module,
+    /// failed `Program::verify`
+    Verify(ItemError),
+    /// the resolver doesn't have a module with the name
+    NotFound(String),
+    /// a module with the name has already been loaded or is being loaded, or
+    /// the program has more than one
+    AlreadyLoaded(String),
+    /// the module imports itself while it is being loaded
+    Cycle(String),
+    /// the program has no modules
+    Empty,
+    /// the resolver was asked for `name`, but the first module of the program
+    /// it returned is named `found`
+    WrongName {
+        name: String,
+        found: String,
+    },
+    /// no module with the name has been loaded, so it can't be reloaded
+    NotLoaded(String),
+    /// the new version of a module has more items than the loaded one
+    TooManyItems {
+        name: String,
+        loaded: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoaderError::Load(e) => write!(f, "{}", e),
+            LoaderError::Link(e) => write!(f, "{}", e),
+            LoaderError::Verify(e) => write!(f, "invalid bytecode: {}", e),
+            LoaderError::NotFound(name) => write!(f, "module `{}` not found", name),
+            LoaderError::AlreadyLoaded(name) => {
+                write!(f, "module `{}` has already been loaded", name)
+            }
+            LoaderError::Cycle(name) => write!(f, "module `{}` imports itself", name),
+            LoaderError::Empty => write!(f, "program has no modules"),
+            LoaderError::WrongName { name, found } => write!(
+                f,
+                "module `{}` was asked for, but the program has `{}` instead",
+                name, found
+            ),
+            LoaderError::NotLoaded(name) => write!(f, "module `{}` has not been loaded", name),
+            LoaderError::TooManyItems {
+                name,
+                loaded,
+                found,
+            } => write!(
+                f,
+                "module `{}` was loaded with {} items, and can't be reloaded with {}",
+                name, loaded, found
+            ),
+        }
+    }
+}
+
+impl From<LoadError> for LoaderError {
+    fn from(e: LoadError) -> Self {
+        LoaderError::Load(e)
+    }
+}
+
+impl From<LinkError> for LoaderError {
+    fn from(e: LinkError) -> Self {
+        LoaderError::Link(e)
+    }
+}
+
+type Resolver = Box<dyn FnMut(&str) -> Option<Vec<u8>>>;
+
+struct LoadedModule {
+    tuple: Tuple,
+    exports: Vec<(String, u32)>,
+}
+
+pub struct Loader {
+    modules: HashMap<String, LoadedModule>,
+    resolver: Option<Resolver>,
+    /// the names of the modules being loaded, whose programs are being linked
+    loading: Vec<String>,
+    /// why a module that a program imports couldn't be loaded, which is
+    /// reported instead of the unresolved import
+    failed: Option<LoaderError>,
+}
+
+impl Loader {
+    pub fn new() -> Loader {
+        let mut loader = Loader {
+            modules: HashMap::new(),
+            resolver: None,
+            loading: Vec::new(),
+            failed: None,
+        };
+        loader.add_natives("json", json::NATIVES).unwrap();
+        loader
+    }
+
+    /// Adds a module named `name`, which exports each of `natives` by its
+    /// name.
+    pub fn add_natives(
+        &mut self,
+        name: &str,
+        natives: &[(&str, NativeFn)],
+    ) -> Result<(), LoaderError> {
+        if self.modules.contains_key(name) {
+            return Err(LoaderError::AlreadyLoaded(name.to_string()));
+        }
+        let items = natives.iter().map(|&(_, f)| RefCell::new(f.into()));
+        let tuple = Tuple::new(items.collect());
+        let exports = natives.iter().enumerate();
+        let exports = exports.map(|(i, &(name, _))| (name.to_string(), i as u32));
+        let module = LoadedModule {
+            tuple,
+            exports: exports.collect(),
+        };
+        self.modules.insert(name.to_string(), module);
+        Ok(())
+    }
+
+    /// Sets the function that `load_name` gets the `.pnb` bytes of a module
+    /// from, or None if it doesn't have the module.
+    pub fn set_resolver(&mut self, resolver: impl FnMut(&str) -> Option<Vec<u8>> + 'static) {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    /// The tuple of the loaded module named `name`.
+    pub fn get(&self, name: &str) -> Option<Tuple> {
+        Some(self.modules.get(name)?.tuple.clone())
+    }
+
+    /// Loads a program from the bytes of a `.pnb` file, and returns the
+    /// tuple of its first module.
+    pub fn load_bytes(&mut self, b: &[u8]) -> Result<Tuple, LoaderError> {
+        // from_bytes has verified it already
+        let program = Program::from_bytes(b)?;
+        self.load_verified(program)
+    }
+
+    /// Loads the module named `name`, from the resolver if it hasn't been
+    /// loaded yet, and returns its tuple. If the first module of the program
+    /// that the resolver returns doesn't have a name, it is kept as `name`,
+    /// and if it has another name, the program isn't loaded.
+    pub fn load_name(&mut self, name: &str) -> Result<Tuple, LoaderError> {
+        if let Some(tuple) = self.get(name) {
+            return Ok(tuple);
+        }
+        if self.loading.iter().any(|n| n == name) {
+            return Err(LoaderError::Cycle(name.to_string()));
+        }
+        let b = match &mut self.resolver {
+            Some(resolver) => resolver(name),
+            None => None,
+        };
+        let b = b.ok_or_else(|| LoaderError::NotFound(name.to_string()))?;
+        let mut program = Program::from_bytes(&b)?;
+        if let Some(module) = program.modules.first_mut() {
+            let found = module.name.get_or_insert_with(|| name.to_string());
+            if found != name {
+                let (name, found) = (name.to_string(), found.clone());
+                return Err(LoaderError::WrongName { name, found });
+            }
+        }
+        self.load_verified(program)
+    }
+
+    /// Verifies a program, links it against the loaded modules, keeps its
+    /// modules that have names, and returns the tuple of its first module.
+    pub fn load_program(&mut self, program: Program) -> Result<Tuple, LoaderError> {
+        program.verify().map_err(LoaderError::Verify)?;
+        self.load_verified(program)
+    }
+
+    fn load_verified(&mut self, program: Program) -> Result<Tuple, LoaderError> {
+        if program.modules.is_empty() {
+            return Err(LoaderError::Empty);
+        }
+        let mut names: Vec<(Option<String>, _)> = Vec::new();
+        for module in program.modules.iter() {
+            if let Some(name) = &module.name {
+                let loaded = self.modules.contains_key(name.as_str())
+                    || self.loading.contains(name)
+                    || names.iter().any(|(n, _)| n.as_ref() == Some(name));
+                if loaded {
+                    return Err(LoaderError::AlreadyLoaded(name.clone()));
+                }
+            }
+            names.push((module.name.clone(), module.exports.clone()));
+        }
+        // the modules aren't kept until the program is linked, so an import
+        // of one of them from a module that the resolver loads meanwhile is
+        // a cycle, instead of loading a second copy of it
+        let len = self.loading.len();
+        self.loading
+            .extend(names.iter().filter_map(|(name, _)| name.clone()));
+        let tuple = self.link(program);
+        self.loading.truncate(len);
+        let tuple = tuple?;
+        for (i, (name, exports)) in names.into_iter().enumerate() {
+            if let Some(name) = name {
+                // every item of a program tuple is a module tuple
+                let tuple = tuple.get(i).unwrap().try_into().unwrap();
+                self.modules.insert(name, LoadedModule { tuple, exports });
+            }
+        }
+        Ok(tuple.get(0).unwrap().try_into().unwrap())
+    }
+
+    /// Replaces the functions of the loaded module named `name` with the ones
+    /// in `module`, and keeps the values of its data items. The module is
+    /// verified first, and nothing is replaced if it fails.
+    pub fn reload(&mut self, name: &str, module: Module) -> Result<(), LoaderError> {
+        self.reload_with(name, module, |_, old, _| old)
+    }
+
+    /// Like `reload`, but `migrate` is called with the index, loaded value and
+    /// new value of each data item, and returns the value it gets.
+    pub fn reload_with(
+        &mut self,
+        name: &str,
+        mut module: Module,
+        mut migrate: impl FnMut(usize, Value, Value) -> Value,
+    ) -> Result<(), LoaderError> {
+        let loaded = self
+            .get(name)
+            .ok_or_else(|| LoaderError::NotLoaded(name.to_string()))?;
+        if module.items.len() > loaded.len() {
+            return Err(LoaderError::TooManyItems {
+                name: name.to_string(),
+                loaded: loaded.len(),
+                found: module.items.len(),
+            });
+        }
+        let kinds: Vec<ItemKind> = module.items.iter().map(ItemKind::of).collect();
+        // ModuleRefs are left alone, and would be out of range in a program
+        // of just this module
+        for item in module.items.iter_mut() {
+            if let ModuleItem::ModuleRef(_) = item {
+                *item = ModuleItem::LiteralValue(LiteralValue::None);
+            }
+        }
+        let exports = mem::take(&mut module.exports);
+        // without a name, imports of the module link to the loaded one
+        module.name = None;
+        let program = Program {
+            modules: vec![module],
+        };
+        program.verify().map_err(LoaderError::Verify)?;
+        let tuple: Tuple = self.link(program)?.get(0).unwrap().try_into().unwrap();
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let val = tuple.get(i).unwrap();
+            let val = match (kind, val) {
+                (ItemKind::Function, Value::Function(f)) => Function {
+                    module: loaded.clone(),
+                    ops: f.ops,
+                    name: f.name,
+                }
+                .into(),
+                (ItemKind::Data, val) => migrate(i, loaded.get(i).unwrap(), val),
+                (ItemKind::Import, val) => val,
+                _ => continue,
+            };
+            loaded.set(i, val);
+        }
+        self.modules.get_mut(name).unwrap().exports = exports;
+        Ok(())
+    }
+
+    /// Links a program against the loaded modules, and creates its tuple.
+    fn link(&mut self, program: Program) -> Result<Tuple, LoaderError> {
+        program
+            .into_tuple_with(self)
+            .map_err(|e| self.failed.take().unwrap_or(LoaderError::Link(e)))
+    }
+
+    /// Replaces item `item` of the loaded module named `module` with a
+    /// function, and returns the item it replaced, or None if there is no
+    /// such item.
+    pub fn replace_function(
+        &self,
+        module: &str,
+        item: usize,
+        function: bytecode::Function,
+    ) -> Option<Value> {
+        let tuple = &self.modules.get(module)?.tuple;
+        tuple.set(item, function.into_value(tuple.clone()).into())
+    }
+}
+
+/// How `Loader::reload` treats an item.
+enum ItemKind {
+    Function,
+    Data,
+    Import,
+    ModuleRef,
+}
+
+impl ItemKind {
+    fn of(item: &ModuleItem) -> ItemKind {
+        match item {
+            ModuleItem::Function(_) => ItemKind::Function,
+            ModuleItem::LiteralValue(_) | ModuleItem::Buffer(_) => ItemKind::Data,
+            ModuleItem::Import { .. } => ItemKind::Import,
+            ModuleItem::ModuleRef(_) => ItemKind::ModuleRef,
+        }
+    }
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Loader::new()
+    }
+}
+
+impl Externs for Loader {
+    fn module(&mut self, name: &str) -> Option<(Tuple, &[(String, u32)])> {
+        if let Err(e) = self.load_name(name) {
+            self.failed.get_or_insert(e);
+            return None;
+        }
+        let module = self.modules.get(name)?;
+        Some((module.tuple.clone(), &module.exports))
+    }
+}
+
+/// Wraps a loader in a Value, for scripts to pass to `load_buffer` and
+/// `load_name`.
+pub fn loader_value(loader: Rc<RefCell<Loader>>) -> Value {
+    let loader: Rc<dyn Any> = loader;
+    Value::Unknown(loader)
+}
+
+/// A native that takes a loader and a Buffer with the bytes of a `.pnb` file,
+/// and returns the tuple of the program's first module, or None if it can't
+/// be loaded.
+pub fn load_buffer(args: Vec<Value>) -> Value {
+    native_args(args)
+        .and_then(|(loader, b)| loader.borrow_mut().load_bytes(&b.as_slice()).ok())
+        .into()
+}
+
+/// A native that takes a loader and a Buffer with the name of a module, and
+/// returns the tuple of the module, or None if it can't be loaded.
+pub fn load_name(args: Vec<Value>) -> Value {
+    native_args(args)
+        .and_then(|(loader, b)| {
+            let name = String::from_utf8(b.as_slice().to_vec()).ok()?;
+            let tuple = loader.borrow_mut().load_name(&name).ok()?;
+            Some(tuple)
+        })
+        .into()
+}
+
+/// The loader and buffer that the natives are called with.
+fn native_args(mut args: Vec<Value>) -> Option<(Rc<RefCell<Loader>>, Buffer)> {
+    // natives get their args in reverse
+    let loader: Rc<dyn Any> = args.pop()?.try_into().ok()?;
+    let b: Buffer = args.pop()?.try_into().ok()?;
+    Some((loader.downcast().ok()?, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::asm::assemble;
+    use crate::datamodel::Identity;
+    use crate::VirtualMachine;
+    use std::cell::Cell;
+
+    fn import(module: &str, name: Option<&str>) -> ModuleItem {
+        ModuleItem::Import {
+            module: module.to_string(),
+            name: name.map(str::to_string),
+        }
+    }
+
+    fn module(name: Option<&str>, items: Vec<ModuleItem>) -> Module {
+        Module {
+            name: name.map(str::to_string),
+            items,
+            exports: Vec::new(),
+        }
+    }
+
+    fn bytes(src: &str) -> Vec<u8> {
+        assemble(src).unwrap().to_bytes()
+    }
+
+    /// A loader whose resolver has the listings in `sources`, and counts the
+    /// modules it is asked for.
+    fn resolving(sources: &[(&'static str, &'static str)]) -> (Loader, Rc<Cell<usize>>) {
+        let sources: HashMap<_, _> = sources.iter().cloned().collect();
+        let asked = Rc::new(Cell::new(0));
+        let count = asked.clone();
+        let mut loader = Loader::new();
+        loader.set_resolver(move |name| {
+            count.set(count.get() + 1);
+            sources.get(name).map(|src| bytes(src))
+        });
+        (loader, asked)
+    }
+
+    #[test]
+    fn imports_are_loaded_through_the_resolver() {
+        let (mut loader, asked) = resolving(&[
+            ("b", "module\n  import c\n  literal 5\n  export five 1\n"),
+            ("c", "module c\n"),
+        ]);
+        let tuple = loader
+            .load_bytes(&bytes("module a\n  import b five\n"))
+            .unwrap();
+        assert!(matches!(tuple.get(0), Some(Value::Integer(5))));
+        assert_eq!(asked.get(), 2);
+        assert!(loader.get("a").is_some());
+        assert!(loader.get("b").is_some());
+        assert!(loader.get("c").is_some());
+        let b = loader.load_name("b").unwrap();
+        assert!(matches!(b.get(1), Some(Value::Integer(5))));
+        assert_eq!(asked.get(), 2);
+    }
+
+    #[test]
+    fn missing_modules_are_not_found() {
+        let (mut loader, asked) = resolving(&[("b", "module\n  import c\n")]);
+        let t = loader.load_name("nope");
+        assert!(matches!(t, Err(LoaderError::NotFound(name)) if name == "nope"));
+        // the error is about the module that is missing, not the import of it
+        let t = loader.load_name("b");
+        assert!(matches!(t, Err(LoaderError::NotFound(name)) if name == "c"));
+        assert_eq!(asked.get(), 3);
+        assert!(loader.get("b").is_none());
+
+        let mut loader = Loader::new();
+        let t = loader.load_name("b");
+        assert!(matches!(t, Err(LoaderError::NotFound(name)) if name == "b"));
+    }
+
+    #[test]
+    fn import_cycles_are_rejected() {
+        let (mut loader, _) =
+            resolving(&[("a", "module\n  import b\n"), ("b", "module\n  import a\n")]);
+        let t = loader.load_name("a");
+        assert!(matches!(t, Err(LoaderError::Cycle(name)) if name == "a"));
+        assert!(loader.get("a").is_none());
+        assert!(loader.get("b").is_none());
+        assert!(loader.loading.is_empty());
+    }
+
+    #[test]
+    fn modules_being_linked_are_not_loaded_again() {
+        // x imports a while a is being linked, whether or not the resolver
+        // has another a
+        let x = "module x\n  import a\n  literal 1\n  export y 1\n";
+        let a = "module a\n  import x y\n";
+        for sources in [vec![("x", x)], vec![("x", x), ("a", "module a\n")]] {
+            let (mut loader, _) = resolving(&sources);
+            let t = loader.load_bytes(&bytes(a));
+            assert!(matches!(t, Err(LoaderError::Cycle(name)) if name == "a"));
+            assert!(loader.get("a").is_none());
+            assert!(loader.get("x").is_none());
+            assert!(loader.loading.is_empty());
+        }
+
+        // and a program from the resolver can't have another one
+        let (mut loader, _) = resolving(&[("x", "module x\n  import a\nmodule a\n")]);
+        let t = loader.load_bytes(&bytes("module a\n  import x\n"));
+        assert!(matches!(t, Err(LoaderError::AlreadyLoaded(name)) if name == "a"));
+        assert!(loader.get("a").is_none());
+    }
+
+    #[test]
+    fn resolved_modules_must_have_the_name() {
+        let (mut loader, asked) = resolving(&[("b", "module c\n  literal 1\n")]);
+        let t = loader.load_name("b");
+        assert!(matches!(
+            t,
+            Err(LoaderError::WrongName { name, found }) if name == "b" && found == "c"
+        ));
+        assert!(loader.get("b").is_none());
+        assert!(loader.get("c").is_none());
+        // an import of the module reports why it couldn't be loaded
+        let t = loader.load_bytes(&bytes("module\n  import b\n"));
+        assert!(matches!(t, Err(LoaderError::WrongName { .. })));
+        assert_eq!(asked.get(), 2);
+    }
+
+    #[test]
+    fn bad_programs_are_rejected() {
+        let mut loader = Loader::new();
+        assert!(matches!(
+            loader.load_bytes(b"not a program"),
+            Err(LoaderError::Load(_))
+        ));
+        let t = loader.load_program(Program {
+            modules: Vec::new(),
+        });
+        assert!(matches!(t, Err(LoaderError::Empty)));
+        let t = loader.load_bytes(&bytes("module a\n  import a x\n"));
+        assert!(matches!(
+            t,
+            Err(LoaderError::Link(LinkError::Unresolved { .. }))
+        ));
+        assert!(loader.get("a").is_none());
+    }
+
+    #[test]
+    fn scripts_can_load_modules() {
+        let (loader, _) = resolving(&[("b", "module\n  literal 5\n")]);
+        let loader = loader_value(Rc::new(RefCell::new(loader)));
+        let name = |b: &[u8]| Buffer::new(b.to_vec()).into();
+        let native = |f: fn(Vec<Value>) -> Value| Value::NativeFn(f);
+
+        let args = vec![loader.clone(), name(b"b")];
+        let b: Tuple = VirtualMachine::call(native(load_name), args)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert!(matches!(b.get(0), Some(Value::Integer(5))));
+        let args = vec![loader.clone(), name(b"nope")];
+        let t = VirtualMachine::call(native(load_name), args).unwrap();
+        assert!(matches!(t, Value::None));
+
+        let args = vec![loader.clone(), name(&bytes("module\n  import b\n"))];
+        let t: Tuple = VirtualMachine::call(native(load_buffer), args)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert!(
+            matches!(t.get(0), Some(Value::Tuple(b)) if matches!(b.get(0), Some(Value::Integer(5))))
+        );
+        let args = vec![Value::Integer(1), name(b"b")];
+        let t = VirtualMachine::call(native(load_name), args).unwrap();
+        assert!(matches!(t, Value::None));
+    }
+
+    const V1: &str = "\
+module m
+    literal 1
+    function
+        LiteralCreate 1
+        Return
+    end
+    function
+        StackLoad 0
+        LiteralCreate 0
+        SeqGet
+        Return
+    end
+    export f 1
+";
+
+    const V2: &str = "\
+module
+    literal 2
+    function
+        LiteralCreate 2
+        Return
+    end
+    function
+        StackLoad 0
+        LiteralCreate 0
+        SeqGet
+        Return
+    end
+    export g 1
+";
+
+    fn first(src: &str) -> Module {
+        assemble(src).unwrap().modules.remove(0)
+    }
+
+    fn call(f: Option<Value>) -> Value {
+        VirtualMachine::call(f.unwrap(), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn reload_swaps_functions_and_keeps_data() {
+        let mut loader = Loader::new();
+        let m = loader.load_bytes(&bytes(V1)).unwrap();
+        let old = m.get(1);
+        m.set(0, Value::Integer(10));
+        loader.reload("m", first(V2)).unwrap();
+        assert!(matches!(loader.get("m"), Some(t) if t.identity() == m.identity()));
+        assert!(matches!(m.get(0), Some(Value::Integer(10))));
+        assert!(matches!(call(m.get(1)), Value::Integer(2)));
+        // the new functions are bound to the loaded tuple
+        assert!(matches!(call(m.get(2)), Value::Integer(10)));
+        // and the old ones keep running the old ops
+        assert!(matches!(call(old), Value::Integer(1)));
+        assert_eq!(loader.modules["m"].exports, vec![("g".to_string(), 1)]);
+    }
+
+    #[test]
+    fn reload_with_migrates_data() {
+        let mut loader = Loader::new();
+        let m = loader.load_bytes(&bytes(V1)).unwrap();
+        let mut seen = Vec::new();
+        loader
+            .reload_with("m", first(V2), |i, old, new| {
+                seen.push(i);
+                assert!(matches!(old, Value::Integer(1)));
+                new
+            })
+            .unwrap();
+        assert_eq!(seen, vec![0]);
+        assert!(matches!(m.get(0), Some(Value::Integer(2))));
+    }
+
+    #[test]
+    fn reload_links_imports_again() {
+        let mut loader = Loader::new();
+        loader
+            .load_bytes(&bytes("module a\n  literal 1\n  export x 0\n"))
+            .unwrap();
+        loader
+            .load_bytes(&bytes("module b\n  literal 2\n  export x 0\n"))
+            .unwrap();
+        let m = loader
+            .load_bytes(&bytes("module m\n  import a x\n"))
+            .unwrap();
+        assert!(matches!(m.get(0), Some(Value::Integer(1))));
+        loader.reload("m", first("module\n  import b x\n")).unwrap();
+        assert!(matches!(m.get(0), Some(Value::Integer(2))));
+        // a new version that imports itself gets the loaded module
+        loader.reload("m", first("module m\n  import m\n")).unwrap();
+        assert!(matches!(m.get(0), Some(Value::Tuple(t)) if t.identity() == m.identity()));
+    }
+
+    #[test]
+    fn reload_leaves_module_refs_alone() {
+        let mut loader = Loader::new();
+        let m = loader
+            .load_bytes(&bytes("module m\n  moduleref 1\nmodule\n  literal 1\n"))
+            .unwrap();
+        let other = m.get(0);
+        assert!(matches!(other, Some(Value::Tuple(_))));
+        // the ModuleRef would be out of range in a program of just the module
+        loader
+            .reload("m", first("module\n  moduleref 1\n"))
+            .unwrap();
+        assert!(matches!(
+            (m.get(0), other),
+            (Some(Value::Tuple(t)), Some(Value::Tuple(u))) if t.identity() == u.identity()
+        ));
+    }
+
+    #[test]
+    fn reload_needs_a_loaded_module_with_room() {
+        let mut loader = Loader::new();
+        let t = loader.reload("m", first(V1));
+        assert!(matches!(t, Err(LoaderError::NotLoaded(name)) if name == "m"));
+        let m = loader
+            .load_bytes(&bytes("module m\n  literal 1\n"))
+            .unwrap();
+        let t = loader.reload("m", first(V2));
+        assert!(matches!(
+            t,
+            Err(LoaderError::TooManyItems {
+                loaded: 1,
+                found: 3,
+                ..
+            })
+        ));
+        assert!(matches!(m.get(0), Some(Value::Integer(1))));
+    }
+
+    #[test]
+    fn functions_can_be_replaced() {
+        let mut loader = Loader::new();
+        let m = loader.load_bytes(&bytes(V1)).unwrap();
+        let f = match first(V2).items.remove(1) {
+            ModuleItem::Function(f) => f,
+            _ => panic!("not a function"),
+        };
+        let old = loader.replace_function("m", 1, f);
+        assert!(matches!(call(old), Value::Integer(1)));
+        assert!(matches!(call(m.get(1)), Value::Integer(2)));
+        let f = || match first(V1).items.remove(2) {
+            ModuleItem::Function(f) => f,
+            _ => panic!("not a function"),
+        };
+        assert!(loader.replace_function("m", 3, f()).is_none());
+        assert!(loader.replace_function("nope", 0, f()).is_none());
+    }
+
+    #[test]
+    fn programs_and_new_versions_are_verified() {
+        // the stack depth at Return depends on the path
+        let bad = "module m\n  function\n    StackLoad 0\n    JumpZero a\n    StackLoad 0\n  a:\n    Return\n  end\n";
+        let mut loader = Loader::new();
+        let t = loader.load_program(assemble(bad).unwrap());
+        assert!(matches!(
+            t,
+            Err(LoaderError::Verify(ItemError {
+                module: 0,
+                item: 0,
+                ..
+            }))
+        ));
+        assert!(loader.get("m").is_none());
+
+        let m = loader.load_bytes(&bytes(V1)).unwrap();
+        let t = loader.reload("m", first(bad));
+        assert!(matches!(t, Err(LoaderError::Verify(_))));
+        assert!(matches!(call(m.get(1)), Value::Integer(1)));
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut loader = Loader::new();
+        let modules = vec![module(Some("a"), Vec::new()), module(Some("a"), Vec::new())];
+        let t = loader.load_program(Program { modules });
+        assert!(matches!(t, Err(LoaderError::AlreadyLoaded(name)) if name == "a"));
+        assert!(loader.get("a").is_none());
+        let modules = vec![module(Some("a"), Vec::new())];
+        loader.load_program(Program { modules }).unwrap();
+        let modules = vec![module(None, Vec::new()), module(Some("a"), Vec::new())];
+        let t = loader.load_program(Program { modules });
+        assert!(matches!(t, Err(LoaderError::AlreadyLoaded(name)) if name == "a"));
+    }
+
+    #[test]
+    fn json_natives_can_be_imported() {
+        let mut loader = Loader::new();
+        let program = Program {
+            modules: vec![module(None, vec![import("json", Some("parse"))])],
+        };
+        let tuple = loader.load_program(program).unwrap();
+        let parse = tuple.get(0).unwrap();
+        assert!(matches!(parse, Value::NativeFn(_)));
+        let args = vec![Buffer::new(b"[1]".to_vec()).into()];
+        let result: Tuple = VirtualMachine::call(parse, args)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert!(matches!(result.get(0), Some(Value::List(_))));
+        let natives = loader.add_natives("json", json::NATIVES);
+        assert!(matches!(natives, Err(LoaderError::AlreadyLoaded(_))));
+    }
+}
@@ -16,6 +16,24 @@ impl VirtualMachine {
         }
     }
 
+    /// Calls `func` on a new VM and runs it until it returns, so that ops can
+    /// call back into scripts. `args` are in the order they were written in.
+    pub fn call(func: Value, mut args: Vec<Value>) -> Result<Value, OpError> {
+        // see crate::bytecode::ops::Call for why args are reversed
+        args.reverse();
+        match func {
+            Value::Function(func) => {
+                let mut vm = VirtualMachine { frame: None };
+                match vm.process(OpAction::Call(func, args))? {
+                    VmState::Running => vm.run_until_exited(),
+                    VmState::Exited(val) => Ok(val),
+                }
+            }
+            Value::NativeFn(func) => Ok(func(args)),
+            _ => Err(OpError::BadType(func.get_type())),
+        }
+    }
+
     pub fn run_until_exited(&mut self) -> Result<Value, OpError> {
         loop {
             let action = self.step()?;